@@ -0,0 +1,745 @@
+use std::fmt;
+
+use half::f16;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use wire_structs::sampler::{
+    approximation_function::ApproximationFunction, g_factor_table::GFactorTable,
+    time_delay_table::TimeDelayTable, view_angle_parameter_cache::ViewAngleParameterCache,
+    view_bound::ViewBound, wormhole_direction_table::WormholeDirectionTable,
+};
+
+// Binary container for the artifacts the renderers load, so they don't have to parse megabytes of
+// JSON floats. Reading only needs the bytes, so it works the same in wasm.
+//
+// Layout, little-endian:
+//   magic        4 bytes, `MAGIC`
+//   version      u32, `FORMAT_VERSION`
+//   header size  u32
+//   header       CBOR `Header`: the artifact's kind, its generation parameters and its sections
+//   payloads     each section's bytes, in header order
+//   checksum     u32, CRC-32 of everything before it
+pub const MAGIC: [u8; 4] = *b"BHAC";
+pub const FORMAT_VERSION: u32 = 1;
+
+const PREAMBLE_SIZE: usize = 12;
+const CHECKSUM_SIZE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Encoding {
+    F32,
+    // Half the size, for values that don't need more than 3 significant digits.
+    F16,
+    // Anything serde can encode, for the parts of an artifact that aren't float tables.
+    Cbor,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArtifactError {
+    NotAnArtifact,
+    UnsupportedVersion(u32),
+    Truncated { expected: usize, found: usize },
+    ChecksumMismatch { expected: u32, found: u32 },
+    BadHeader(String),
+    WrongKind { expected: String, found: String },
+    MissingParameter(String),
+    MissingSection(String),
+    BadSection { name: String, reason: String },
+}
+
+impl fmt::Display for ArtifactError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArtifactError::NotAnArtifact => write!(f, "not an artifact container"),
+            ArtifactError::UnsupportedVersion(version) => write!(
+                f,
+                "format version {} isn't supported, expected {}",
+                version, FORMAT_VERSION
+            ),
+            ArtifactError::Truncated { expected, found } => {
+                write!(f, "truncated: expected {} bytes, found {}", expected, found)
+            }
+            ArtifactError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "checksum mismatch: expected {:08x}, found {:08x}",
+                expected, found
+            ),
+            ArtifactError::BadHeader(reason) => write!(f, "bad header: {}", reason),
+            ArtifactError::WrongKind { expected, found } => {
+                write!(f, "expected a {} artifact, found {}", expected, found)
+            }
+            ArtifactError::MissingParameter(name) => write!(f, "missing parameter {}", name),
+            ArtifactError::MissingSection(name) => write!(f, "missing section {}", name),
+            ArtifactError::BadSection { name, reason } => {
+                write!(f, "bad section {}: {}", name, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArtifactError {}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+struct SectionHeader {
+    name: String,
+    encoding: Encoding,
+    dimensions: Vec<u32>,
+    byte_len: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+struct Header {
+    kind: String,
+    parameters: Vec<(String, f64)>,
+    sections: Vec<SectionHeader>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub encoding: Encoding,
+    // Sizes of the table's axes, fastest varying first. Empty for CBOR sections.
+    pub dimensions: Vec<u32>,
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtifactContainer {
+    pub kind: String,
+    pub parameters: Vec<(String, f64)>,
+    pub sections: Vec<Section>,
+}
+
+impl ArtifactContainer {
+    pub fn new(kind: &str) -> Self {
+        ArtifactContainer {
+            kind: kind.to_string(),
+            parameters: Vec::new(),
+            sections: Vec::new(),
+        }
+    }
+
+    pub fn with_parameter(mut self, name: &str, value: f64) -> Self {
+        self.parameters.push((name.to_string(), value));
+        self
+    }
+
+    pub fn with_floats(
+        mut self,
+        name: &str,
+        encoding: Encoding,
+        dimensions: &[u32],
+        values: &[f32],
+    ) -> Self {
+        let bytes = match encoding {
+            Encoding::F32 => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            Encoding::F16 => values
+                .iter()
+                .flat_map(|v| f16::from_f32(*v).to_le_bytes())
+                .collect(),
+            Encoding::Cbor => panic!("{} is a float table, not CBOR", name),
+        };
+        self.sections.push(Section {
+            name: name.to_string(),
+            encoding,
+            dimensions: dimensions.to_vec(),
+            bytes,
+        });
+        self
+    }
+
+    pub fn with_value<T: Serialize>(mut self, name: &str, value: &T) -> Self {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(value, &mut bytes).unwrap();
+        self.sections.push(Section {
+            name: name.to_string(),
+            encoding: Encoding::Cbor,
+            dimensions: Vec::new(),
+            bytes,
+        });
+        self
+    }
+
+    pub fn parameter(&self, name: &str) -> Result<f64, ArtifactError> {
+        self.parameters
+            .iter()
+            .find(|(parameter, _)| parameter == name)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| ArtifactError::MissingParameter(name.to_string()))
+    }
+
+    pub fn section(&self, name: &str) -> Result<&Section, ArtifactError> {
+        self.sections
+            .iter()
+            .find(|section| section.name == name)
+            .ok_or_else(|| ArtifactError::MissingSection(name.to_string()))
+    }
+
+    // The section's values, whichever precision they were stored in.
+    pub fn floats(&self, name: &str) -> Result<Vec<f32>, ArtifactError> {
+        let section = self.section(name)?;
+        let bad_section = |reason: &str| ArtifactError::BadSection {
+            name: name.to_string(),
+            reason: reason.to_string(),
+        };
+        let values: Vec<f32> = match section.encoding {
+            Encoding::F32 => section
+                .bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            Encoding::F16 => section
+                .bytes
+                .chunks_exact(2)
+                .map(|b| f16::from_le_bytes([b[0], b[1]]).to_f32())
+                .collect(),
+            Encoding::Cbor => return Err(bad_section("is CBOR, not a float table")),
+        };
+        let expected: u64 = section.dimensions.iter().map(|d| *d as u64).product();
+        if values.len() as u64 != expected {
+            return Err(bad_section(&format!(
+                "has {} values, but its dimensions {:?} need {}",
+                values.len(),
+                section.dimensions,
+                expected
+            )));
+        }
+        Ok(values)
+    }
+
+    pub fn value<T: DeserializeOwned>(&self, name: &str) -> Result<T, ArtifactError> {
+        let section = self.section(name)?;
+        if section.encoding != Encoding::Cbor {
+            return Err(ArtifactError::BadSection {
+                name: name.to_string(),
+                reason: "is a float table, not CBOR".to_string(),
+            });
+        }
+        ciborium::de::from_reader(section.bytes.as_slice()).map_err(|error| {
+            ArtifactError::BadSection {
+                name: name.to_string(),
+                reason: format!("{:?}", error),
+            }
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = Header {
+            kind: self.kind.clone(),
+            parameters: self.parameters.clone(),
+            sections: self
+                .sections
+                .iter()
+                .map(|section| SectionHeader {
+                    name: section.name.clone(),
+                    encoding: section.encoding,
+                    dimensions: section.dimensions.clone(),
+                    byte_len: section.bytes.len() as u64,
+                })
+                .collect(),
+        };
+        let mut header_bytes = Vec::new();
+        ciborium::ser::into_writer(&header, &mut header_bytes).unwrap();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(header_bytes.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&header_bytes);
+        for section in &self.sections {
+            bytes.extend_from_slice(&section.bytes);
+        }
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ArtifactError> {
+        let truncated = |expected: usize| ArtifactError::Truncated {
+            expected,
+            found: bytes.len(),
+        };
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(ArtifactError::NotAnArtifact);
+        }
+        if bytes.len() < PREAMBLE_SIZE {
+            return Err(truncated(PREAMBLE_SIZE));
+        }
+        let version = read_u32(&bytes[4..8]);
+        if version != FORMAT_VERSION {
+            return Err(ArtifactError::UnsupportedVersion(version));
+        }
+        if bytes.len() < PREAMBLE_SIZE + CHECKSUM_SIZE {
+            return Err(truncated(PREAMBLE_SIZE + CHECKSUM_SIZE));
+        }
+        // The sizes in the header aren't trusted until the checksum vouches for them.
+        let checksum_start = bytes.len() - CHECKSUM_SIZE;
+        let (expected_checksum, found_checksum) = (
+            read_u32(&bytes[checksum_start..]),
+            crc32(&bytes[..checksum_start]),
+        );
+        if expected_checksum != found_checksum {
+            return Err(ArtifactError::ChecksumMismatch {
+                expected: expected_checksum,
+                found: found_checksum,
+            });
+        }
+
+        let header_size = read_u32(&bytes[8..12]) as usize;
+        let header_end = PREAMBLE_SIZE
+            .checked_add(header_size)
+            .filter(|header_end| *header_end <= checksum_start)
+            .ok_or_else(|| {
+                ArtifactError::BadHeader(format!("header size {} is past the end", header_size))
+            })?;
+        let header: Header = ciborium::de::from_reader(&bytes[PREAMBLE_SIZE..header_end])
+            .map_err(|error| ArtifactError::BadHeader(format!("{:?}", error)))?;
+
+        let payload_size = header
+            .sections
+            .iter()
+            .try_fold(0_u64, |size, section| size.checked_add(section.byte_len))
+            .and_then(|size| usize::try_from(size).ok())
+            .ok_or_else(|| ArtifactError::BadHeader("section sizes overflow".to_string()))?;
+        if payload_size != checksum_start - header_end {
+            return Err(ArtifactError::BadHeader(format!(
+                "sections take {} bytes, found {}",
+                payload_size,
+                checksum_start - header_end
+            )));
+        }
+
+        let mut start = header_end;
+        let sections = header
+            .sections
+            .into_iter()
+            .map(|section| {
+                let end = start + section.byte_len as usize;
+                let bytes = bytes[start..end].to_vec();
+                start = end;
+                Section {
+                    name: section.name,
+                    encoding: section.encoding,
+                    dimensions: section.dimensions,
+                    bytes,
+                }
+            })
+            .collect();
+        Ok(ArtifactContainer {
+            kind: header.kind,
+            parameters: header.parameters,
+            sections,
+        })
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+// The IEEE polynomial, as used by zip and png.
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => 0xEDB88320 ^ (crc >> 1),
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| {
+        CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+// An artifact that can be stored in a container.
+pub trait Artifact: Sized {
+    // Checked on read, so one artifact's file can't be loaded as another's.
+    const KIND: &'static str;
+
+    fn to_container(&self) -> ArtifactContainer;
+
+    fn from_container(container: &ArtifactContainer) -> Result<Self, ArtifactError>;
+}
+
+pub fn write_artifact<T: Artifact>(artifact: &T) -> Vec<u8> {
+    artifact.to_container().to_bytes()
+}
+
+pub fn read_artifact<T: Artifact>(bytes: &[u8]) -> Result<T, ArtifactError> {
+    let container = ArtifactContainer::from_bytes(bytes)?;
+    if container.kind != T::KIND {
+        return Err(ArtifactError::WrongKind {
+            expected: T::KIND.to_string(),
+            found: container.kind,
+        });
+    }
+    T::from_container(&container)
+}
+
+fn dimensions<const N: usize>(
+    container: &ArtifactContainer,
+    name: &str,
+) -> Result<[u32; N], ArtifactError> {
+    let dimensions = &container.section(name)?.dimensions;
+    dimensions
+        .as_slice()
+        .try_into()
+        .map_err(|_| ArtifactError::BadSection {
+            name: name.to_string(),
+            reason: format!("expected {} dimensions, found {:?}", N, dimensions),
+        })
+}
+
+// A section that has to line up with another, so indexing one by the other's dimensions is safe.
+fn floats_with_dimensions(
+    container: &ArtifactContainer,
+    name: &str,
+    expected: &[u32],
+) -> Result<Vec<f32>, ArtifactError> {
+    let found = &container.section(name)?.dimensions;
+    if found.as_slice() != expected {
+        return Err(ArtifactError::BadSection {
+            name: name.to_string(),
+            reason: format!("expected dimensions {:?}, found {:?}", expected, found),
+        });
+    }
+    container.floats(name)
+}
+
+fn bounds(container: &ArtifactContainer, name: &str) -> Result<[f32; 2], ArtifactError> {
+    Ok([
+        container.parameter(&format!("{}.0", name))? as f32,
+        container.parameter(&format!("{}.1", name))? as f32,
+    ])
+}
+
+impl Artifact for TimeDelayTable {
+    const KIND: &'static str = "time_delay_table";
+
+    fn to_container(&self) -> ArtifactContainer {
+        ArtifactContainer::new(Self::KIND)
+            .with_parameter("disc_bounds.0", self.disc_bounds[0] as f64)
+            .with_parameter("disc_bounds.1", self.disc_bounds[1] as f64)
+            .with_parameter("distance_bounds.0", self.distance_bounds[0] as f64)
+            .with_parameter("distance_bounds.1", self.distance_bounds[1] as f64)
+            .with_floats("delays", Encoding::F32, &self.dimensions, &self.delays)
+    }
+
+    fn from_container(container: &ArtifactContainer) -> Result<Self, ArtifactError> {
+        Ok(TimeDelayTable {
+            dimensions: dimensions(container, "delays")?,
+            disc_bounds: bounds(container, "disc_bounds")?,
+            distance_bounds: bounds(container, "distance_bounds")?,
+            delays: container.floats("delays")?,
+        })
+    }
+}
+
+impl Artifact for GFactorTable {
+    const KIND: &'static str = "g_factor_table";
+
+    fn to_container(&self) -> ArtifactContainer {
+        ArtifactContainer::new(Self::KIND)
+            .with_parameter("disc_bounds.0", self.disc_bounds[0] as f64)
+            .with_parameter("disc_bounds.1", self.disc_bounds[1] as f64)
+            .with_parameter("distance_bounds.0", self.distance_bounds[0] as f64)
+            .with_parameter("distance_bounds.1", self.distance_bounds[1] as f64)
+            .with_floats(
+                "gravitational_shifts",
+                Encoding::F32,
+                &self.dimensions,
+                &self.gravitational_shifts,
+            )
+            .with_floats(
+                "doppler_coefficients",
+                Encoding::F32,
+                &self.dimensions,
+                &self.doppler_coefficients,
+            )
+    }
+
+    fn from_container(container: &ArtifactContainer) -> Result<Self, ArtifactError> {
+        let dimensions = dimensions(container, "gravitational_shifts")?;
+        Ok(GFactorTable {
+            dimensions,
+            disc_bounds: bounds(container, "disc_bounds")?,
+            distance_bounds: bounds(container, "distance_bounds")?,
+            gravitational_shifts: container.floats("gravitational_shifts")?,
+            doppler_coefficients: floats_with_dimensions(
+                container,
+                "doppler_coefficients",
+                &dimensions,
+            )?,
+        })
+    }
+}
+
+impl Artifact for WormholeDirectionTable {
+    const KIND: &'static str = "wormhole_direction_table";
+
+    fn to_container(&self) -> ArtifactContainer {
+        ArtifactContainer::new(Self::KIND)
+            .with_parameter("distance_bounds.0", self.distance_bounds[0] as f64)
+            .with_parameter("distance_bounds.1", self.distance_bounds[1] as f64)
+            .with_parameter("z_bounds.0", self.z_bounds[0] as f64)
+            .with_parameter("z_bounds.1", self.z_bounds[1] as f64)
+            .with_parameter("throat_radius", self.throat_radius as f64)
+            .with_floats(
+                "final_angles",
+                Encoding::F32,
+                &self.dimensions,
+                &self.final_angles,
+            )
+            // Only ever 0 or 1.
+            .with_floats("far_side", Encoding::F16, &self.dimensions, &self.far_side)
+    }
+
+    fn from_container(container: &ArtifactContainer) -> Result<Self, ArtifactError> {
+        let dimensions = dimensions(container, "final_angles")?;
+        Ok(WormholeDirectionTable {
+            dimensions,
+            distance_bounds: bounds(container, "distance_bounds")?,
+            z_bounds: bounds(container, "z_bounds")?,
+            throat_radius: container.parameter("throat_radius")? as f32,
+            final_angles: container.floats("final_angles")?,
+            far_side: floats_with_dimensions(container, "far_side", &dimensions)?,
+        })
+    }
+}
+
+impl Artifact for ViewBound {
+    const KIND: &'static str = "view_bound";
+
+    fn to_container(&self) -> ArtifactContainer {
+        ArtifactContainer::new(Self::KIND).with_floats(
+            "dist_to_view_bound",
+            Encoding::F32,
+            &[self.dist_to_view_bound.len() as u32],
+            &self.dist_to_view_bound,
+        )
+    }
+
+    fn from_container(container: &ArtifactContainer) -> Result<Self, ArtifactError> {
+        Ok(ViewBound {
+            dist_to_view_bound: container.floats("dist_to_view_bound")?,
+        })
+    }
+}
+
+// One section per field of `ApproximationFunction`, indexed by (view, distance) like `params`.
+const APPROXIMATION_FIELDS: [&str; 7] = [
+    "theta_start",
+    "theta_final",
+    "min_distance",
+    "theta_max_start",
+    "theta_min_start",
+    "initial_dist",
+    "view",
+];
+
+fn approximation_fields(function: &ApproximationFunction) -> [f32; 7] {
+    [
+        function.theta_start,
+        function.theta_final,
+        function.min_distance,
+        function.theta_max_start,
+        function.theta_min_start,
+        function.initial_dist,
+        function.view,
+    ]
+}
+
+impl Artifact for ViewAngleParameterCache {
+    const KIND: &'static str = "view_angle_parameter_cache";
+
+    fn to_container(&self) -> ArtifactContainer {
+        let dimensions = [
+            self.params.len() as u32 / self.dist_dim.max(1),
+            self.dist_dim,
+        ];
+        let fields: Vec<[f32; 7]> = self.params.iter().map(approximation_fields).collect();
+        APPROXIMATION_FIELDS.iter().enumerate().fold(
+            ArtifactContainer::new(Self::KIND).with_parameter("dist_dim", self.dist_dim as f64),
+            |container, (i, name)| {
+                let values: Vec<f32> = fields.iter().map(|field| field[i]).collect();
+                container.with_floats(name, Encoding::F32, &dimensions, &values)
+            },
+        )
+    }
+
+    fn from_container(container: &ArtifactContainer) -> Result<Self, ArtifactError> {
+        let dist_dim = container.parameter("dist_dim")? as u32;
+        let [views, _] = dimensions(container, APPROXIMATION_FIELDS[0])?;
+        let fields = APPROXIMATION_FIELDS
+            .iter()
+            .map(|name| floats_with_dimensions(container, name, &[views, dist_dim]))
+            .collect::<Result<Vec<_>, _>>()?;
+        let params = (0..fields[0].len())
+            .map(|i| ApproximationFunction {
+                theta_start: fields[0][i],
+                theta_final: fields[1][i],
+                min_distance: fields[2][i],
+                theta_max_start: fields[3][i],
+                theta_min_start: fields[4][i],
+                initial_dist: fields[5][i],
+                view: fields[6][i],
+            })
+            .collect();
+        Ok(ViewAngleParameterCache { dist_dim, params })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wire_structs::sampler::{
+        view_angle_parameter_cache::ViewAngleParameterCache,
+        wormhole_direction_table::WormholeDirectionTable,
+    };
+
+    use super::{
+        crc32, read_artifact, write_artifact, Artifact, ArtifactContainer, ArtifactError, Encoding,
+        Header, SectionHeader, APPROXIMATION_FIELDS, FORMAT_VERSION, MAGIC,
+    };
+
+    fn table() -> WormholeDirectionTable {
+        WormholeDirectionTable {
+            dimensions: [3, 2],
+            distance_bounds: [5., 30.],
+            z_bounds: [-1., 1.],
+            throat_radius: 1.,
+            final_angles: vec![0.1, 0.2, 0.3, 1.4, 1.5, 1.6],
+            far_side: vec![0., 0., 1., 0., 1., 1.],
+        }
+    }
+
+    #[test]
+    fn checksum_matches_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn round_trips() {
+        let container = ArtifactContainer::new("test")
+            .with_parameter("radius", 1.5)
+            .with_floats("f32", Encoding::F32, &[2, 2], &[1., -2., 3.25, 1e-7])
+            .with_floats("f16", Encoding::F16, &[3], &[0.5, 1., 1000.])
+            .with_value("spec", &vec![(1_u32, "a".to_string())]);
+        let read = ArtifactContainer::from_bytes(&container.to_bytes()).unwrap();
+        assert_eq!(read, container);
+        assert_eq!(read.parameter("radius"), Ok(1.5));
+        assert_eq!(read.floats("f32").unwrap(), vec![1., -2., 3.25, 1e-7]);
+        assert_eq!(read.floats("f16").unwrap(), vec![0.5, 1., 1000.]);
+        assert_eq!(
+            read.value::<Vec<(u32, String)>>("spec").unwrap(),
+            vec![(1, "a".to_string())]
+        );
+        assert_eq!(
+            read.floats("missing"),
+            Err(ArtifactError::MissingSection("missing".to_string()))
+        );
+
+        let table = table();
+        let read: WormholeDirectionTable = read_artifact(&write_artifact(&table)).unwrap();
+        assert_eq!(read.dimensions, table.dimensions);
+        assert_eq!(read.distance_bounds, table.distance_bounds);
+        assert_eq!(read.final_angles, table.final_angles);
+        assert_eq!(read.far_side, table.far_side);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let bytes = write_artifact(&table());
+        let read = |bytes: &[u8]| read_artifact::<WormholeDirectionTable>(bytes).err();
+
+        assert_eq!(
+            read(b"{\"dimensions\":[3,2]}"),
+            Some(ArtifactError::NotAnArtifact)
+        );
+        // Truncated files fail the checksum before anything reads the header's sizes.
+        assert!(matches!(
+            read(&bytes[..bytes.len() - 10]),
+            Some(ArtifactError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            read(&bytes[..10]),
+            Some(ArtifactError::Truncated { .. })
+        ));
+
+        let mut corrupted = bytes.clone();
+        let len = corrupted.len();
+        corrupted[len - 8] ^= 1;
+        assert!(matches!(
+            read(&corrupted),
+            Some(ArtifactError::ChecksumMismatch { .. })
+        ));
+
+        let mut newer = bytes.clone();
+        newer[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            read(&newer),
+            Some(ArtifactError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+
+        // A header whose sizes add up past u64 is rejected even with a valid checksum.
+        let section = |byte_len: u64| SectionHeader {
+            name: "f32".to_string(),
+            encoding: Encoding::F32,
+            dimensions: vec![1],
+            byte_len,
+        };
+        let header = Header {
+            kind: "wormhole_direction_table".to_string(),
+            parameters: Vec::new(),
+            sections: vec![section(u64::MAX), section(8)],
+        };
+        let mut header_bytes = Vec::new();
+        ciborium::ser::into_writer(&header, &mut header_bytes).unwrap();
+        let mut overflowing = Vec::new();
+        overflowing.extend_from_slice(&MAGIC);
+        overflowing.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        overflowing.extend_from_slice(&(header_bytes.len() as u32).to_le_bytes());
+        overflowing.extend_from_slice(&header_bytes);
+        overflowing.extend_from_slice(&[0; 4]);
+        let checksum = crc32(&overflowing);
+        overflowing.extend_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(
+            read(&overflowing),
+            Some(ArtifactError::BadHeader(_))
+        ));
+
+        let other = ArtifactContainer::new("view_bound").to_bytes();
+        assert!(matches!(
+            read(&other),
+            Some(ArtifactError::WrongKind { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_mismatched_sections() {
+        // Two views at two distances, except the last field only has one view.
+        let container = APPROXIMATION_FIELDS.iter().fold(
+            ArtifactContainer::new(ViewAngleParameterCache::KIND).with_parameter("dist_dim", 2.),
+            |container, name| match *name == "view" {
+                true => container.with_floats(name, Encoding::F32, &[1, 2], &[0.; 2]),
+                false => container.with_floats(name, Encoding::F32, &[2, 2], &[0.; 4]),
+            },
+        );
+        assert!(matches!(
+            ViewAngleParameterCache::from_container(&container),
+            Err(ArtifactError::BadSection { name, .. }) if name == "view"
+        ));
+    }
+}
@@ -3,31 +3,239 @@ use std::{
     io::Read,
 };
 
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::artifact_container::{read_artifact, write_artifact, Artifact};
+
+// Part of every artifact's inputs hash. Bump it when a change to the integrator or samplers
+// changes what the same parameters generate, so every artifact is regenerated.
+pub const GENERATOR_VERSION: u32 = 1;
+
+// Whether artifacts that are missing or were generated from other inputs may be regenerated.
+// `Frozen` is for shipping the artifacts as they are: a mismatch is an error instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Regeneration {
+    Allowed,
+    Frozen,
+}
+
+// Sidecar written next to each artifact, recording what it was generated from. The inputs are
+// kept so a mismatch can be diagnosed by diffing manifests.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct Manifest {
+    inputs_hash: String,
+    generator_version: u32,
+    inputs: serde_json::Value,
+}
+
+impl Manifest {
+    fn new<I: Serialize>(inputs: &I) -> Self {
+        let inputs = serde_json::to_value(inputs).unwrap();
+        let generator_version = GENERATOR_VERSION;
+        let hashed = serde_json::to_vec(&(generator_version, &inputs)).unwrap();
+        Self {
+            inputs_hash: format!("{:016x}", fnv1a(&hashed)),
+            generator_version,
+            inputs,
+        }
+    }
+}
+
+// The hash an artifact generated from `inputs` records in its manifest. Artifacts built from
+// another artifact include its hash in their own inputs, so they're regenerated along with it.
+pub fn inputs_hash<I: Serialize>(inputs: &I) -> String {
+    Manifest::new(inputs).inputs_hash
+}
+
+// 64-bit FNV-1a; unlike `DefaultHasher` it's stable across Rust versions.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn manifest_path(path: &str) -> String {
+    format!("{}.manifest.json", path)
+}
 
 fn get_file_as_byte_vec(filename: &str) -> Result<Vec<u8>, std::io::Error> {
     let mut f = File::open(&filename)?;
     let metadata = fs::metadata(&filename)?;
     let mut buffer = vec![0; metadata.len() as usize];
-    f.read(&mut buffer)?;
+    f.read_exact(&mut buffer)?;
 
     Ok(buffer)
 }
 
-pub fn get_or_generate_file<T>(path: &str, generating_function: &dyn Fn() -> T) -> T
+// Reads the artifact at `path` if its manifest matches, otherwise says why it can't be used.
+fn read_if_current<T>(
+    path: &str,
+    manifest: &Manifest,
+    read: &dyn Fn(&[u8]) -> Result<T, String>,
+) -> Result<T, String> {
+    let stored =
+        get_file_as_byte_vec(&manifest_path(path)).map_err(|_| "no manifest".to_string())?;
+    let stored: Manifest = serde_json::from_slice(&stored)
+        .map_err(|error| format!("unreadable manifest: {}", error))?;
+    if stored.inputs_hash != manifest.inputs_hash {
+        return Err(format!(
+            "generated from inputs {}, expected {}",
+            stored.inputs_hash, manifest.inputs_hash
+        ));
+    }
+    let buffer = get_file_as_byte_vec(path).map_err(|error| error.to_string())?;
+    read(&buffer)
+}
+
+fn get_or_generate<T, I: Serialize>(
+    path: &str,
+    inputs: &I,
+    regeneration: Regeneration,
+    read: &dyn Fn(&[u8]) -> Result<T, String>,
+    write: &dyn Fn(&T) -> Vec<u8>,
+    generating_function: &dyn Fn() -> T,
+) -> T {
+    let manifest = Manifest::new(inputs);
+    let reason = match read_if_current(path, &manifest, read) {
+        Ok(object) => return object,
+        Err(reason) => reason,
+    };
+    if regeneration == Regeneration::Frozen {
+        panic!("{} is stale and regeneration is frozen: {}", path, reason);
+    }
+    println!("Regenerating {}: {}", path, reason);
+
+    let object = generating_function();
+    let folder_path = path.rsplit_once("/").unwrap().0;
+    fs::create_dir_all(folder_path).unwrap();
+    // Drop the old manifest first, so an interrupted write can't leave it vouching for the file.
+    let _ = fs::remove_file(manifest_path(path));
+    fs::write(path, write(&object)).expect("Unable to write file");
+    let manifest = serde_json::to_string_pretty(&manifest).unwrap();
+    fs::write(manifest_path(path), manifest).expect("Unable to write file");
+    object
+}
+
+// Returns the artifact at `path` if it was generated from `inputs`, otherwise generates it.
+// `inputs` should hold everything the generating function depends on.
+pub fn get_or_generate_file<T, I>(
+    path: &str,
+    inputs: &I,
+    regeneration: Regeneration,
+    generating_function: &dyn Fn() -> T,
+) -> T
 where
     T: DeserializeOwned + Serialize,
+    I: Serialize,
 {
-    let buffer = get_file_as_byte_vec(path);
-    let object;
-    if buffer.is_ok() {
-        return serde_json::from_slice::<T>(&buffer.unwrap()).unwrap();
-    } else {
-        object = generating_function();
-        let data = serde_json::to_string(&object).unwrap();
-        let folder_path = path.rsplit_once("/").unwrap().0;
-        fs::create_dir_all(folder_path).unwrap();
-        fs::write(path, data).expect("Unable to write file");
+    get_or_generate(
+        path,
+        inputs,
+        regeneration,
+        &|buffer| serde_json::from_slice(buffer).map_err(|error| error.to_string()),
+        &|object| serde_json::to_vec(object).unwrap(),
+        generating_function,
+    )
+}
+
+// Like `get_or_generate_file`, for artifacts stored in a container. Files that can't be read, such
+// as ones left truncated or written by an older format, are regenerated.
+pub fn get_or_generate_artifact<T: Artifact, I: Serialize>(
+    path: &str,
+    inputs: &I,
+    regeneration: Regeneration,
+    generating_function: &dyn Fn() -> T,
+) -> T {
+    get_or_generate(
+        path,
+        inputs,
+        regeneration,
+        &|buffer| read_artifact(buffer).map_err(|error| error.to_string()),
+        &|object| write_artifact(object),
+        generating_function,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, fs};
+
+    use super::{get_or_generate_file, inputs_hash, manifest_path, Manifest, Regeneration};
+
+    fn artifact_path(name: &str) -> String {
+        let folder = std::env::temp_dir().join(format!("artifact_utils_{}", std::process::id()));
+        folder.join(name).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn regenerates_when_inputs_change() {
+        let path = artifact_path("regenerates.txt");
+        let generated = Cell::new(0);
+        let get = |inputs: (u32, f32)| {
+            get_or_generate_file(&path, &inputs, Regeneration::Allowed, &|| {
+                generated.set(generated.get() + 1);
+                vec![inputs.1; 3]
+            })
+        };
+
+        assert_eq!(get((1, 0.5)), vec![0.5; 3]);
+        assert_eq!(get((1, 0.5)), vec![0.5; 3]);
+        assert_eq!(generated.get(), 1);
+
+        assert_eq!(get((1, 0.25)), vec![0.25; 3]);
+        assert_eq!(generated.get(), 2);
+
+        // Files without a manifest, like ones from before manifests, aren't trusted.
+        fs::remove_file(manifest_path(&path)).unwrap();
+        assert_eq!(get((1, 0.25)), vec![0.25; 3]);
+        assert_eq!(generated.get(), 3);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(manifest_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn downstream_regenerates_with_upstream() {
+        let upstream_path = artifact_path("upstream.txt");
+        let downstream_path = artifact_path("downstream.txt");
+        let generated = Cell::new(0);
+        let get = |upstream_inputs: u32| {
+            let upstream = get_or_generate_file(
+                &upstream_path,
+                &upstream_inputs,
+                Regeneration::Allowed,
+                &|| vec![upstream_inputs as f32],
+            );
+            let inputs = ("downstream", inputs_hash(&upstream_inputs));
+            get_or_generate_file(&downstream_path, &inputs, Regeneration::Allowed, &|| {
+                generated.set(generated.get() + 1);
+                vec![2. * upstream[0]]
+            })
+        };
+
+        assert_eq!(get(1), vec![2.]);
+        assert_eq!(get(1), vec![2.]);
+        assert_eq!(generated.get(), 1);
+        assert_eq!(get(2), vec![4.]);
+        assert_eq!(generated.get(), 2);
+
+        let manifest: Manifest =
+            serde_json::from_slice(&fs::read(manifest_path(&upstream_path)).unwrap()).unwrap();
+        assert_eq!(manifest.inputs_hash, inputs_hash(&2_u32));
+        for path in [&upstream_path, &downstream_path] {
+            fs::remove_file(path).unwrap();
+            fs::remove_file(manifest_path(path)).unwrap();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "regeneration is frozen")]
+    fn frozen_rejects_mismatched_inputs() {
+        let path = artifact_path("frozen.txt");
+        get_or_generate_file(&path, &1, Regeneration::Allowed, &|| vec![1.]);
+        // Still readable with the inputs it was generated from.
+        get_or_generate_file(&path, &1, Regeneration::Frozen, &|| -> Vec<f32> {
+            panic!()
+        });
+        get_or_generate_file(&path, &2, Regeneration::Frozen, &|| vec![2.]);
     }
-    object
 }
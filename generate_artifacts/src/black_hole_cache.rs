@@ -1,8 +1,19 @@
+use std::{fmt, path::Path};
+
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    final_direction_cache::direction_cache::DirectionCache,
-    path_distance_cache::distance_cache::DistanceCache,
+    artifact_container::{Artifact, ArtifactContainer, ArtifactError},
+    cache_spec::{DirectionCacheSpec, DistanceCacheSpec},
+    checkpoint::CheckpointError,
+    final_direction_cache::{
+        direction_cache::DirectionCache, kerr_direction_cache::KerrDirectionCache,
+    },
+    path_distance_cache::{distance_cache::DistanceCache, g_factor_cache::GFactorCache},
+    path_integration2::{
+        ray_cast_config::RayCastConfig,
+        structs::field::{ChargeOutOfRange, FieldModel},
+    },
 };
 
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
@@ -14,39 +25,140 @@ pub struct BlackHoleCache {
     pub disc_bounds: (f64, f64),
     pub distance_cache: DistanceCache,
     pub direction_cache: DirectionCache,
+    // Missing from caches generated before the disc was shaded with frequency shifts.
+    #[serde(default)]
+    pub g_factor_cache: Option<GFactorCache>,
+    // Only present when rendering a spinning black hole.
+    #[serde(default)]
+    pub kerr_cache: Option<KerrDirectionCache>,
+}
+
+// Everything a `BlackHoleCache` is computed from.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BlackHoleCacheParams {
+    pub distance_bounds: (f64, f64),
+    pub black_hole_radius: f64,
+    pub disc_bounds: (f64, f64),
+    pub direction_spec: DirectionCacheSpec,
+    pub distance_spec: DistanceCacheSpec,
+    pub model: FieldModel,
+    pub config: RayCastConfig,
+}
+
+#[derive(Debug)]
+pub enum BlackHoleCacheError {
+    Charge(ChargeOutOfRange),
+    Checkpoint(CheckpointError),
+    // The caches being combined were computed with different parameters.
+    Mismatch(&'static str),
+}
+
+impl fmt::Display for BlackHoleCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlackHoleCacheError::Charge(error) => error.fmt(f),
+            BlackHoleCacheError::Checkpoint(error) => error.fmt(f),
+            BlackHoleCacheError::Mismatch(parameter) => {
+                write!(f, "the caches were computed with different {}", parameter)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlackHoleCacheError {}
+
+impl From<CheckpointError> for BlackHoleCacheError {
+    fn from(error: CheckpointError) -> Self {
+        BlackHoleCacheError::Checkpoint(error)
+    }
+}
+
+// Fails with the first parameter that differs.
+fn check_matches(checks: &[(&'static str, bool)]) -> Result<(), BlackHoleCacheError> {
+    match checks.iter().find(|(_, matches)| !matches) {
+        Some((parameter, _)) => Err(BlackHoleCacheError::Mismatch(parameter)),
+        None => Ok(()),
+    }
 }
 
 impl BlackHoleCache {
-    pub fn compute_new(
-        distance_bounds: (f64, f64),
-        black_hole_radius: f64,
-        disc_bounds: (f64, f64),
-        direction_cache_size: (usize, usize),
-        distance_cache_size: (usize, usize, usize),
-    ) -> Self {
+    pub fn compute_new(params: &BlackHoleCacheParams) -> Result<Self, BlackHoleCacheError> {
+        params
+            .model
+            .validate()
+            .map_err(BlackHoleCacheError::Charge)?;
         let distance_cache = DistanceCache::compute_new(
-            distance_cache_size,
-            distance_bounds,
-            black_hole_radius,
-            disc_bounds,
+            params.distance_spec.clone(),
+            params.distance_bounds,
+            params.black_hole_radius,
+            params.model,
+            params.config,
+            params.disc_bounds,
         );
-        let direction_cache =
-            DirectionCache::compute_new(direction_cache_size, distance_bounds, black_hole_radius);
-        BlackHoleCache {
-            direction_cache_size,
-            distance_bounds,
-            black_hole_radius,
-            distance_cache_size,
-            disc_bounds,
-            distance_cache,
-            direction_cache,
-        }
+        let direction_cache = DirectionCache::compute_new(
+            params.direction_spec.clone(),
+            params.distance_bounds,
+            params.black_hole_radius,
+            params.model,
+            params.config,
+        );
+        Self::new(distance_cache, direction_cache)
+    }
+
+    // Like `compute_new`, checkpointing both caches' slices under `checkpoint_dir` so an
+    // interrupted run can be resumed. The checkpoints can be deleted once this returns.
+    pub fn compute_checkpointed(
+        params: &BlackHoleCacheParams,
+        checkpoint_dir: &Path,
+    ) -> Result<Self, BlackHoleCacheError> {
+        params
+            .model
+            .validate()
+            .map_err(BlackHoleCacheError::Charge)?;
+        let distance_cache = DistanceCache::compute_checkpointed(
+            params.distance_spec.clone(),
+            params.distance_bounds,
+            params.black_hole_radius,
+            params.model,
+            params.config,
+            params.disc_bounds,
+            &checkpoint_dir.join("distance"),
+        )?;
+        let direction_cache = DirectionCache::compute_checkpointed(
+            params.direction_spec.clone(),
+            params.distance_bounds,
+            params.black_hole_radius,
+            params.model,
+            params.config,
+            &checkpoint_dir.join("direction"),
+        )?;
+        Self::new(distance_cache, direction_cache)
     }
 
-    pub fn new(distance_cache: DistanceCache, direction_cache: DirectionCache) -> Self {
-        assert!(direction_cache.black_hole_radius == distance_cache.black_hole_radius);
-        assert!(direction_cache.distance_bounds == distance_cache.distance_bounds);
-        BlackHoleCache {
+    pub fn new(
+        distance_cache: DistanceCache,
+        direction_cache: DirectionCache,
+    ) -> Result<Self, BlackHoleCacheError> {
+        check_matches(&[
+            (
+                "black hole radii",
+                direction_cache.black_hole_radius == distance_cache.black_hole_radius,
+            ),
+            (
+                "distance bounds",
+                direction_cache.distance_bounds == distance_cache.distance_bounds,
+            ),
+            (
+                "field models",
+                direction_cache.model == distance_cache.model,
+            ),
+            (
+                "ray cast configs",
+                direction_cache.config == distance_cache.config,
+            ),
+        ])?;
+        let g_factor_cache = GFactorCache::compute_new(&distance_cache);
+        Ok(BlackHoleCache {
             direction_cache_size: direction_cache.cache_size,
             distance_bounds: direction_cache.distance_bounds,
             black_hole_radius: direction_cache.black_hole_radius,
@@ -54,6 +166,140 @@ impl BlackHoleCache {
             disc_bounds: distance_cache.disc_bounds,
             distance_cache,
             direction_cache,
-        }
+            g_factor_cache: Some(g_factor_cache),
+            kerr_cache: None,
+        })
+    }
+
+    pub fn with_kerr_cache(
+        self,
+        kerr_cache: KerrDirectionCache,
+    ) -> Result<Self, BlackHoleCacheError> {
+        check_matches(&[
+            (
+                "black hole radii",
+                kerr_cache.black_hole_radius == self.black_hole_radius,
+            ),
+            (
+                "distance bounds",
+                kerr_cache.distance_bounds == self.distance_bounds,
+            ),
+        ])?;
+        Ok(BlackHoleCache {
+            kerr_cache: Some(kerr_cache),
+            ..self
+        })
+    }
+}
+
+// The caches' nested structure is kept as CBOR, which stores the floats in binary.
+impl Artifact for BlackHoleCache {
+    const KIND: &'static str = "black_hole_cache";
+
+    fn to_container(&self) -> ArtifactContainer {
+        ArtifactContainer::new(Self::KIND)
+            .with_parameter("black_hole_radius", self.black_hole_radius)
+            .with_parameter("distance_bounds.0", self.distance_bounds.0)
+            .with_parameter("distance_bounds.1", self.distance_bounds.1)
+            .with_parameter("disc_bounds.0", self.disc_bounds.0)
+            .with_parameter("disc_bounds.1", self.disc_bounds.1)
+            .with_value("distance_cache", &self.distance_cache)
+            .with_value("direction_cache", &self.direction_cache)
+            .with_value("g_factor_cache", &self.g_factor_cache)
+            .with_value("kerr_cache", &self.kerr_cache)
+    }
+
+    fn from_container(container: &ArtifactContainer) -> Result<Self, ArtifactError> {
+        let distance_cache: DistanceCache = container.value("distance_cache")?;
+        let direction_cache: DirectionCache = container.value("direction_cache")?;
+        Ok(BlackHoleCache {
+            direction_cache_size: direction_cache.cache_size,
+            distance_bounds: (
+                container.parameter("distance_bounds.0")?,
+                container.parameter("distance_bounds.1")?,
+            ),
+            black_hole_radius: container.parameter("black_hole_radius")?,
+            distance_cache_size: distance_cache.cache_size,
+            disc_bounds: (
+                container.parameter("disc_bounds.0")?,
+                container.parameter("disc_bounds.1")?,
+            ),
+            distance_cache,
+            direction_cache,
+            g_factor_cache: container.value("g_factor_cache")?,
+            kerr_cache: container.value("kerr_cache")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cache_spec::{AxisSpec, DirectionCacheSpec, DistanceCacheSpec},
+        final_direction_cache::direction_cache::DirectionCache,
+        path_distance_cache::distance_cache::DistanceCache,
+        path_integration2::{
+            ray_cast_config::RayCastConfig,
+            structs::field::{ChargeOutOfRange, FieldModel},
+        },
+    };
+
+    use super::{BlackHoleCache, BlackHoleCacheError, BlackHoleCacheParams};
+
+    #[test]
+    fn rejects_charges_past_extremal() {
+        let compute = |charge: f64| {
+            let params = BlackHoleCacheParams {
+                distance_bounds: (5., 20.),
+                black_hole_radius: 1.5,
+                disc_bounds: (3., 6.),
+                direction_spec: DirectionCacheSpec::default(),
+                distance_spec: DistanceCacheSpec::default(),
+                model: FieldModel::ReissnerNordstrom { charge },
+                config: RayCastConfig::default(),
+            };
+            match BlackHoleCache::compute_new(&params) {
+                Err(BlackHoleCacheError::Charge(error)) => Some(error),
+                _ => None,
+            }
+        };
+        assert_eq!(compute(1.5), Some(ChargeOutOfRange(1.5)));
+        assert_eq!(compute(-1.5), Some(ChargeOutOfRange(-1.5)));
+        assert!(matches!(compute(f64::NAN), Some(ChargeOutOfRange(charge)) if charge.is_nan()));
+    }
+
+    #[test]
+    fn rejects_mismatched_caches() {
+        let distance_spec = DistanceCacheSpec {
+            distance: AxisSpec::linear(2).unwrap(),
+            angle: AxisSpec::linear(2).unwrap(),
+            z: AxisSpec::linear(4).unwrap(),
+            z_refinement: None,
+        };
+        let direction_spec = DirectionCacheSpec {
+            distance: AxisSpec::linear(2).unwrap(),
+            z: AxisSpec::linear(4).unwrap(),
+            z_refinement: None,
+        };
+        // Everything matches but the radius.
+        let distance_cache = DistanceCache::compute_new(
+            distance_spec,
+            (5., 20.),
+            1.,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+            (3., 6.),
+        );
+        let direction_cache = DirectionCache::compute_new(
+            direction_spec,
+            (5., 20.),
+            1.5,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+        );
+        assert!(matches!(
+            BlackHoleCache::new(distance_cache, direction_cache),
+            Err(BlackHoleCacheError::Mismatch("black hole radii"))
+        ));
     }
 }
@@ -0,0 +1,550 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    final_direction_cache::{
+        direction_cache::DIRECTION_CACHE_SIZE, fixed_distance_direction_cache::DISTANCE_CACHE_SIZE,
+    },
+    path_distance_cache::{
+        distance_cache::ALL_DISTANCE_CACHE_SIZE,
+        fixed_distance_distance_cache::DISTANCE_CACHE_SIZE as ANGLE_CACHE_SIZE,
+        fixed_distance_fixed_angle_distance_cache::ANGLE_DISTANCE_CACHE_SIZE,
+    },
+    refinement::Refinement,
+};
+
+// Spacing of the default direction cache z axis.
+const LINEAR_SCALE: f64 = 5.;
+const POW_F: f64 = 32.0;
+
+// How the web shaders turn z into a direction texture column, index = max(z^16, z / 20), so the
+// textures are resampled onto this whatever the cache's own z axis.
+pub const SHADER_DIRECTION_Z_CURVE: SamplingCurve = SamplingCurve::LinearPower {
+    linear_scale: 20.,
+    exponent: 1. / 16.,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpecError {
+    // Lookups interpolate between a sample and the next, so every axis needs two.
+    TooFewSamples(usize),
+    BadCurve {
+        curve: SamplingCurve,
+        reason: String,
+    },
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpecError::TooFewSamples(size) => {
+                write!(f, "an axis needs at least 2 samples, got {}", size)
+            }
+            SpecError::BadCurve { curve, reason } => write!(f, "{:?}: {}", curve, reason),
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+// How the samples along one axis of a cache are spread over its bounds. Each curve maps the
+// evenly spaced index fraction t in [0, 1] to a position x in [0, 1] within the bounds, and back.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum SamplingCurve {
+    Linear,
+    // x = t^exponent; exponents below 1 put more samples near 1.
+    Power { exponent: f64 },
+    // Evenly spaced in log(1 + scale * x), so larger scales put more samples near 0.
+    Log { scale: f64 },
+    // Linear between (t, x) knots, which must increase in both and run from t = 0 to t = 1.
+    Piecewise { knots: Vec<(f64, f64)> },
+    // x = min(linear_scale * t, t^exponent): linear near 0, then `Power`. This is the z spacing
+    // direction caches have always used, which packs samples near the photon sphere.
+    LinearPower { linear_scale: f64, exponent: f64 },
+}
+
+impl SamplingCurve {
+    // Whether the curve maps [0, 1] onto [0, 1] monotonically, which lookups rely on.
+    pub fn validate(&self) -> Result<(), SpecError> {
+        let bad = |reason: &str| {
+            Err(SpecError::BadCurve {
+                curve: self.clone(),
+                reason: reason.to_string(),
+            })
+        };
+        let positive = |value: f64| value.is_finite() && value > 0.;
+        match self {
+            SamplingCurve::Linear => Ok(()),
+            SamplingCurve::Power { exponent } if !positive(*exponent) => {
+                bad("the exponent must be positive")
+            }
+            SamplingCurve::Log { scale } if !positive(*scale) => bad("the scale must be positive"),
+            SamplingCurve::LinearPower {
+                linear_scale,
+                exponent,
+            } if !positive(*linear_scale) || !positive(*exponent) => {
+                bad("the scale and exponent must be positive")
+            }
+            SamplingCurve::Piecewise { knots } => {
+                if knots.len() < 2 {
+                    return bad("needs at least 2 knots");
+                }
+                if knots[0].0 != 0. || knots[knots.len() - 1].0 != 1. {
+                    return bad("the knots must run from t = 0 to t = 1");
+                }
+                if knots.iter().any(|knot| !(0. ..=1.).contains(&knot.1)) {
+                    return bad("the knots must lie in [0, 1]");
+                }
+                if knots
+                    .windows(2)
+                    .any(|pair| pair[0].0 >= pair[1].0 || pair[0].1 >= pair[1].1)
+                {
+                    return bad("the knots must increase");
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn index_01_to_float_01(&self, t: f64) -> f64 {
+        let t = t.clamp(0., 1.);
+        let x = match self {
+            SamplingCurve::Linear => t,
+            SamplingCurve::Power { exponent } => t.powf(*exponent),
+            SamplingCurve::Log { scale } => ((1. + scale).powf(t) - 1.) / scale,
+            SamplingCurve::Piecewise { knots } => interpolate_knots(knots, t, false),
+            SamplingCurve::LinearPower {
+                linear_scale,
+                exponent,
+            } => f64::min(
+                (linear_scale * t).clamp(0., 1.),
+                t.powf(*exponent).clamp(0., 1.),
+            ),
+        };
+        x.clamp(0., 1.)
+    }
+
+    // Linear axes extrapolate past their bounds, like the caches always have; the rest clamp.
+    pub fn float_01_to_index_01(&self, float_01: f64) -> f64 {
+        let x = float_01.clamp(0., 1.);
+        let t = match self {
+            SamplingCurve::Linear => return float_01,
+            SamplingCurve::Power { exponent } => x.powf(1. / exponent),
+            SamplingCurve::Log { scale } => (1. + scale * x).ln() / (1. + scale).ln(),
+            SamplingCurve::Piecewise { knots } => interpolate_knots(knots, x, true),
+            SamplingCurve::LinearPower {
+                linear_scale,
+                exponent,
+            } => f64::max(
+                (x / linear_scale).clamp(0., 1.),
+                x.powf(1. / exponent).clamp(0., 1.),
+            ),
+        };
+        t.clamp(0., 1.)
+    }
+
+    // Position of the i-th of `size` samples, in [0, 1].
+    pub fn index_to_float_01(&self, index: usize, size: usize) -> f64 {
+        self.index_01_to_float_01(index as f64 / (size - 1) as f64)
+    }
+
+    // The sample at or before float_01, and how far float_01 is towards the next one.
+    pub fn float_01_to_left_index(&self, float_01: f64, size: usize) -> (usize, f64) {
+        let float_index = (size - 1) as f64 * self.float_01_to_index_01(float_01);
+        let index = (float_index as usize).clamp(0, size - 2);
+        let t = float_index - index as f64;
+        (index, t)
+    }
+}
+
+// Maps t to x through the knots, or x back to t if `inverse` is set.
+fn interpolate_knots(knots: &[(f64, f64)], input: f64, inverse: bool) -> f64 {
+    let key = |knot: (f64, f64)| match inverse {
+        true => (knot.1, knot.0),
+        false => knot,
+    };
+    if knots.len() < 2 {
+        return input;
+    }
+    // Refined axes have hundreds of knots, so find the segment by bisection.
+    let right = knots
+        .partition_point(|knot| key(*knot).0 < input)
+        .clamp(1, knots.len() - 1);
+    let (left, right) = (key(knots[right - 1]), key(knots[right]));
+    if right.0 == left.0 {
+        return right.1;
+    }
+    let t = (input - left.0) / (right.0 - left.0);
+    left.1 + t * (right.1 - left.1)
+}
+
+// How lookups blend the samples either side. Interpolation is in index space, so it follows the
+// axis' sampling curve. The cubics are far more accurate wherever the samples resolve the curve,
+// at no cost in memory; near the photon sphere, where they don't, nothing interpolates well.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // Cubic through the two samples on either side. Smooth, but can overshoot sharp bends.
+    CatmullRom,
+    // Cubic Hermite with tangents limited so it never overshoots monotone samples.
+    MonotoneHermite,
+}
+
+impl Interpolation {
+    // Between samples index and index + 1 of the `len` samples `value` reads, t of the way along.
+    // Cubics extrapolate a sample past either end, so the ends stay cubic.
+    pub fn interpolate(
+        &self,
+        value: impl Fn(usize) -> f64,
+        len: usize,
+        index: usize,
+        t: f64,
+    ) -> f64 {
+        self.try_interpolate(|i| Some(value(i)), len, index, t)
+            .unwrap()
+    }
+
+    // As `interpolate`, but None if any sample it reads is.
+    pub fn try_interpolate(
+        &self,
+        value: impl Fn(usize) -> Option<f64>,
+        len: usize,
+        index: usize,
+        t: f64,
+    ) -> Option<f64> {
+        let (left, right) = (value(index)?, value(index + 1)?);
+        if *self == Interpolation::Linear || len < 3 {
+            return Some(right * t + (1. - t) * left);
+        }
+        let before = match index {
+            0 => 2. * left - right,
+            _ => value(index - 1)?,
+        };
+        let after = match index + 2 < len {
+            true => value(index + 2)?,
+            false => 2. * right - left,
+        };
+        let (left_tangent, right_tangent) = match self {
+            Interpolation::CatmullRom => (0.5 * (right - before), 0.5 * (after - left)),
+            _ => (
+                monotone_tangent(left - before, right - left),
+                monotone_tangent(right - left, after - right),
+            ),
+        };
+        let (t2, t3) = (t * t, t * t * t);
+        Some(
+            (2. * t3 - 3. * t2 + 1.) * left
+                + (t3 - 2. * t2 + t) * left_tangent
+                + (-2. * t3 + 3. * t2) * right
+                + (t3 - t2) * right_tangent,
+        )
+    }
+}
+
+// Harmonic mean of the neighbouring slopes, or flat at a local extremum. Both tangents of a segment
+// are then at most twice its slope, which keeps the cubic monotone.
+fn monotone_tangent(before: f64, after: f64) -> f64 {
+    if before * after <= 0. {
+        return 0.;
+    }
+    2. / (1. / before + 1. / after)
+}
+
+// Resolution, sample spacing and interpolation of one cache axis. Axes are validated when they're
+// built or deserialized.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "UncheckedAxisSpec")]
+pub struct AxisSpec {
+    pub size: usize,
+    pub curve: SamplingCurve,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+#[derive(Deserialize)]
+struct UncheckedAxisSpec {
+    size: usize,
+    curve: SamplingCurve,
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+impl TryFrom<UncheckedAxisSpec> for AxisSpec {
+    type Error = SpecError;
+
+    fn try_from(axis: UncheckedAxisSpec) -> Result<Self, SpecError> {
+        Ok(AxisSpec {
+            interpolation: axis.interpolation,
+            ..AxisSpec::new(axis.size, axis.curve)?
+        })
+    }
+}
+
+impl AxisSpec {
+    pub fn new(size: usize, curve: SamplingCurve) -> Result<Self, SpecError> {
+        if size < 2 {
+            return Err(SpecError::TooFewSamples(size));
+        }
+        curve.validate()?;
+        Ok(AxisSpec {
+            size,
+            curve,
+            interpolation: Interpolation::Linear,
+        })
+    }
+
+    pub fn linear(size: usize) -> Result<Self, SpecError> {
+        AxisSpec::new(size, SamplingCurve::Linear)
+    }
+
+    // An axis with a sample at each of the (increasing) positions, in [0, 1].
+    pub fn from_positions(positions: &[f64]) -> Result<Self, SpecError> {
+        let last = positions.len().saturating_sub(1).max(1) as f64;
+        AxisSpec::new(
+            positions.len(),
+            SamplingCurve::Piecewise {
+                knots: positions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| (i as f64 / last, *x))
+                    .collect(),
+            },
+        )
+    }
+
+    pub fn index_to_float_01(&self, index: usize) -> f64 {
+        self.curve.index_to_float_01(index, self.size)
+    }
+
+    pub fn index_to_value(&self, index: usize, bounds: (f64, f64)) -> f64 {
+        (bounds.1 - bounds.0) * self.index_to_float_01(index) + bounds.0
+    }
+
+    pub fn float_01_to_left_index(&self, float_01: f64) -> (usize, f64) {
+        self.curve.float_01_to_left_index(float_01, self.size)
+    }
+
+    // Interpolates the axis' samples at float_01. `value` reads the sample at an index below
+    // `size`.
+    pub fn interpolate(&self, value: impl Fn(usize) -> f64, float_01: f64) -> f64 {
+        let (index, t) = self.float_01_to_left_index(float_01);
+        self.interpolation.interpolate(value, self.size, index, t)
+    }
+
+    pub fn try_interpolate(
+        &self,
+        value: impl Fn(usize) -> Option<f64>,
+        float_01: f64,
+    ) -> Option<f64> {
+        let (index, t) = self.float_01_to_left_index(float_01);
+        self.interpolation
+            .try_interpolate(value, self.size, index, t)
+    }
+}
+
+// Axes of a `DirectionCache`: camera distance, then z within each distance's bounds.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DirectionCacheSpec {
+    pub distance: AxisSpec,
+    pub z: AxisSpec,
+    // Refines each distance's z samples, starting from `z`.
+    #[serde(default)]
+    pub z_refinement: Option<Refinement>,
+}
+
+// Axes of a `DistanceCache`: camera distance, then the angle around the black hole, then z within
+// each angle's bounds.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DistanceCacheSpec {
+    pub distance: AxisSpec,
+    pub angle: AxisSpec,
+    pub z: AxisSpec,
+    // Refines each angle's z samples, starting from `z`.
+    #[serde(default)]
+    pub z_refinement: Option<Refinement>,
+}
+
+impl DirectionCacheSpec {
+    pub fn cache_size(&self) -> (usize, usize) {
+        (self.distance.size, self.z.size)
+    }
+
+    pub fn default_z() -> AxisSpec {
+        AxisSpec::new(
+            DISTANCE_CACHE_SIZE,
+            SamplingCurve::LinearPower {
+                linear_scale: LINEAR_SCALE,
+                exponent: 1. / POW_F,
+            },
+        )
+        .unwrap()
+    }
+}
+
+// The resolution and spacing caches had before they took a spec, which caches serialized
+// without one are read with.
+impl Default for DirectionCacheSpec {
+    fn default() -> Self {
+        DirectionCacheSpec {
+            distance: AxisSpec::linear(DIRECTION_CACHE_SIZE).unwrap(),
+            z: DirectionCacheSpec::default_z(),
+            z_refinement: None,
+        }
+    }
+}
+
+impl DistanceCacheSpec {
+    pub fn cache_size(&self) -> (usize, usize, usize) {
+        (self.distance.size, self.angle.size, self.z.size)
+    }
+
+    pub fn default_angle() -> AxisSpec {
+        AxisSpec::linear(ANGLE_CACHE_SIZE).unwrap()
+    }
+
+    pub fn default_z() -> AxisSpec {
+        AxisSpec::linear(ANGLE_DISTANCE_CACHE_SIZE).unwrap()
+    }
+}
+
+impl Default for DistanceCacheSpec {
+    fn default() -> Self {
+        DistanceCacheSpec {
+            distance: AxisSpec::linear(ALL_DISTANCE_CACHE_SIZE).unwrap(),
+            angle: DistanceCacheSpec::default_angle(),
+            z: DistanceCacheSpec::default_z(),
+            z_refinement: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AxisSpec, Interpolation, SamplingCurve, SpecError};
+
+    #[test]
+    fn curves_invert() {
+        let curves = [
+            SamplingCurve::Linear,
+            SamplingCurve::Power { exponent: 3. },
+            SamplingCurve::Power { exponent: 1. / 32. },
+            SamplingCurve::Log { scale: 100. },
+            SamplingCurve::Piecewise {
+                knots: vec![(0., 0.), (0.5, 0.1), (0.9, 0.8), (1., 1.)],
+            },
+            SamplingCurve::LinearPower {
+                linear_scale: 5.,
+                exponent: 1. / 32.,
+            },
+        ];
+        for curve in curves {
+            let axis = AxisSpec::new(65, curve.clone()).unwrap();
+            let mut previous = -1.;
+            for i in 0..axis.size {
+                let float_01 = axis.index_to_float_01(i);
+                assert!(float_01 > previous, "{:?} isn't increasing at {}", curve, i);
+                previous = float_01;
+
+                // Lookups land back on the sample.
+                let (index, t) = axis.float_01_to_left_index(float_01);
+                let float_index = index as f64 + t;
+                assert!(
+                    (float_index - i as f64).abs() < 1e-6,
+                    "{:?}, i: {}, float_index: {}",
+                    curve,
+                    i,
+                    float_index
+                );
+            }
+            assert_eq!(axis.index_to_float_01(0), 0.);
+            assert_eq!(axis.index_to_float_01(axis.size - 1), 1.);
+        }
+    }
+
+    #[test]
+    fn cubics_fit_smooth_samples() {
+        let quadratic = |i: usize| {
+            let x = i as f64;
+            0.5 * x * x - 3. * x + 2.
+        };
+        for interpolation in [Interpolation::CatmullRom, Interpolation::MonotoneHermite] {
+            for index in 0..9 {
+                let x = index as f64 + 0.5;
+                let value = interpolation.interpolate(quadratic, 10, index, 0.5);
+                let expected = 0.5 * x * x - 3. * x + 2.;
+                // Catmull-Rom's central differences are exact for quadratics away from the ends.
+                if interpolation == Interpolation::CatmullRom && index > 0 && index < 8 {
+                    assert!((value - expected).abs() < 1e-12, "index: {}", index);
+                }
+                assert!(
+                    (value - expected).abs() < 0.2,
+                    "{:?}, index: {}",
+                    interpolation,
+                    index
+                );
+            }
+            // Samples are reproduced exactly.
+            assert_eq!(
+                interpolation.interpolate(quadratic, 10, 3, 0.),
+                quadratic(3)
+            );
+            assert_eq!(
+                interpolation.interpolate(quadratic, 10, 3, 1.),
+                quadratic(4)
+            );
+        }
+    }
+
+    #[test]
+    fn monotone_hermite_doesnt_overshoot() {
+        let step = |i: usize| if i < 5 { 0. } else { 1. };
+        let mut previous = 0.;
+        for index in 0..9 {
+            for k in 0..=10 {
+                let t = k as f64 / 10.;
+                let value = Interpolation::MonotoneHermite.interpolate(step, 10, index, t);
+                assert!((0. ..=1.).contains(&value));
+                assert!(value >= previous);
+                previous = value;
+            }
+        }
+        let overshoot = Interpolation::CatmullRom.interpolate(step, 10, 5, 0.5);
+        assert!(overshoot > 1.);
+    }
+
+    #[test]
+    fn rejects_bad_axes() {
+        assert!(matches!(
+            AxisSpec::new(1, SamplingCurve::Linear),
+            Err(SpecError::TooFewSamples(1))
+        ));
+        assert!(AxisSpec::from_positions(&[0.5]).is_err());
+        let bad_curves = [
+            SamplingCurve::Log { scale: 0. },
+            SamplingCurve::Log { scale: -1. },
+            SamplingCurve::Power { exponent: f64::NAN },
+            SamplingCurve::Piecewise {
+                knots: vec![(0., 0.), (0.6, 0.5), (0.4, 0.7), (1., 1.)],
+            },
+            SamplingCurve::Piecewise {
+                knots: vec![(0., 0.), (0.5, 0.5), (0.9, 1.)],
+            },
+        ];
+        for curve in bad_curves {
+            assert!(AxisSpec::new(8, curve.clone()).is_err(), "{:?}", curve);
+        }
+
+        // Specs read from a file are checked the same way.
+        let json = r#"{"size": 8, "curve": {"Log": {"scale": 0.0}}}"#;
+        assert!(serde_json::from_str::<AxisSpec>(json).is_err());
+        let json = r#"{"size": 8, "curve": {"Log": {"scale": 10.0}}}"#;
+        let axis: AxisSpec = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            axis,
+            AxisSpec::new(8, SamplingCurve::Log { scale: 10. }).unwrap()
+        );
+    }
+}
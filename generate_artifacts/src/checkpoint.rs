@@ -0,0 +1,240 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{artifact_container::ArtifactContainer, path_integration2::batch::parallel_map};
+
+// Each slice is stored in an artifact container, so a slice cut off mid-write fails its checksum
+// and is generated again.
+const SLICE_KIND: &str = "cache_slice";
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io { path: PathBuf, error: io::Error },
+    // A slice left by a run with different parameters.
+    Mismatch { path: PathBuf, reason: String },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            CheckpointError::Mismatch { path, reason } => write!(
+                f,
+                "{} is from another run ({}); delete the checkpoints to start over",
+                path.display(),
+                reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+fn slice_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("slice_{:04}.bin", index))
+}
+
+// Reads the slice checkpointed at `path`, or None if there isn't a complete one.
+fn read_slice<P, R>(
+    path: &Path,
+    distance: f64,
+    parameters: &P,
+) -> Result<Option<R>, CheckpointError>
+where
+    P: DeserializeOwned + PartialEq,
+    R: DeserializeOwned,
+{
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            let path = path.to_path_buf();
+            return Err(CheckpointError::Io { path, error });
+        }
+    };
+    let container = match ArtifactContainer::from_bytes(&bytes) {
+        Ok(container) if container.kind == SLICE_KIND => container,
+        Ok(container) => {
+            println!(
+                "Discarding {}: a {} artifact",
+                path.display(),
+                container.kind
+            );
+            return Ok(None);
+        }
+        Err(error) => {
+            println!("Discarding {}: {}", path.display(), error);
+            return Ok(None);
+        }
+    };
+
+    let mismatch = |reason: String| CheckpointError::Mismatch {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let stored_distance = container
+        .parameter("distance")
+        .map_err(|error| mismatch(error.to_string()))?;
+    if stored_distance != distance {
+        return Err(mismatch(format!(
+            "distance {}, expected {}",
+            stored_distance, distance
+        )));
+    }
+    let stored_parameters: P = container
+        .value("parameters")
+        .map_err(|error| mismatch(error.to_string()))?;
+    if stored_parameters != *parameters {
+        return Err(mismatch("different cache parameters".to_string()));
+    }
+    container
+        .value("slice")
+        .map(Some)
+        .map_err(|error| mismatch(error.to_string()))
+}
+
+fn write_slice<P: Serialize, R: Serialize>(
+    path: &Path,
+    distance: f64,
+    parameters: &P,
+    slice: &R,
+) -> Result<(), CheckpointError> {
+    let bytes = ArtifactContainer::new(SLICE_KIND)
+        .with_parameter("distance", distance)
+        .with_value("parameters", parameters)
+        .with_value("slice", slice)
+        .to_bytes();
+    // Written aside and renamed, so the checkpoint is never a partial file.
+    let partial = path.with_extension("partial");
+    fs::write(&partial, bytes)
+        .and_then(|_| fs::rename(&partial, path))
+        .map_err(|error| CheckpointError::Io {
+            path: path.to_path_buf(),
+            error,
+        })
+}
+
+// Generates a cache's slice for each camera distance, like `parallel_map`. With a checkpoint
+// directory, each slice is written there as soon as it's done and slices already there are
+// reused, so an interrupted run picks up where it stopped when called again with the same
+// arguments.
+//
+// `parameters` should hold everything the slices depend on besides the distance. Every reused
+// slice is checked against them and its distance before being assembled, so checkpoints from a
+// different run are an error rather than mixed in.
+pub fn checkpointed_map<P, R, F>(
+    checkpoint_dir: Option<&Path>,
+    parameters: &P,
+    distances: &[f64],
+    generate: F,
+) -> Result<Vec<R>, CheckpointError>
+where
+    P: Serialize + DeserializeOwned + PartialEq + Sync,
+    R: Serialize + DeserializeOwned + Send,
+    F: Fn(f64) -> R + Sync,
+{
+    let dir = match checkpoint_dir {
+        Some(dir) => dir,
+        None => return Ok(parallel_map(distances, |&distance| generate(distance))),
+    };
+    fs::create_dir_all(dir).map_err(|error| CheckpointError::Io {
+        path: dir.to_path_buf(),
+        error,
+    })?;
+
+    let mut slices = Vec::with_capacity(distances.len());
+    for (index, &distance) in distances.iter().enumerate() {
+        slices.push(read_slice(&slice_path(dir, index), distance, parameters)?);
+    }
+    let missing: Vec<usize> = (0..distances.len())
+        .filter(|&index| slices[index].is_none())
+        .collect();
+    if missing.len() < distances.len() {
+        println!(
+            "Resuming from {} of {} slices in {}",
+            distances.len() - missing.len(),
+            distances.len(),
+            dir.display()
+        );
+    }
+
+    let generated = parallel_map(&missing, |&index| {
+        let slice = generate(distances[index]);
+        write_slice(
+            &slice_path(dir, index),
+            distances[index],
+            parameters,
+            &slice,
+        )?;
+        Ok(slice)
+    });
+    for (index, slice) in missing.into_iter().zip(generated) {
+        slices[index] = Some(slice?);
+    }
+    Ok(slices.into_iter().map(Option::unwrap).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::{checkpointed_map, slice_path, CheckpointError};
+
+    fn checkpoint_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("checkpoint_{}_{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn resumes_from_checkpoints() {
+        let dir = checkpoint_dir("resumes");
+        let distances = [5., 10., 20.];
+        let generated = AtomicUsize::new(0);
+        let generate = |distance: f64| {
+            generated.fetch_add(1, Ordering::Relaxed);
+            vec![distance; 2]
+        };
+        let expected: Vec<Vec<f64>> = distances.iter().map(|&d| vec![d; 2]).collect();
+
+        let slices = checkpointed_map(Some(&dir), &(1.5, 7), &distances, generate).unwrap();
+        assert_eq!(slices, expected);
+        assert_eq!(generated.load(Ordering::Relaxed), 3);
+
+        // As if interrupted before the last slice was written, and partway through the second.
+        fs::remove_file(slice_path(&dir, 2)).unwrap();
+        let second = fs::read(slice_path(&dir, 1)).unwrap();
+        fs::write(slice_path(&dir, 1), &second[..second.len() / 2]).unwrap();
+        let slices = checkpointed_map(Some(&dir), &(1.5, 7), &distances, generate).unwrap();
+        assert_eq!(slices, expected);
+        assert_eq!(generated.load(Ordering::Relaxed), 5);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_slices_from_other_runs() {
+        let dir = checkpoint_dir("rejects");
+        let distances = [5., 10.];
+        checkpointed_map(Some(&dir), &(1.5, 7), &distances, |d| d).unwrap();
+
+        let other_parameters = checkpointed_map(Some(&dir), &(2.0, 7), &distances, |d| d);
+        assert!(matches!(
+            other_parameters,
+            Err(CheckpointError::Mismatch { .. })
+        ));
+        let other_distances = checkpointed_map(Some(&dir), &(1.5, 7), &[5., 11.], |d| d);
+        assert!(matches!(
+            other_distances,
+            Err(CheckpointError::Mismatch { .. })
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
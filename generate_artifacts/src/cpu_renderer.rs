@@ -0,0 +1,191 @@
+use std::f64::consts::{PI, TAU};
+
+use glam::DVec3;
+use image::{Rgb, RgbImage};
+
+use crate::path_integration2::{
+    path::cast_multi_ray_response, ray_cast_config::RayCastConfig, structs::multi_field::MultiField,
+};
+
+// Renders a `MultiField` by tracing every pixel, without any caches. It's far too slow for the
+// web renderers, but works for any arrangement of masses, so it doubles as ground truth.
+
+pub struct CpuCamera {
+    pub position: DVec3,
+    pub look_at: DVec3,
+    // Rough up direction; only the part perpendicular to the view direction is used.
+    pub up: DVec3,
+    pub fov_degrees: f64,
+    pub resolution: (u32, u32),
+}
+
+impl CpuCamera {
+    // Direction through the centre of pixel (x, y), with y = 0 at the top of the image.
+    pub fn ray_dir(&self, x: u32, y: u32) -> DVec3 {
+        let forward = (self.look_at - self.position).normalize();
+        let right = forward.cross(self.up).normalize();
+        let up = right.cross(forward);
+        let half_width = (0.5 * self.fov_degrees.to_radians()).tan();
+        let aspect = self.resolution.1 as f64 / self.resolution.0 as f64;
+        let u = 2. * (x as f64 + 0.5) / self.resolution.0 as f64 - 1.;
+        let v = 1. - 2. * (y as f64 + 0.5) / self.resolution.1 as f64;
+        (forward + half_width * (u * right + aspect * v * up)).normalize()
+    }
+}
+
+// Sky with a line every 15 degrees of latitude and longitude, so the lensing is visible without a
+// backdrop image.
+pub fn grid_sky(dir: DVec3) -> Rgb<u8> {
+    let spacing = TAU / 24.;
+    let latitude = dir.y.clamp(-1., 1.).asin() + 0.5 * PI;
+    let longitude = f64::atan2(dir.z, dir.x) + PI;
+    let on_line = |angle: f64| {
+        let offset = (angle / spacing).fract();
+        !(0.05..=0.95).contains(&offset)
+    };
+    match on_line(latitude) || on_line(longitude) {
+        true => Rgb([255, 255, 255]),
+        false => Rgb([
+            (64. + 128. * longitude / TAU) as u8,
+            32,
+            (64. + 128. * latitude / PI) as u8,
+        ]),
+    }
+}
+
+// Looks up an equirectangular backdrop, like the galaxy texture the web renderers use.
+pub fn equirectangular_sky(backdrop: &RgbImage) -> impl Fn(DVec3) -> Rgb<u8> + '_ {
+    move |dir: DVec3| {
+        let theta = dir.y.clamp(-1., 1.).asin() / PI + 0.5;
+        let phi = (f64::atan2(dir.z, dir.x) / TAU + 1.) % 1.;
+        let x = (phi * (backdrop.width() - 1) as f64) as u32;
+        let y = ((1. - theta) * (backdrop.height() - 1) as f64) as u32;
+        *backdrop.get_pixel(x, y)
+    }
+}
+
+// Rays that fall into any of the masses are black.
+pub fn render_multi_field(
+    field: &MultiField,
+    camera: &CpuCamera,
+    config: RayCastConfig,
+    sky: &dyn Fn(DVec3) -> Rgb<u8>,
+) -> RgbImage {
+    let (width, height) = camera.resolution;
+    RgbImage::from_fn(width, height, |x, y| {
+        let response =
+            cast_multi_ray_response(camera.position, camera.ray_dir(x, y), field, config);
+        match response.final_dir {
+            Some(final_dir) => sky(final_dir),
+            None => Rgb([0, 0, 0]),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::DVec3;
+    use image::Rgb;
+
+    use crate::path_integration2::{
+        path::{cast_multi_ray_response, cast_ray_steps_response, RAY_START_DIR},
+        ray_cast_config::RayCastConfig,
+        structs::{
+            adaptive_step::Tolerance,
+            field::FieldModel,
+            integrator::IntegratorKind,
+            multi_field::{MultiField, PointMass},
+        },
+    };
+
+    use super::{grid_sky, render_multi_field, CpuCamera};
+
+    #[test]
+    fn single_mass_matches_schwarzschild() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        // Moving the mass and the camera together shouldn't change anything.
+        let offset = DVec3::new(3., -2., 7.);
+        let field = MultiField::new(vec![PointMass {
+            position: offset,
+            radius: black_hole_radius,
+        }]);
+        for integrator in [
+            IntegratorKind::Rk4,
+            IntegratorKind::Rk45(Tolerance::default()),
+        ] {
+            let config = RayCastConfig::with_integrator(integrator);
+            for z in [0.2, 0.6, 0.9, 0.97] {
+                let start_dir = DVec3::new((1.0_f64 - z * z).sqrt(), 0., z);
+                let expected = cast_ray_steps_response(
+                    z,
+                    camera_distance,
+                    black_hole_radius,
+                    FieldModel::Schwarzschild,
+                    config,
+                )
+                .final_dir;
+                let actual = cast_multi_ray_response(
+                    offset + camera_distance * RAY_START_DIR,
+                    start_dir,
+                    &field,
+                    config,
+                )
+                .final_dir;
+                match (expected, actual) {
+                    (Some(expected), Some(actual)) => assert!(
+                        (expected - actual).length() < 0.005,
+                        "z: {}\nexpected: {}\nactual: {}",
+                        z,
+                        expected,
+                        actual
+                    ),
+                    (None, None) => {}
+                    _ => panic!("z: {}\nexpected: {:?}\nactual: {:?}", z, expected, actual),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn binary_render_is_symmetric() {
+        let field = MultiField::new(vec![
+            PointMass {
+                position: DVec3::new(-3., 0., 0.),
+                radius: 1.,
+            },
+            PointMass {
+                position: DVec3::new(3., 0., 0.),
+                radius: 1.,
+            },
+        ]);
+        let camera = CpuCamera {
+            position: DVec3::new(0., 0., -30.),
+            look_at: DVec3::ZERO,
+            up: DVec3::Y,
+            fov_degrees: 40.,
+            resolution: (24, 16),
+        };
+        let image = render_multi_field(&field, &camera, RayCastConfig::default(), &grid_sky);
+
+        // Both holes cast a shadow, and the view between them is open sky.
+        let black = Rgb([0, 0, 0]);
+        let shadow_x = |mass_x: f64| {
+            let half_width = (0.5 * camera.fov_degrees.to_radians()).tan();
+            let u = mass_x / 30. / half_width;
+            (0.5 * (u + 1.) * camera.resolution.0 as f64) as u32
+        };
+        assert_eq!(*image.get_pixel(shadow_x(-3.), 8), black);
+        assert_eq!(*image.get_pixel(shadow_x(3.), 8), black);
+        assert_ne!(*image.get_pixel(12, 8), black);
+
+        // The scene is mirror symmetric, so the shadows are too.
+        for y in 0..16 {
+            for x in 0..24 {
+                let is_shadow = *image.get_pixel(x, y) == black;
+                let mirrored = *image.get_pixel(23 - x, y) == black;
+                assert_eq!(is_shadow, mirrored, "x: {}, y: {}", x, y);
+            }
+        }
+    }
+}
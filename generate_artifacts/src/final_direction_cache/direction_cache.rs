@@ -1,77 +1,166 @@
-
+use std::path::Path;
 
 use glam::DVec3;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    cache_spec::{DirectionCacheSpec, SamplingCurve},
+    checkpoint::{checkpointed_map, CheckpointError},
+    path_integration2::{ray_cast_config::RayCastConfig, structs::field::FieldModel},
+};
+
 pub const DIRECTION_CACHE_SIZE: usize = 1 << 5;
 
-use super::fixed_distance_direction_cache::{FixedDistanceDirectionCache, DISTANCE_CACHE_SIZE};
+use super::fixed_distance_direction_cache::{final_dir_from_angle, FixedDistanceDirectionCache};
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct DirectionCache {
     pub cache_size: (usize, usize),
     pub distance_bounds: (f64, f64),
     pub black_hole_radius: f64,
+    #[serde(default)]
+    pub model: FieldModel,
+    #[serde(default)]
+    pub config: RayCastConfig,
+    #[serde(default)]
+    pub spec: DirectionCacheSpec,
     pub distance_angle_to_z_to_distance: Vec<FixedDistanceDirectionCache>,
 }
 
-fn d_01_to_left_index(d_01: f64, vec_len: usize) -> (usize, f64) {
-    let float_index = (vec_len - 1) as f64 * d_01;
-    let index = (float_index as usize).clamp(0, vec_len - 2);
-    let t = float_index - index as f64;
-    (index, t)
-}
-fn index_to_float_01(index: usize, vec_len: usize) -> f64 {
-    let float_01 = (index as f64) / (vec_len - 1) as f64;
-    return float_01.clamp(0., 1.);
+// The final angles resampled for the renderers, which look them up with evenly spaced camera
+// distances and their own z spacing, whatever the cache's axes.
+#[derive(Debug, PartialEq)]
+pub struct DirectionTexture {
+    // (z, camera distance) sample counts, z varying fastest in `final_angles`.
+    pub dimensions: (usize, usize),
+    // Per camera distance.
+    pub z_bounds: Vec<(f64, f64)>,
+    pub final_angles: Vec<f64>,
 }
 
 impl DirectionCache {
     pub fn compute_new(
-        _cache_size: (usize, usize),
+        spec: DirectionCacheSpec,
         distance_bounds: (f64, f64),
         black_hole_radius: f64,
+        model: FieldModel,
+        config: RayCastConfig,
     ) -> Self {
-        let mut distance_angle_to_z_to_distance = Vec::new();
-        for i in 0..DIRECTION_CACHE_SIZE {
-            let dist = (distance_bounds.1 - distance_bounds.0) as f64 * i as f64
-                / (DIRECTION_CACHE_SIZE - 1) as f64
-                + distance_bounds.0;
-            println!("Generating dist: {}", dist);
-            let fixed_distance_cache =
-                FixedDistanceDirectionCache::compute_new(dist, black_hole_radius);
-            distance_angle_to_z_to_distance.push(fixed_distance_cache);
-        }
-        DirectionCache {
-            cache_size: (DIRECTION_CACHE_SIZE, DISTANCE_CACHE_SIZE),
+        // Can't fail without checkpoints to read or write.
+        Self::compute(
+            spec,
+            distance_bounds,
+            black_hole_radius,
+            model,
+            config,
+            None,
+        )
+        .unwrap()
+    }
+
+    // Like `compute_new`, but each distance's slice is checkpointed in `checkpoint_dir`, so calling
+    // this again with the same arguments after an interruption resumes from the finished slices.
+    pub fn compute_checkpointed(
+        spec: DirectionCacheSpec,
+        distance_bounds: (f64, f64),
+        black_hole_radius: f64,
+        model: FieldModel,
+        config: RayCastConfig,
+        checkpoint_dir: &Path,
+    ) -> Result<Self, CheckpointError> {
+        Self::compute(
+            spec,
             distance_bounds,
             black_hole_radius,
+            model,
+            config,
+            Some(checkpoint_dir),
+        )
+    }
+
+    fn compute(
+        spec: DirectionCacheSpec,
+        distance_bounds: (f64, f64),
+        black_hole_radius: f64,
+        model: FieldModel,
+        config: RayCastConfig,
+        checkpoint_dir: Option<&Path>,
+    ) -> Result<Self, CheckpointError> {
+        let dists: Vec<f64> = (0..spec.distance.size)
+            .map(|i| spec.distance.index_to_value(i, distance_bounds))
+            .collect();
+        let parameters = (
+            spec.clone(),
+            distance_bounds,
+            black_hole_radius,
+            model,
+            config,
+        );
+        let distance_angle_to_z_to_distance =
+            checkpointed_map(checkpoint_dir, &parameters, &dists, |dist| {
+                println!("Generating dist: {}", dist);
+                FixedDistanceDirectionCache::compute_new(
+                    spec.z.clone(),
+                    spec.z_refinement,
+                    dist,
+                    black_hole_radius,
+                    model,
+                    config,
+                )
+            })?;
+        Ok(DirectionCache {
+            cache_size: spec.cache_size(),
+            distance_bounds,
+            black_hole_radius,
+            model,
+            config,
+            spec,
             distance_angle_to_z_to_distance,
-        }
+        })
     }
 
     pub fn get_z_bounds(&self, d_01: f64) -> (f64, f64) {
-        let (index, t) = d_01_to_left_index(d_01, self.distance_angle_to_z_to_distance.len());
-        let left = (
-            self.distance_angle_to_z_to_distance[index].min_z,
-            self.distance_angle_to_z_to_distance[index].max_z,
-        );
-        let right = (
-            self.distance_angle_to_z_to_distance[index + 1].min_z,
-            self.distance_angle_to_z_to_distance[index + 1].max_z,
-        );
+        let caches = &self.distance_angle_to_z_to_distance;
+        let distance = &self.spec.distance;
         (
-            right.0 * t + (1. - t) * left.0,
-            right.1 * t + (1. - t) * left.1,
+            distance.interpolate(|i| caches[i].min_z, d_01),
+            distance.interpolate(|i| caches[i].max_z, d_01),
         )
     }
 
-    pub fn get_final_dir(&self, d_01: f64, z: f64) -> DVec3 {
-        let (index, t) = d_01_to_left_index(d_01, self.distance_angle_to_z_to_distance.len());
+    pub fn get_final_angle(&self, d_01: f64, z: f64) -> f64 {
+        let caches = &self.distance_angle_to_z_to_distance;
         let z_bounds = self.get_z_bounds(d_01);
         let z_01 = ((z - z_bounds.0) / (z_bounds.1 - z_bounds.0)).clamp(0., 1.);
-        let left = self.distance_angle_to_z_to_distance[index].get_final_dir(z_01);
-        let right = self.distance_angle_to_z_to_distance[index + 1].get_final_dir(z_01);
-        t * right + (1. - t) * left
+        self.spec
+            .distance
+            .interpolate(|i| caches[i].get_final_angle(z_01), d_01)
+    }
+
+    pub fn get_final_dir(&self, d_01: f64, z: f64) -> DVec3 {
+        final_dir_from_angle(self.get_final_angle(d_01, z))
+    }
+
+    // Rows have as many samples as the spec's z axis, spaced by `z_curve` between the row's z
+    // bounds; there are as many rows as cached camera distances.
+    pub fn texture(&self, z_curve: &SamplingCurve) -> DirectionTexture {
+        let width = self.spec.z.size;
+        let height = self.distance_angle_to_z_to_distance.len();
+        let mut z_bounds = Vec::new();
+        let mut final_angles = Vec::new();
+        for y in 0..height {
+            let d_01 = SamplingCurve::Linear.index_to_float_01(y, height);
+            let bounds = self.get_z_bounds(d_01);
+            z_bounds.push(bounds);
+            for x in 0..width {
+                let z = (bounds.1 - bounds.0) * z_curve.index_to_float_01(x, width) + bounds.0;
+                final_angles.push(self.get_final_angle(d_01, z));
+            }
+        }
+        DirectionTexture {
+            dimensions: (width, height),
+            z_bounds,
+            final_angles,
+        }
     }
 }
 
@@ -81,20 +170,28 @@ mod tests {
     use test_utils::plot_trajectories;
 
     use crate::{
-        final_direction_cache::{
-            direction_cache::DirectionCache, fixed_distance_direction_cache::DISTANCE_CACHE_SIZE,
+        cache_spec::{AxisSpec, DirectionCacheSpec, SamplingCurve, SHADER_DIRECTION_Z_CURVE},
+        final_direction_cache::direction_cache::DirectionCache,
+        path_integration2::{
+            path::cast_ray_steps_response, ray_cast_config::RayCastConfig,
+            structs::field::FieldModel,
         },
-        path_integration2::path::cast_ray_steps_response,
+        refinement::Refinement,
     };
 
-    use super::DIRECTION_CACHE_SIZE;
-
     #[test]
     fn all_distance_direction_test() {
-        let cache_size = (DIRECTION_CACHE_SIZE, DISTANCE_CACHE_SIZE);
+        let spec = DirectionCacheSpec::default();
+        let cache_size = spec.cache_size();
         let distance = (5.0, 30.);
         let black_hole_radius = 1.5;
-        let cache = DirectionCache::compute_new(cache_size, distance, black_hole_radius);
+        let cache = DirectionCache::compute_new(
+            spec,
+            distance,
+            black_hole_radius,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+        );
 
         let mut lines = Vec::new();
         let mut samples = Vec::new();
@@ -130,7 +227,13 @@ mod tests {
 
                 let dist = (distance.1 - distance.0) * d_01 + distance.0;
                 let approx_final_dir = cache.get_final_dir(*d_01, z);
-                let response = cast_ray_steps_response(z, dist, cache.black_hole_radius);
+                let response = cast_ray_steps_response(
+                    z,
+                    dist,
+                    cache.black_hole_radius,
+                    cache.model,
+                    cache.config,
+                );
 
                 let true_final_dir = response.final_dir;
                 if true_final_dir.is_none() {
@@ -157,10 +260,15 @@ mod tests {
 
     #[test]
     fn serialization() {
-        let cache_size = (DIRECTION_CACHE_SIZE, DISTANCE_CACHE_SIZE);
         let distance = (5.0, 20.);
         let black_hole_radius = 1.5;
-        let cache = DirectionCache::compute_new(cache_size, distance, black_hole_radius);
+        let cache = DirectionCache::compute_new(
+            DirectionCacheSpec::default(),
+            distance,
+            black_hole_radius,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+        );
 
         let serialized = serde_json::to_string(&cache);
 
@@ -174,4 +282,87 @@ mod tests {
         let deserialized = deserialized.unwrap();
         assert_eq!(deserialized, cache);
     }
+
+    #[test]
+    fn honors_spec() {
+        let spec = DirectionCacheSpec {
+            distance: AxisSpec::new(3, SamplingCurve::Log { scale: 4. }).unwrap(),
+            z: AxisSpec::new(64, SamplingCurve::Power { exponent: 0.5 }).unwrap(),
+            z_refinement: None,
+        };
+        let cache = DirectionCache::compute_new(
+            spec.clone(),
+            (5.0, 20.),
+            1.5,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+        );
+        assert_eq!(cache.cache_size, (3, 64));
+        assert_eq!(cache.distance_angle_to_z_to_distance.len(), 3);
+        for fixed in &cache.distance_angle_to_z_to_distance {
+            assert_eq!(fixed.z_to_final_angle.len(), 64);
+            assert_eq!(fixed.z_axis, spec.z);
+        }
+
+        // The middle distance is placed by the log curve, so lookups at it land exactly on it.
+        let d_01 = spec.distance.index_to_float_01(1);
+        let middle = &cache.distance_angle_to_z_to_distance[1];
+        let z_bounds = cache.get_z_bounds(d_01);
+        assert!((z_bounds.0 - middle.min_z).abs() < 1e-9);
+        assert!((z_bounds.1 - middle.max_z).abs() < 1e-9);
+
+        // The spec travels with the cache, so a deserialized cache looks up the same way.
+        let deserialized: DirectionCache =
+            serde_json::from_str(&serde_json::to_string(&cache).unwrap()).unwrap();
+        assert_eq!(deserialized.spec, spec);
+        let z = 0.5 * (middle.min_z + middle.max_z);
+        assert_eq!(
+            deserialized.get_final_dir(d_01, z),
+            cache.get_final_dir(d_01, z)
+        );
+    }
+
+    #[test]
+    fn texture_is_regular_when_refined() {
+        let spec = DirectionCacheSpec {
+            distance: AxisSpec::new(3, SamplingCurve::Log { scale: 4. }).unwrap(),
+            z: AxisSpec::linear(16).unwrap(),
+            z_refinement: Some(Refinement {
+                tolerance: 0.001,
+                max_size: 128,
+            }),
+        };
+        let cache = DirectionCache::compute_new(
+            spec,
+            (5.0, 20.),
+            1.5,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+        );
+        let lens: Vec<usize> = cache
+            .distance_angle_to_z_to_distance
+            .iter()
+            .map(|fixed| fixed.z_to_final_angle.len())
+            .collect();
+        assert!(lens.iter().all(|len| *len > 16), "{:?}", lens);
+
+        let texture = cache.texture(&SHADER_DIRECTION_Z_CURVE);
+        assert_eq!(texture.dimensions, (16, 3));
+        assert_eq!(texture.z_bounds.len(), 3);
+        assert_eq!(texture.final_angles.len(), 16 * 3);
+        // Rows are evenly spaced in camera distance and columns follow the shaders' z spacing.
+        for y in 0..3 {
+            let d_01 = 0.5 * y as f64;
+            let bounds = cache.get_z_bounds(d_01);
+            assert_eq!(texture.z_bounds[y], bounds);
+            for x in 0..16 {
+                let z_01 = SHADER_DIRECTION_Z_CURVE.index_to_float_01(x, 16);
+                let z = (bounds.1 - bounds.0) * z_01 + bounds.0;
+                assert_eq!(
+                    texture.final_angles[16 * y + x],
+                    cache.get_final_angle(d_01, z)
+                );
+            }
+        }
+    }
 }
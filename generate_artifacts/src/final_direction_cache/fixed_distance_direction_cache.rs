@@ -1,45 +1,24 @@
 use std::f64::consts::TAU;
 
+use crate::cache_spec::{AxisSpec, DirectionCacheSpec};
 use crate::path_integration2::{
     path::cast_ray_steps_response,
-    path::find_optimal_z,
-    response::{Response},
+    path::find_optimal_z_by_summary,
+    ray_cast_config::RayCastConfig,
+    ray_summary::RaySummary,
+    response::ToAngle,
+    structs::{
+        drift::{interpolate_rejected, DriftPolicy},
+        field::FieldModel,
+    },
 };
+use crate::refinement::{refine, sample_axis, Refinement};
 use glam::DVec3;
 use serde::{Deserialize, Serialize};
 
 pub const DISTANCE_CACHE_SIZE: usize = 1 << 9;
 const ANGLE_EPSILON: f64 = 0.01 * TAU / 360.;
-const LINEAR_SCALE: f64 = 5.;
 const MAX_ANGLE: f64 = TAU;
-const POW_F: f64 = 32.0;
-
-fn index_to_z_01(i: usize, cache_size: usize) -> f64 {
-    let z_01 = (i as f64) / (cache_size - 1) as f64;
-    let z_01_high = z_01.powf(1. / POW_F).clamp(0., 1.);
-    let z_01_low = (LINEAR_SCALE * z_01).clamp(0., 1.);
-    f64::min(z_01_low, z_01_high)
-}
-
-fn z_01_to_left_index(z_01: f64, cache_size: usize) -> (usize, f64) {
-    let z_01_high = z_01.powf(POW_F).clamp(0., 1.);
-    let z_01_low = (z_01 / LINEAR_SCALE).clamp(0., 1.);
-
-    let z_01 = f64::max(z_01_low, z_01_high) * (cache_size - 1) as f64;
-
-    let index = (z_01 as usize).clamp(0, cache_size - 2);
-    let t = z_01 - index as f64;
-    (index, t)
-}
-
-fn index_to_z(max_z: f64, min_z: f64, i: usize, cache_size: usize) -> f64 {
-    let float_01 = index_to_z_01(i, cache_size);
-    (max_z - min_z) * float_01 + min_z
-}
-fn z_to_left_index(max_z: f64, min_z: f64, z: f64, cache_size: usize) -> (usize, f64) {
-    let z_01 = (z - min_z) / (max_z - min_z);
-    z_01_to_left_index(z_01, cache_size)
-}
 
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct FixedDistanceDirectionCache {
@@ -47,15 +26,37 @@ pub struct FixedDistanceDirectionCache {
     pub min_z: f64,
     pub camera_distance: f64,
     pub black_hole_radius: f64,
-    pub z_to_final_dir: Vec<(f64, (f64, f64))>,
+    #[serde(default)]
+    pub model: FieldModel,
+    #[serde(default)]
+    pub config: RayCastConfig,
+    // Total angle the final direction has turned through, unwrapped so it counts every loop
+    // around the hole. See `final_dir_from_angle`.
+    pub z_to_final_angle: Vec<(f64, f64)>,
+    #[serde(default = "DirectionCacheSpec::default_z")]
+    pub z_axis: AxisSpec,
+    // Samples whose conserved quantities drifted past `config.drift_limit`.
+    #[serde(default)]
+    pub drift_flagged_z: Vec<f64>,
 }
 
-fn find_closest_z(camera_distance: f64, black_hole_radius: f64) -> f64 {
-    let too_close =
-        |r: Response| r.hits_black_hole() || r.get_angle_dist().get_max_angle() > MAX_ANGLE;
-    find_optimal_z(
+// Inverse of `ToAngle::get_angle`: angles are measured from -z towards +x.
+pub fn final_dir_from_angle(angle: f64) -> DVec3 {
+    DVec3::new(angle.sin(), 0.0, -angle.cos())
+}
+
+fn find_closest_z(
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
+) -> f64 {
+    let too_close = |r: &RaySummary| r.hits_black_hole() || r.max_angle > MAX_ANGLE;
+    find_optimal_z_by_summary(
         camera_distance as f32,
         black_hole_radius as f32,
+        model,
+        config,
         (-1., 1.),
         &too_close,
     )
@@ -63,20 +64,25 @@ fn find_closest_z(camera_distance: f64, black_hole_radius: f64) -> f64 {
 }
 
 // use this find z values where we don't have to apply anti-aliasing
-fn find_minimum_pertubation_z(camera_distance: f64, black_hole_radius: f64, max_z: f64) -> f64 {
-    let too_close = |r: Response| {
-        if r.hits_black_hole() {
-            return true;
+fn find_minimum_pertubation_z(
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
+    max_z: f64,
+) -> f64 {
+    // Still in flight there's no final direction, so only the finished ray is judged.
+    let too_close = |r: &RaySummary| match (r.initial_dir, r.final_dir) {
+        (Some(initial_dir), Some(final_dir)) => {
+            initial_dir.dot(final_dir.normalize()).acos().abs() > ANGLE_EPSILON
         }
-
-        let initial_dir = (r.path[1] - r.path[0]).normalize();
-        let final_dir = r.final_dir.unwrap().normalize();
-        let angle = initial_dir.dot(final_dir).acos();
-        angle.abs() > ANGLE_EPSILON
+        _ => r.hits_black_hole(),
     };
-    find_optimal_z(
+    find_optimal_z_by_summary(
         camera_distance as f32,
         black_hole_radius as f32,
+        model,
+        config,
         (-1., max_z),
         &too_close,
     )
@@ -84,22 +90,66 @@ fn find_minimum_pertubation_z(camera_distance: f64, black_hole_radius: f64, max_
 }
 
 impl FixedDistanceDirectionCache {
-    pub fn compute_new(camera_distance: f64, black_hole_radius: f64) -> Self {
-        let cache_size = DISTANCE_CACHE_SIZE;
-        let max_z = find_closest_z(camera_distance, black_hole_radius);
-        let min_z = find_minimum_pertubation_z(camera_distance, black_hole_radius, max_z);
-        let mut z_to_final_dir = Vec::new();
-        z_to_final_dir.push((min_z, ((1. - min_z * min_z).sqrt(), min_z)));
-        for i in 1..cache_size {
-            let z = index_to_z(max_z, min_z, i, cache_size);
-            let final_dir =
-                cast_ray_steps_response(z, camera_distance, black_hole_radius).final_dir;
-            if final_dir.is_none() {
-                panic!("Should always miss black hole!\nmax_z: {}\nz: {}", max_z, z);
+    // With a refinement, the z samples start from `z_axis` and are refined until interpolating
+    // the final directions is within tolerance; the cache then stores the refined axis.
+    pub fn compute_new(
+        z_axis: AxisSpec,
+        z_refinement: Option<Refinement>,
+        camera_distance: f64,
+        black_hole_radius: f64,
+        model: FieldModel,
+        config: RayCastConfig,
+    ) -> Self {
+        let max_z = find_closest_z(camera_distance, black_hole_radius, model, config);
+        let min_z =
+            find_minimum_pertubation_z(camera_distance, black_hole_radius, model, config, max_z);
+        let sample = |z_01: f64| {
+            if z_01 == 0. {
+                let initial_dir = DVec3::new((1. - min_z * min_z).sqrt(), 0., min_z);
+                return (initial_dir.get_angle(), None);
+            }
+            let z = (max_z - min_z) * z_01 + min_z;
+            let response =
+                cast_ray_steps_response(z, camera_distance, black_hole_radius, model, config);
+            let final_angle = match response.get_final_angle() {
+                Some(final_angle) => final_angle,
+                None => panic!("Should always miss black hole!\nmax_z: {}\nz: {}", max_z, z),
+            };
+            (final_angle, config.drift_policy(&response))
+        };
+        let samples = match z_refinement {
+            Some(refinement) => refine(&z_axis, refinement, sample, |left, right, middle| {
+                (0.5 * (left.0 + right.0) - middle.0).abs()
+            }),
+            None => sample_axis(&z_axis, sample),
+        };
+        let z_axis = match z_refinement {
+            Some(_) => {
+                // Refinement only adds samples, so there are at least two.
+                let positions: Vec<f64> = samples.iter().map(|(z_01, _)| *z_01).collect();
+                AxisSpec {
+                    interpolation: z_axis.interpolation,
+                    ..AxisSpec::from_positions(&positions).unwrap()
+                }
             }
-            let final_dir = final_dir.unwrap().normalize();
-            z_to_final_dir.push((z, (final_dir.x, final_dir.z)));
+            None => z_axis,
+        };
+
+        let mut zs = Vec::new();
+        let mut final_angles = Vec::new();
+        let mut rejected = Vec::new();
+        let mut drift_flagged_z = Vec::new();
+        for (z_01, (final_angle, drift_policy)) in samples {
+            let z = (max_z - min_z) * z_01 + min_z;
+            if drift_policy.is_some() {
+                drift_flagged_z.push(z);
+            }
+            zs.push(z);
+            final_angles.push(final_angle);
+            rejected.push(drift_policy == Some(DriftPolicy::Reject));
         }
+        interpolate_rejected(&mut final_angles, &rejected, |a, b, t| a + t * (b - a));
+        let z_to_final_angle = zs.into_iter().zip(final_angles).collect();
         println!(
             "dist:{}\nMin_z: {}\nMax_z: {}",
             camera_distance, min_z, max_z
@@ -109,30 +159,44 @@ impl FixedDistanceDirectionCache {
             min_z,
             camera_distance,
             black_hole_radius,
-            z_to_final_dir,
+            model,
+            config,
+            z_to_final_angle,
+            z_axis,
+            drift_flagged_z,
         }
     }
 
+    pub fn get_final_angle(&self, z_01: f64) -> f64 {
+        self.z_axis
+            .interpolate(|i| self.z_to_final_angle[i].1, z_01)
+    }
+
     pub fn get_final_dir(&self, z_01: f64) -> DVec3 {
-        let (index, t) = z_01_to_left_index(z_01, self.z_to_final_dir.len());
-        let left = self.z_to_final_dir[index].1;
-        let right = self.z_to_final_dir[index + 1].1;
-        DVec3::new(
-            t * right.0 + (1. - t) * left.0,
-            0.0,
-            t * right.1 + (1. - t) * left.1,
-        )
+        final_dir_from_angle(self.get_final_angle(z_01))
     }
 }
 
 #[cfg(test)]
 mod tests {
 
+    use glam::DVec3;
     use test_utils::plot_trajectories;
 
     use crate::{
-        final_direction_cache::fixed_distance_direction_cache::{index_to_z, index_to_z_01},
-        path_integration2::path::cast_ray_steps_response,
+        cache_spec::{AxisSpec, DirectionCacheSpec, Interpolation},
+        path_integration2::{
+            analytic::finite_deflection,
+            impact_parameter::impact_parameter,
+            path::cast_ray_steps_response,
+            ray_cast_config::RayCastConfig,
+            response::ToAngle,
+            structs::{
+                drift::{DriftLimit, DriftPolicy},
+                field::FieldModel,
+            },
+        },
+        refinement::Refinement,
     };
 
     use super::{FixedDistanceDirectionCache, DISTANCE_CACHE_SIZE};
@@ -143,11 +207,18 @@ mod tests {
         let black_hole_radius = 1.5;
 
         let mut errors = Vec::new();
-        let cache = FixedDistanceDirectionCache::compute_new(camera_distance, black_hole_radius);
+        let cache = FixedDistanceDirectionCache::compute_new(
+            DirectionCacheSpec::default_z(),
+            None,
+            camera_distance,
+            black_hole_radius,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+        );
         let mut line = Vec::new();
 
-        for i in 0..cache.z_to_final_dir.len() {
-            let z_01 = index_to_z_01(i, DISTANCE_CACHE_SIZE);
+        for i in 0..cache.z_to_final_angle.len() {
+            let z_01 = cache.z_axis.index_to_float_01(i);
             line.push(((i as f32) / (DISTANCE_CACHE_SIZE - 1) as f32, z_01 as f32));
         }
         errors.push(line);
@@ -165,17 +236,28 @@ mod tests {
 
         let mut errors = Vec::new();
         for camera_distance in [2., 5., 10., 15., 20.] {
-            let cache =
-                FixedDistanceDirectionCache::compute_new(camera_distance, black_hole_radius);
+            let cache = FixedDistanceDirectionCache::compute_new(
+                DirectionCacheSpec::default_z(),
+                None,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::PseudoForce,
+                RayCastConfig::default(),
+            );
 
             let mut line = Vec::new();
 
-            for i in 0..cache.z_to_final_dir.len() {
-                let z = index_to_z(cache.max_z, cache.min_z, i, DISTANCE_CACHE_SIZE);
-                let curr_angle =
-                    cast_ray_steps_response(z, cache.camera_distance, cache.black_hole_radius)
-                        .get_angle_dist()
-                        .get_max_angle();
+            for i in 0..cache.z_to_final_angle.len() {
+                let z = cache.z_axis.index_to_value(i, (cache.min_z, cache.max_z));
+                let curr_angle = cast_ray_steps_response(
+                    z,
+                    cache.camera_distance,
+                    cache.black_hole_radius,
+                    cache.model,
+                    cache.config,
+                )
+                .get_angle_dist()
+                .get_max_angle();
                 println!("z: {}, Final dir: {:?}", z, curr_angle);
                 line.push((
                     (i as f32) / (DISTANCE_CACHE_SIZE - 1) as f32,
@@ -197,14 +279,26 @@ mod tests {
         let camera_distance = 20.0;
         let black_hole_radius = 1.5;
 
-        let cache = FixedDistanceDirectionCache::compute_new(camera_distance, black_hole_radius);
+        let cache = FixedDistanceDirectionCache::compute_new(
+            DirectionCacheSpec::default_z(),
+            None,
+            camera_distance,
+            black_hole_radius,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+        );
 
         let mut paths = Vec::new();
 
-        for z in &cache.z_to_final_dir {
+        for z in &cache.z_to_final_angle {
             let z = z.0;
-            let response =
-                cast_ray_steps_response(z, cache.camera_distance, cache.black_hole_radius);
+            let response = cast_ray_steps_response(
+                z,
+                cache.camera_distance,
+                cache.black_hole_radius,
+                cache.model,
+                cache.config,
+            );
             paths.push(
                 response
                     .path
@@ -227,11 +321,17 @@ mod tests {
         let mut lines = Vec::new();
         let mut samples = Vec::new();
         for camera_distance in [5., 10., 15., 20.] {
-            let cache =
-                FixedDistanceDirectionCache::compute_new(camera_distance, black_hole_radius);
+            let cache = FixedDistanceDirectionCache::compute_new(
+                DirectionCacheSpec::default_z(),
+                None,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::PseudoForce,
+                RayCastConfig::default(),
+            );
 
-            for i in 0..(2 * cache.z_to_final_dir.len()) {
-                let z_01 = i as f64 / (2 * cache.z_to_final_dir.len() - 1) as f64;
+            for i in 0..(2 * cache.z_to_final_angle.len()) {
+                let z_01 = i as f64 / (2 * cache.z_to_final_angle.len() - 1) as f64;
                 samples.push(z_01);
                 if z_01 > 0. && z_01 < 1. {
                     samples.push(z_01 * z_01);
@@ -244,8 +344,13 @@ mod tests {
             for z_01 in &samples {
                 let approx_final_dir = cache.get_final_dir(*z_01).normalize();
                 let z = (cache.max_z - cache.min_z) * z_01 + cache.min_z;
-                let response =
-                    cast_ray_steps_response(z, cache.camera_distance, cache.black_hole_radius);
+                let response = cast_ray_steps_response(
+                    z,
+                    cache.camera_distance,
+                    cache.black_hole_radius,
+                    cache.model,
+                    cache.config,
+                );
 
                 let true_final_dir = response.final_dir;
                 assert!(
@@ -276,7 +381,14 @@ mod tests {
     fn fixed_distance_direction_serialization() {
         let distance = 10.0;
         let black_hole_radius = 1.5;
-        let cache = FixedDistanceDirectionCache::compute_new(distance, black_hole_radius);
+        let cache = FixedDistanceDirectionCache::compute_new(
+            DirectionCacheSpec::default_z(),
+            None,
+            distance,
+            black_hole_radius,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+        );
 
         let serialized = serde_json::to_string(&cache);
 
@@ -290,4 +402,263 @@ mod tests {
         let deserialized = deserialized.unwrap();
         assert_eq!(deserialized, cache);
     }
+
+    #[test]
+    fn fixed_distance_direction_drift_flags() {
+        let distance = 10.0;
+        let black_hole_radius = 1.5;
+        let cache = FixedDistanceDirectionCache::compute_new(
+            DirectionCacheSpec::default_z(),
+            None,
+            distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+        );
+        assert!(cache.drift_flagged_z.is_empty());
+
+        let flag_everything = RayCastConfig {
+            drift_limit: Some(DriftLimit {
+                threshold: 0.,
+                policy: DriftPolicy::Flag,
+            }),
+            ..RayCastConfig::default()
+        };
+        let flagged = FixedDistanceDirectionCache::compute_new(
+            DirectionCacheSpec::default_z(),
+            None,
+            distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            flag_everything,
+        );
+        // The first sample is the undeflected ray, which isn't cast.
+        assert_eq!(flagged.drift_flagged_z.len(), DISTANCE_CACHE_SIZE - 1);
+        assert_eq!(flagged.z_to_final_angle, cache.z_to_final_angle);
+
+        // RK4 conserves both quantities well, so a loose limit shouldn't trigger.
+        let loose = RayCastConfig {
+            drift_limit: Some(DriftLimit {
+                threshold: 0.001,
+                policy: DriftPolicy::Reject,
+            }),
+            ..RayCastConfig::default()
+        };
+        let loose = FixedDistanceDirectionCache::compute_new(
+            DirectionCacheSpec::default_z(),
+            None,
+            distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            loose,
+        );
+        assert!(loose.drift_flagged_z.is_empty());
+        assert_eq!(loose.z_to_final_angle, cache.z_to_final_angle);
+    }
+
+    #[test]
+    fn fixed_distance_direction_drift_rejects() {
+        let compute = |config| {
+            FixedDistanceDirectionCache::compute_new(
+                AxisSpec::linear(64).unwrap(),
+                None,
+                10.,
+                1.5,
+                FieldModel::Schwarzschild,
+                config,
+            )
+        };
+        let cache = compute(RayCastConfig::default());
+        // RK4 drifts by a few 1e-7 at this distance, so this limit rejects some of the rays.
+        let rejecting = compute(RayCastConfig {
+            drift_limit: Some(DriftLimit {
+                threshold: 5e-7,
+                policy: DriftPolicy::Reject,
+            }),
+            ..RayCastConfig::default()
+        });
+        let rejected: Vec<bool> = cache
+            .z_to_final_angle
+            .iter()
+            .map(|(z, _)| rejecting.drift_flagged_z.contains(z))
+            .collect();
+        assert_eq!(
+            rejected.iter().filter(|&&r| r).count(),
+            rejecting.drift_flagged_z.len()
+        );
+        assert!(rejected.contains(&true) && rejected.contains(&false));
+
+        // Rejected samples are replaced by interpolating their closest accepted neighbours.
+        let angles = &cache.z_to_final_angle;
+        for (i, &(z, final_angle)) in rejecting.z_to_final_angle.iter().enumerate() {
+            assert_eq!(z, angles[i].0);
+            if !rejected[i] {
+                assert_eq!(final_angle, angles[i].1);
+                continue;
+            }
+            let left = (0..i).rev().find(|&j| !rejected[j]);
+            let right = (i + 1..angles.len()).find(|&j| !rejected[j]);
+            let expected = match (left, right) {
+                (Some(left), Some(right)) => {
+                    let t = (i - left) as f64 / (right - left) as f64;
+                    angles[left].1 + t * (angles[right].1 - angles[left].1)
+                }
+                (Some(left), None) => angles[left].1,
+                (None, Some(right)) => angles[right].1,
+                (None, None) => unreachable!(),
+            };
+            assert!((final_angle - expected).abs() < 1e-12, "i: {}", i);
+        }
+    }
+
+    #[test]
+    fn fixed_distance_direction_matches_analytic() {
+        let distance = 10.0;
+        let black_hole_radius = 1.5;
+        let cache = FixedDistanceDirectionCache::compute_new(
+            DirectionCacheSpec::default_z(),
+            None,
+            distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+        );
+        let escape_radius = cache.config.escape_radius(distance);
+        let mut max_error: f64 = 0.;
+        for (z, final_angle) in &cache.z_to_final_angle {
+            let b = impact_parameter(*z, distance, black_hole_radius, FieldModel::Schwarzschild);
+            // Rays below the critical impact parameter have no turning point, so no closed form.
+            let deflection =
+                match finite_deflection(b, black_hole_radius, distance, escape_radius, *z > 0.) {
+                    Some(deflection) => deflection,
+                    None => continue,
+                };
+            // The stored angle counts every loop, so it's the whole deflection on top of the
+            // initial direction's angle.
+            let initial_dir = DVec3::new((1. - z * z).sqrt(), 0., *z);
+            let error = (final_angle - initial_dir.get_angle() - deflection).abs();
+            max_error = max_error.max(error);
+        }
+        assert!(max_error < 0.0001, "max error: {}", max_error);
+        // Rays closer to the hole turn further, loops included.
+        assert!(cache
+            .z_to_final_angle
+            .windows(2)
+            .all(|pair| pair[0].1 < pair[1].1));
+    }
+
+    #[test]
+    fn refined_beats_uniform() {
+        let distance = 10.0;
+        let black_hole_radius = 1.5;
+        let cache = |z_axis: AxisSpec, z_refinement: Option<Refinement>| {
+            FixedDistanceDirectionCache::compute_new(
+                z_axis,
+                z_refinement,
+                distance,
+                black_hole_radius,
+                FieldModel::PseudoForce,
+                RayCastConfig::default(),
+            )
+        };
+        let refinement = Refinement {
+            tolerance: 0.0001,
+            max_size: 256,
+        };
+        let refined = cache(AxisSpec::linear(16).unwrap(), Some(refinement));
+        let size = refined.z_to_final_angle.len();
+        assert!(size > 16 && size <= refinement.max_size);
+        assert_eq!(refined.z_axis.size, size);
+        // Refined samples crowd towards max_z, where the rays graze the photon sphere.
+        let upper = refined
+            .z_to_final_angle
+            .iter()
+            .filter(|(z, _)| *z > 0.5 * (refined.min_z + refined.max_z))
+            .count();
+        assert!(upper > size / 2, "{} of {}", upper, size);
+
+        let uniform = cache(AxisSpec::linear(size).unwrap(), None);
+        let max_error = |cache: &FixedDistanceDirectionCache| {
+            let samples = 500;
+            (0..samples)
+                .map(|i| {
+                    let z_01 = (i as f64 + 0.5) / samples as f64;
+                    let z = (cache.max_z - cache.min_z) * z_01 + cache.min_z;
+                    let response = cast_ray_steps_response(
+                        z,
+                        distance,
+                        black_hole_radius,
+                        cache.model,
+                        cache.config,
+                    );
+                    let expected = response.final_dir.unwrap().normalize();
+                    let actual = cache.get_final_dir(z_01);
+                    (expected.x - actual.x).hypot(expected.z - actual.z)
+                })
+                .fold(0., f64::max)
+        };
+        let (refined_error, uniform_error) = (max_error(&refined), max_error(&uniform));
+        assert!(
+            refined_error < uniform_error,
+            "refined: {}, uniform: {}",
+            refined_error,
+            uniform_error
+        );
+
+        let deserialized: FixedDistanceDirectionCache =
+            serde_json::from_str(&serde_json::to_string(&refined).unwrap()).unwrap();
+        assert_eq!(deserialized, refined);
+    }
+
+    #[test]
+    fn cubic_beats_linear() {
+        let distance = 10.0;
+        let black_hole_radius = 1.5;
+        let mut cache = FixedDistanceDirectionCache::compute_new(
+            AxisSpec::linear(64).unwrap(),
+            None,
+            distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+        );
+        let escape_radius = cache.config.escape_radius(distance);
+        let samples = 1000;
+        let expected: Vec<(f64, DVec3)> = (0..samples)
+            .filter_map(|i| {
+                let z_01 = (i as f64 + 0.5) / samples as f64;
+                let z = (cache.max_z - cache.min_z) * z_01 + cache.min_z;
+                let b = impact_parameter(z, distance, black_hole_radius, FieldModel::Schwarzschild);
+                let deflection =
+                    finite_deflection(b, black_hole_radius, distance, escape_radius, z > 0.)?;
+                let angle = f64::atan2((1. - z * z).sqrt(), z) - deflection;
+                Some((z_01, DVec3::new(angle.sin(), 0., angle.cos())))
+            })
+            .collect();
+        // Same samples, so the same memory; only the lookup changes. Near the photon sphere the
+        // direction turns faster than any of them can follow, so the median shows the smooth part.
+        let mut errors = |interpolation: Interpolation| {
+            cache.z_axis.interpolation = interpolation;
+            let mut errors: Vec<f64> = expected
+                .iter()
+                .map(|(z_01, expected)| (cache.get_final_dir(*z_01) - *expected).length())
+                .collect();
+            errors.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let rms = (errors.iter().map(|e| e * e).sum::<f64>() / errors.len() as f64).sqrt();
+            (errors[errors.len() / 2], rms)
+        };
+        let (linear_median, linear_rms) = errors(Interpolation::Linear);
+        for interpolation in [Interpolation::CatmullRom, Interpolation::MonotoneHermite] {
+            let (median, rms) = errors(interpolation);
+            assert!(
+                median < 0.25 * linear_median && rms < linear_rms,
+                "{:?}: median {}, rms {}, linear: median {}, rms {}",
+                interpolation,
+                median,
+                rms,
+                linear_median,
+                linear_rms
+            );
+        }
+    }
 }
@@ -0,0 +1,333 @@
+use glam::DVec3;
+use serde::{Deserialize, Serialize};
+
+use crate::path_integration2::{
+    analytic::finite_deflection,
+    impact_parameter::{impact_parameter, z_for_impact_parameter},
+    path::{cast_ray_steps_response, find_optimal_z_by_summary},
+    ray_cast_config::RayCastConfig,
+    ray_summary::RaySummary,
+    response::Response,
+    structs::field::FieldModel,
+};
+
+pub const IMPACT_PARAMETER_CACHE_SIZE: usize = 1 << 6;
+// Closest sample to the critical impact parameter, relative to it.
+const MIN_RELATIVE_OFFSET: f64 = 0.0001;
+
+// Total deflection keyed by impact parameter, measured from a far away reference camera. Most of
+// the deflection happens near the hole and doesn't depend on where the camera is, so one table
+// serves every camera once the closed form accounts for the rest (see `get_final_dir`).
+//
+// Samples are spaced logarithmically in b - b_c, since the deflection diverges logarithmically at
+// the critical impact parameter b_c.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct ImpactParameterDirectionCache {
+    pub black_hole_radius: f64,
+    pub model: FieldModel,
+    pub config: RayCastConfig,
+    pub reference_distance: f64,
+    pub critical_impact_parameter: f64,
+    pub max_impact_parameter: f64,
+    pub b_to_deflection: Vec<f64>,
+}
+
+fn index_to_b(critical_b: f64, max_b: f64, i: usize, cache_size: usize) -> f64 {
+    let t = i as f64 / (cache_size - 1) as f64;
+    let min_offset = (MIN_RELATIVE_OFFSET * critical_b).ln();
+    let max_offset = (max_b - critical_b).ln();
+    critical_b + (min_offset + t * (max_offset - min_offset)).exp()
+}
+
+fn b_to_left_index(critical_b: f64, max_b: f64, b: f64, cache_size: usize) -> (usize, f64) {
+    let min_offset = (MIN_RELATIVE_OFFSET * critical_b).ln();
+    let max_offset = (max_b - critical_b).ln();
+    let t = ((b - critical_b).ln() - min_offset) / (max_offset - min_offset);
+    let float_index = (cache_size - 1) as f64 * t.clamp(0., 1.);
+    let index = (float_index as usize).clamp(0, cache_size - 2);
+    (index, float_index - index as f64)
+}
+
+// Angle the direction of travel turns through: the angle swept around the hole, plus the change in
+// angle between the direction of travel and the outward radial direction.
+fn total_deflection(response: &Response) -> f64 {
+    let start = response.path[0];
+    let end = *response.path.last().unwrap();
+    let initial_dir = (response.path[1] - start).normalize();
+    let start_heading = initial_dir.dot(start.normalize()).clamp(-1., 1.).acos();
+    let end_heading = response
+        .final_dir
+        .unwrap()
+        .dot(end.normalize())
+        .clamp(-1., 1.)
+        .acos();
+    response.get_angle_dist().get_max_angle() + end_heading - start_heading
+}
+
+impl ImpactParameterDirectionCache {
+    pub fn compute_new(
+        cache_size: usize,
+        black_hole_radius: f64,
+        model: FieldModel,
+        config: RayCastConfig,
+        reference_distance: f64,
+        max_impact_parameter: f64,
+    ) -> Self {
+        let hits = |r: &RaySummary| r.hits_black_hole();
+        let critical_z = find_optimal_z_by_summary(
+            reference_distance as f32,
+            black_hole_radius as f32,
+            model,
+            config,
+            (0., 1.),
+            &hits,
+        )
+        .0;
+        let critical_impact_parameter =
+            impact_parameter(critical_z, reference_distance, black_hole_radius, model);
+
+        let mut b_to_deflection = Vec::new();
+        for i in 0..cache_size {
+            let b = index_to_b(
+                critical_impact_parameter,
+                max_impact_parameter,
+                i,
+                cache_size,
+            );
+            let z = z_for_impact_parameter(b, reference_distance, black_hole_radius, model)
+                .expect("The reference camera should be further than the largest b!");
+            let response =
+                cast_ray_steps_response(z, reference_distance, black_hole_radius, model, config);
+            if response.hits_black_hole() {
+                panic!("Should always miss black hole!\nb: {}", b);
+            }
+            b_to_deflection.push(total_deflection(&response));
+        }
+        ImpactParameterDirectionCache {
+            black_hole_radius,
+            model,
+            config,
+            reference_distance,
+            critical_impact_parameter,
+            max_impact_parameter,
+            b_to_deflection,
+        }
+    }
+
+    // Returns None inside the shadow. Past the table the deflection falls off like 1 / b.
+    pub fn get_deflection(&self, b: f64) -> Option<f64> {
+        if b < self.critical_impact_parameter {
+            return None;
+        }
+        if b > self.max_impact_parameter {
+            let last = *self.b_to_deflection.last().unwrap();
+            return Some(last * self.max_impact_parameter / b);
+        }
+        let (index, t) = b_to_left_index(
+            self.critical_impact_parameter,
+            self.max_impact_parameter,
+            b,
+            self.b_to_deflection.len(),
+        );
+        let left = self.b_to_deflection[index];
+        let right = self.b_to_deflection[index + 1];
+        Some(t * right + (1. - t) * left)
+    }
+
+    // How much more the ray from `camera_distance` with impact parameter b turns than the one the
+    // table measured from the reference camera. Only Schwarzschild has the closed form for it, so
+    // other models are only served at the reference camera.
+    fn deflection_correction(&self, b: f64, camera_distance: f64, incoming: bool) -> Option<f64> {
+        if camera_distance == self.reference_distance && incoming {
+            return Some(0.);
+        }
+        if self.model != FieldModel::Schwarzschild {
+            return None;
+        }
+        let deflection = |r_start: f64, incoming: bool| {
+            let r_end = self.config.escape_radius(r_start);
+            finite_deflection(b, self.black_hole_radius, r_start, r_end, incoming)
+        };
+        Some(deflection(camera_distance, incoming)? - deflection(self.reference_distance, true)?)
+    }
+
+    // Final direction of the ray `cast_ray_steps_response` casts for (camera distance, z), in the
+    // same frame. Returns None inside the shadow, for rays heading away from the hole below the
+    // critical impact parameter, and wherever `deflection_correction` can't be computed.
+    pub fn get_final_dir(&self, camera_distance: f64, z: f64) -> Option<DVec3> {
+        let b = impact_parameter(z, camera_distance, self.black_hole_radius, self.model);
+        let deflection =
+            self.get_deflection(b)? + self.deflection_correction(b, camera_distance, z > 0.)?;
+        // Gravity turns the direction clockwise in the x-z plane.
+        let angle = f64::atan2((1. - z * z).max(0.).sqrt(), z) - deflection;
+        Some(DVec3::new(angle.sin(), 0., angle.cos()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        final_direction_cache::fixed_distance_direction_cache::DISTANCE_CACHE_SIZE,
+        path_integration2::{
+            analytic::{critical_impact_parameter, deflection},
+            impact_parameter::z_for_impact_parameter,
+            path::cast_ray_steps_response,
+            ray_cast_config::RayCastConfig,
+            structs::field::FieldModel,
+        },
+    };
+
+    use super::{ImpactParameterDirectionCache, IMPACT_PARAMETER_CACHE_SIZE};
+
+    #[test]
+    fn impact_parameter_matches_analytic() {
+        let black_hole_radius = 1.5;
+        let cache = ImpactParameterDirectionCache::compute_new(
+            IMPACT_PARAMETER_CACHE_SIZE,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+            1000.,
+            100.,
+        );
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        assert!((cache.critical_impact_parameter - critical_b).abs() < 0.001);
+        for relative in [1.001, 1.01, 1.1, 1.5, 3., 10., 20.] {
+            let b = relative * critical_b;
+            let expected = deflection(b, black_hole_radius).unwrap();
+            let actual = cache.get_deflection(b).unwrap();
+            assert!(
+                (actual - expected).abs() < 0.01 * expected,
+                "b: {}\nexpected: {}\nactual: {}",
+                b,
+                expected,
+                actual
+            );
+        }
+        assert!(cache.get_deflection(0.99 * critical_b).is_none());
+    }
+
+    #[test]
+    fn impact_parameter_serves_every_far_camera() {
+        let black_hole_radius = 1.5;
+        let model = FieldModel::Schwarzschild;
+        let cache = ImpactParameterDirectionCache::compute_new(
+            IMPACT_PARAMETER_CACHE_SIZE,
+            black_hole_radius,
+            model,
+            RayCastConfig::default(),
+            1000.,
+            100.,
+        );
+        assert!(cache.b_to_deflection.len() < DISTANCE_CACHE_SIZE / 4);
+        for camera_distance in [200., 500.] {
+            let mut cache_error: f64 = 0.;
+            for b in [6., 10., 30., 60.] {
+                let z =
+                    z_for_impact_parameter(b, camera_distance, black_hole_radius, model).unwrap();
+                let expected = cast_ray_steps_response(
+                    z,
+                    camera_distance,
+                    black_hole_radius,
+                    model,
+                    cache.config,
+                )
+                .final_dir
+                .unwrap();
+                cache_error = cache_error
+                    .max((cache.get_final_dir(camera_distance, z).unwrap() - expected).length());
+            }
+            assert!(
+                cache_error < 0.005,
+                "camera distance: {}, error: {}",
+                camera_distance,
+                cache_error
+            );
+        }
+    }
+
+    #[test]
+    fn impact_parameter_corrects_for_close_cameras() {
+        let black_hole_radius = 1.5;
+        let model = FieldModel::Schwarzschild;
+        let cache = ImpactParameterDirectionCache::compute_new(
+            IMPACT_PARAMETER_CACHE_SIZE,
+            black_hole_radius,
+            model,
+            RayCastConfig::default(),
+            1000.,
+            100.,
+        );
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        for camera_distance in [5., 10., 20., 30.] {
+            let mut cache_error: f64 = 0.;
+            for relative in [1.05, 1.3, 2., 4.] {
+                let b = relative * critical_b;
+                // Cameras closer than b can't see the ray.
+                let z = match z_for_impact_parameter(b, camera_distance, black_hole_radius, model) {
+                    Some(z) => z,
+                    None => continue,
+                };
+                // Rays heading away from the hole only sweep the outgoing half.
+                for z in [z, -z] {
+                    let expected = cast_ray_steps_response(
+                        z,
+                        camera_distance,
+                        black_hole_radius,
+                        model,
+                        cache.config,
+                    )
+                    .final_dir
+                    .unwrap();
+                    let actual = cache.get_final_dir(camera_distance, z).unwrap();
+                    cache_error = cache_error.max((actual - expected).length());
+                }
+            }
+            assert!(
+                cache_error < 0.005,
+                "camera distance: {}, error: {}",
+                camera_distance,
+                cache_error
+            );
+        }
+    }
+
+    #[test]
+    fn impact_parameter_needs_closed_form_away_from_reference() {
+        let cache = ImpactParameterDirectionCache::compute_new(
+            16,
+            1.5,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+            100.,
+            20.,
+        );
+        assert!(cache.get_final_dir(100., 0.95).is_some());
+        assert!(cache.get_final_dir(100., -0.95).is_none());
+        assert!(cache.get_final_dir(20., 0.95).is_none());
+    }
+
+    #[test]
+    fn serialization() {
+        let cache = ImpactParameterDirectionCache::compute_new(
+            16,
+            1.5,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+            100.,
+            20.,
+        );
+
+        let serialized = serde_json::to_string(&cache);
+
+        assert!(serialized.is_ok());
+
+        let deserialized: Result<ImpactParameterDirectionCache, serde_json::Error> =
+            serde_json::from_str(serialized.unwrap().as_str());
+
+        assert!(deserialized.is_ok());
+
+        let deserialized = deserialized.unwrap();
+        assert_eq!(deserialized, cache);
+    }
+}
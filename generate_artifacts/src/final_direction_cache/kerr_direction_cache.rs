@@ -0,0 +1,297 @@
+use std::fmt;
+
+use glam::DVec3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache_spec::SpecError,
+    path_integration2::{
+        path::{cast_kerr_ray_response, KerrRayError},
+        ray_cast_config::RayCastConfig,
+        structs::{
+            integrator::UnsupportedIntegrator,
+            kerr_field::{KerrField, SpinOutOfRange},
+        },
+    },
+};
+
+pub const KERR_CACHE_SIZE: (usize, usize, usize, usize) = (1 << 3, 1 << 3, 1 << 5, 1 << 5);
+
+// Final directions for a spinning black hole. Frame dragging breaks the rotational symmetry the
+// other caches rely on, so rays are keyed by (camera distance, inclination, x, y) instead of a
+// single z. Directions are in the frame used by `cast_kerr_ray_response`.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct KerrDirectionCache {
+    pub cache_size: (usize, usize, usize, usize),
+    pub distance_bounds: (f64, f64),
+    pub inclination_bounds: (f64, f64),
+    // The image plane covers [-view_width, view_width] on both axes.
+    pub view_width: f64,
+    pub black_hole_radius: f64,
+    pub spin: f64,
+    #[serde(default)]
+    pub config: RayCastConfig,
+    pub final_dirs: Vec<Option<(f64, f64, f64)>>,
+}
+
+#[derive(Debug)]
+pub enum KerrCacheError {
+    Axis(SpecError),
+    Spin(SpinOutOfRange),
+    Integrator(UnsupportedIntegrator),
+}
+
+impl From<KerrRayError> for KerrCacheError {
+    fn from(error: KerrRayError) -> Self {
+        match error {
+            KerrRayError::Spin(error) => KerrCacheError::Spin(error),
+            KerrRayError::Integrator(error) => KerrCacheError::Integrator(error),
+        }
+    }
+}
+
+impl fmt::Display for KerrCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KerrCacheError::Axis(error) => error.fmt(f),
+            KerrCacheError::Spin(error) => error.fmt(f),
+            KerrCacheError::Integrator(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for KerrCacheError {}
+
+fn index_to_float(bounds: (f64, f64), index: usize, vec_len: usize) -> f64 {
+    let float_01 = (index as f64) / (vec_len - 1) as f64;
+    (bounds.1 - bounds.0) * float_01.clamp(0., 1.) + bounds.0
+}
+
+fn float_01_to_left_index(float_01: f64, vec_len: usize) -> (usize, f64) {
+    let float_index = (vec_len - 1) as f64 * float_01.clamp(0., 1.);
+    let index = (float_index as usize).clamp(0, vec_len - 2);
+    let t = float_index - index as f64;
+    (index, t)
+}
+
+impl KerrDirectionCache {
+    pub fn compute_new(
+        cache_size: (usize, usize, usize, usize),
+        distance_bounds: (f64, f64),
+        inclination_bounds: (f64, f64),
+        view_width: f64,
+        black_hole_radius: f64,
+        spin: f64,
+        config: RayCastConfig,
+    ) -> Result<Self, KerrCacheError> {
+        // Lookups interpolate between neighbouring samples, so every axis needs two.
+        let sizes = [cache_size.0, cache_size.1, cache_size.2, cache_size.3];
+        if let Some(&size) = sizes.iter().find(|&&size| size < 2) {
+            return Err(KerrCacheError::Axis(SpecError::TooFewSamples(size)));
+        }
+        KerrField::new(black_hole_radius, spin).map_err(KerrCacheError::Spin)?;
+        config
+            .integrator
+            .require_rk4()
+            .map_err(KerrCacheError::Integrator)?;
+
+        let view_bounds = (-view_width, view_width);
+        let mut final_dirs = Vec::new();
+        for d in 0..cache_size.0 {
+            let dist = index_to_float(distance_bounds, d, cache_size.0);
+            println!("Generating dist: {}", dist);
+            for i in 0..cache_size.1 {
+                let inclination = index_to_float(inclination_bounds, i, cache_size.1);
+                for x in 0..cache_size.2 {
+                    let x = index_to_float(view_bounds, x, cache_size.2);
+                    for y in 0..cache_size.3 {
+                        let y = index_to_float(view_bounds, y, cache_size.3);
+                        let final_dir = cast_kerr_ray_response(
+                            x,
+                            y,
+                            dist,
+                            inclination,
+                            black_hole_radius,
+                            spin,
+                            config,
+                        )?
+                        .final_dir;
+                        final_dirs.push(final_dir.map(|v| (v.x, v.y, v.z)));
+                    }
+                }
+            }
+        }
+        Ok(KerrDirectionCache {
+            cache_size,
+            distance_bounds,
+            inclination_bounds,
+            view_width,
+            black_hole_radius,
+            spin,
+            config,
+            final_dirs,
+        })
+    }
+
+    fn index(&self, d: usize, i: usize, x: usize, y: usize) -> usize {
+        ((d * self.cache_size.1 + i) * self.cache_size.2 + x) * self.cache_size.3 + y
+    }
+
+    // Returns None if any of the surrounding samples hit the black hole.
+    pub fn get_final_dir(&self, d_01: f64, inclination_01: f64, x: f64, y: f64) -> Option<DVec3> {
+        let x_01 = 0.5 * (x / self.view_width + 1.);
+        let y_01 = 0.5 * (y / self.view_width + 1.);
+        let (d, d_t) = float_01_to_left_index(d_01, self.cache_size.0);
+        let (i, i_t) = float_01_to_left_index(inclination_01, self.cache_size.1);
+        let (x, x_t) = float_01_to_left_index(x_01, self.cache_size.2);
+        let (y, y_t) = float_01_to_left_index(y_01, self.cache_size.3);
+
+        let mut final_dir = DVec3::ZERO;
+        for corner in 0..16 {
+            let weight = |bit: usize, t: f64| match corner & bit == 0 {
+                true => 1. - t,
+                false => t,
+            };
+            let index = self.index(
+                d + (corner & 1),
+                i + ((corner >> 1) & 1),
+                x + ((corner >> 2) & 1),
+                y + ((corner >> 3) & 1),
+            );
+            let sample = self.final_dirs[index]?;
+            final_dir += weight(1, d_t)
+                * weight(2, i_t)
+                * weight(4, x_t)
+                * weight(8, y_t)
+                * DVec3::new(sample.0, sample.1, sample.2);
+        }
+        Some(final_dir.normalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::path_integration2::{
+        path::cast_kerr_ray_response, ray_cast_config::RayCastConfig,
+        structs::integrator::IntegratorKind,
+    };
+
+    use super::{KerrCacheError, KerrDirectionCache};
+
+    #[test]
+    fn kerr_interpolation_error() {
+        let distance_bounds = (10., 20.);
+        let inclination_bounds = (0.25, 1.5);
+        let black_hole_radius = 1.5;
+        let spin = 0.9;
+        let cache = KerrDirectionCache::compute_new(
+            (3, 3, 33, 33),
+            distance_bounds,
+            inclination_bounds,
+            1.,
+            black_hole_radius,
+            spin,
+            RayCastConfig::default(),
+        )
+        .unwrap();
+
+        for (d_01, inclination_01, x, y) in [
+            (0.3, 0.6, 0.8, 0.1),
+            (0.7, 0.2, -0.7, 0.6),
+            (0.5, 0.9, 0.5, -0.9),
+        ] {
+            let dist = (distance_bounds.1 - distance_bounds.0) * d_01 + distance_bounds.0;
+            let inclination = (inclination_bounds.1 - inclination_bounds.0) * inclination_01
+                + inclination_bounds.0;
+            let expected = cast_kerr_ray_response(
+                x,
+                y,
+                dist,
+                inclination,
+                black_hole_radius,
+                spin,
+                cache.config,
+            )
+            .unwrap()
+            .final_dir
+            .unwrap();
+            let actual = cache.get_final_dir(d_01, inclination_01, x, y).unwrap();
+            assert!(
+                (expected - actual).length() < 0.05,
+                "x: {}, y: {}\nexpected: {}\nactual: {}",
+                x,
+                y,
+                expected,
+                actual
+            );
+        }
+
+        // The centre of the image is always inside the shadow.
+        assert!(cache.get_final_dir(0.5, 0.5, 0., 0.).is_none());
+    }
+
+    #[test]
+    fn serialization() {
+        let cache = KerrDirectionCache::compute_new(
+            (2, 2, 5, 5),
+            (5., 20.),
+            (0.5, 1.5),
+            1.,
+            1.5,
+            0.5,
+            RayCastConfig::default(),
+        )
+        .unwrap();
+
+        let serialized = serde_json::to_string(&cache);
+
+        assert!(serialized.is_ok());
+
+        let deserialized: Result<KerrDirectionCache, serde_json::Error> =
+            serde_json::from_str(serialized.unwrap().as_str());
+
+        assert!(deserialized.is_ok());
+
+        let deserialized = deserialized.unwrap();
+        assert_eq!(deserialized, cache);
+    }
+
+    #[test]
+    fn rejects_bad_parameters() {
+        let compute = |cache_size, spin| {
+            KerrDirectionCache::compute_new(
+                cache_size,
+                (5., 20.),
+                (0.5, 1.5),
+                1.,
+                1.5,
+                spin,
+                RayCastConfig::default(),
+            )
+        };
+        assert!(matches!(
+            compute((2, 1, 5, 5), 0.5),
+            Err(KerrCacheError::Axis(_))
+        ));
+        assert!(matches!(
+            compute((2, 2, 5, 5), 1.5),
+            Err(KerrCacheError::Spin(_))
+        ));
+        assert!(matches!(
+            compute((2, 2, 5, 5), -1.01),
+            Err(KerrCacheError::Spin(_))
+        ));
+        assert!(matches!(
+            KerrDirectionCache::compute_new(
+                (2, 2, 5, 5),
+                (5., 20.),
+                (0.5, 1.5),
+                1.,
+                1.5,
+                0.5,
+                RayCastConfig::with_integrator(IntegratorKind::Leapfrog),
+            ),
+            Err(KerrCacheError::Integrator(_))
+        ));
+    }
+}
@@ -1,2 +1,5 @@
 pub mod direction_cache;
 pub mod fixed_distance_direction_cache;
+pub mod impact_parameter_direction_cache;
+pub mod kerr_direction_cache;
+pub mod wormhole_direction_cache;
@@ -0,0 +1,258 @@
+use std::f64::consts::TAU;
+
+use glam::DVec3;
+use serde::{Deserialize, Serialize};
+use wire_structs::sampler::wormhole_direction_table::WormholeDirectionTable;
+
+use crate::path_integration2::{
+    path::cast_wormhole_ray_response,
+    ray_cast_config::RayCastConfig,
+    structs::{integrator::UnsupportedIntegrator, wormhole_field::Universe},
+};
+
+pub const WORMHOLE_CACHE_SIZE: (usize, usize) = (1 << 5, 1 << 9);
+
+// Final directions for an Ellis wormhole, keyed by (camera distance, z) like `DirectionCache`.
+// Each entry also records which side of the throat the ray ends on, so the renderer can pick the
+// sky it sees. Directions are in the frame used by `cast_wormhole_ray_response`.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct WormholeDirectionCache {
+    pub cache_size: (usize, usize),
+    pub distance_bounds: (f64, f64),
+    pub z_bounds: (f64, f64),
+    pub throat_radius: f64,
+    #[serde(default)]
+    pub config: RayCastConfig,
+    // Angle of the final direction from +z towards +x, and the side it ends on. Missing for rays
+    // that circle the throat until they run out of distance.
+    pub final_dirs: Vec<Option<(f64, Universe)>>,
+}
+
+fn index_to_float(bounds: (f64, f64), index: usize, vec_len: usize) -> f64 {
+    let float_01 = (index as f64) / (vec_len - 1) as f64;
+    (bounds.1 - bounds.0) * float_01.clamp(0., 1.) + bounds.0
+}
+
+// z is sampled evenly in the angle between the ray and the direction to the throat, since the
+// final direction changes fastest for rays that start almost straight at it.
+fn index_to_z(z_bounds: (f64, f64), index: usize, vec_len: usize) -> f64 {
+    index_to_float((z_bounds.0.acos(), z_bounds.1.acos()), index, vec_len).cos()
+}
+
+fn z_to_float_01(z_bounds: (f64, f64), z: f64) -> f64 {
+    (z.clamp(-1., 1.).acos() - z_bounds.0.acos()) / (z_bounds.1.acos() - z_bounds.0.acos())
+}
+
+fn float_01_to_left_index(float_01: f64, vec_len: usize) -> (usize, f64) {
+    let float_index = (vec_len - 1) as f64 * float_01.clamp(0., 1.);
+    let index = (float_index as usize).clamp(0, vec_len - 2);
+    let t = float_index - index as f64;
+    (index, t)
+}
+
+// Unwraps `angle` to within half a turn of `reference`.
+fn unwrap_angle(angle: f64, reference: f64) -> f64 {
+    let turns = ((reference - angle) / TAU).round();
+    angle + turns * TAU
+}
+
+impl WormholeDirectionCache {
+    pub fn compute_new(
+        cache_size: (usize, usize),
+        distance_bounds: (f64, f64),
+        z_bounds: (f64, f64),
+        throat_radius: f64,
+        config: RayCastConfig,
+    ) -> Result<Self, UnsupportedIntegrator> {
+        let mut final_dirs = Vec::new();
+        for d in 0..cache_size.0 {
+            let dist = index_to_float(distance_bounds, d, cache_size.0);
+            println!("Generating dist: {}", dist);
+            for z in 0..cache_size.1 {
+                let z = index_to_z(z_bounds, z, cache_size.1);
+                let response = cast_wormhole_ray_response(z, dist, throat_radius, config)?;
+                let universe = response.universe;
+                final_dirs.push(response.final_dir.map(|v| (f64::atan2(v.x, v.z), universe)));
+            }
+        }
+        Ok(WormholeDirectionCache {
+            cache_size,
+            distance_bounds,
+            z_bounds,
+            throat_radius,
+            config,
+            final_dirs,
+        })
+    }
+
+    fn index(&self, d: usize, z: usize) -> usize {
+        d * self.cache_size.1 + z
+    }
+
+    // Returns None if any of the surrounding samples circled the throat. Samples on different
+    // sides of the throat can't be blended, so near the edge of the throat this returns the
+    // closest sample instead.
+    pub fn get_final_dir(&self, d_01: f64, z: f64) -> Option<(DVec3, Universe)> {
+        let z_01 = z_to_float_01(self.z_bounds, z);
+        let (d, d_t) = float_01_to_left_index(d_01, self.cache_size.0);
+        let (z, z_t) = float_01_to_left_index(z_01, self.cache_size.1);
+
+        let mut corners = [(0., Universe::Near, 0.); 4];
+        for (corner, entry) in corners.iter_mut().enumerate() {
+            let weight = |bit: usize, t: f64| match corner & bit == 0 {
+                true => 1. - t,
+                false => t,
+            };
+            let (angle, universe) =
+                self.final_dirs[self.index(d + (corner & 1), z + (corner >> 1))]?;
+            *entry = (angle, universe, weight(1, d_t) * weight(2, z_t));
+        }
+
+        let closest = corners
+            .iter()
+            .copied()
+            .reduce(|a, b| match a.2 >= b.2 {
+                true => a,
+                false => b,
+            })
+            .unwrap();
+        let universe = closest.1;
+        let angle = match corners.iter().all(|c| c.1 == universe) {
+            true => corners
+                .iter()
+                .map(|c| c.2 * unwrap_angle(c.0, closest.0))
+                .sum(),
+            false => closest.0,
+        };
+        Some((DVec3::new(angle.sin(), 0., angle.cos()), universe))
+    }
+
+    // Packs the cache for the web renderer. Rays that circle the throat take the previous sample,
+    // and angles are unwrapped along z so the texture can be sampled linearly.
+    pub fn direction_table(&self) -> WormholeDirectionTable {
+        let mut final_angles = Vec::new();
+        let mut far_side = Vec::new();
+        for d in 0..self.cache_size.0 {
+            let mut previous = (0., Universe::Near);
+            for z in 0..self.cache_size.1 {
+                let (angle, universe) = self.final_dirs[self.index(d, z)]
+                    .map(|(angle, universe)| (unwrap_angle(angle, previous.0), universe))
+                    .unwrap_or(previous);
+                previous = (angle, universe);
+                final_angles.push(angle as f32);
+                far_side.push(match universe {
+                    Universe::Near => 0.,
+                    Universe::Far => 1.,
+                });
+            }
+        }
+        WormholeDirectionTable {
+            dimensions: [self.cache_size.1 as u32, self.cache_size.0 as u32],
+            distance_bounds: [self.distance_bounds.0 as f32, self.distance_bounds.1 as f32],
+            z_bounds: [self.z_bounds.0 as f32, self.z_bounds.1 as f32],
+            throat_radius: self.throat_radius as f32,
+            final_angles,
+            far_side,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::path_integration2::{
+        path::cast_wormhole_ray_response, ray_cast_config::RayCastConfig,
+        structs::wormhole_field::Universe,
+    };
+
+    use super::WormholeDirectionCache;
+
+    #[test]
+    fn wormhole_interpolation_error() {
+        let distance_bounds = (5., 20.);
+        let z_bounds = (0., 1.);
+        let throat_radius = 1.;
+        let cache = WormholeDirectionCache::compute_new(
+            (17, 129),
+            distance_bounds,
+            z_bounds,
+            throat_radius,
+            RayCastConfig::default(),
+        )
+        .unwrap();
+
+        let mut checked = 0;
+        for d_01 in [0.1, 0.4, 0.85] {
+            let dist = (distance_bounds.1 - distance_bounds.0) * d_01 + distance_bounds.0;
+            for z in [0.2_f64, 0.5, 0.9, 0.95, 0.999, 0.9999] {
+                // Like at a photon sphere, the deflection diverges at the edge of the throat.
+                let b = (1. - z * z).sqrt() * dist;
+                if (b / throat_radius - 1.).abs() < 0.5 {
+                    continue;
+                }
+                let response =
+                    cast_wormhole_ray_response(z, dist, throat_radius, cache.config).unwrap();
+                let (actual, universe) = cache.get_final_dir(d_01, z).unwrap();
+                assert_eq!(universe, response.universe, "dist: {}, z: {}", dist, z);
+                checked += 1;
+                let expected = response.final_dir.unwrap();
+                assert!(
+                    (expected - actual).length() < 0.05,
+                    "dist: {}, z: {}\nexpected: {}\nactual: {}",
+                    dist,
+                    z,
+                    expected,
+                    actual
+                );
+            }
+        }
+        assert!(checked > 10);
+
+        // Looking straight at the throat shows the far side.
+        assert_eq!(cache.get_final_dir(0.5, 1.).unwrap().1, Universe::Far);
+        assert_eq!(cache.get_final_dir(0.5, 0.).unwrap().1, Universe::Near);
+    }
+
+    #[test]
+    fn direction_table_marks_far_side() {
+        let cache = WormholeDirectionCache::compute_new(
+            (2, 65),
+            (5., 20.),
+            (0., 1.),
+            1.,
+            RayCastConfig::default(),
+        )
+        .unwrap();
+        let table = cache.direction_table();
+        assert_eq!(table.final_angles.len(), 2 * 65);
+        assert_eq!(table.far_side.len(), 2 * 65);
+        // z = 0 misses the throat and z = 1 goes straight through, for every camera distance.
+        for d in 0..2 {
+            assert_eq!(table.far_side[d * 65], 0.);
+            assert_eq!(table.far_side[d * 65 + 64], 1.);
+        }
+    }
+
+    #[test]
+    fn serialization() {
+        let cache = WormholeDirectionCache::compute_new(
+            (2, 9),
+            (5., 20.),
+            (0., 1.),
+            1.,
+            RayCastConfig::default(),
+        )
+        .unwrap();
+
+        let serialized = serde_json::to_string(&cache);
+
+        assert!(serialized.is_ok());
+
+        let deserialized: Result<WormholeDirectionCache, serde_json::Error> =
+            serde_json::from_str(serialized.unwrap().as_str());
+
+        assert!(deserialized.is_ok());
+
+        let deserialized = deserialized.unwrap();
+        assert_eq!(deserialized, cache);
+    }
+}
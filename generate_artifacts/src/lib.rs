@@ -1,6 +1,11 @@
-mod artifact_utils;
+pub mod artifact_container;
+pub mod artifact_utils;
 pub mod black_hole_cache;
+pub mod cache_spec;
+pub mod checkpoint;
+pub mod cpu_renderer;
 pub mod factory;
 pub mod final_direction_cache;
 pub mod path_distance_cache;
 pub mod path_integration2;
+pub mod refinement;
@@ -1,12 +1,17 @@
-use std::f64::consts::TAU;
-
-
+use std::{f64::consts::TAU, fs, io, path::Path};
 
 use approximation_utils::analyze_approximations;
-use artifact_utils::get_or_generate_file;
-
-
 use distance_velocity_utils::analyze_distance_velocity;
+use generate_artifacts::{
+    artifact_utils::{get_or_generate_artifact, get_or_generate_file, inputs_hash, Regeneration},
+    black_hole_cache::{BlackHoleCache, BlackHoleCacheParams},
+    cache_spec::{DirectionCacheSpec, DistanceCacheSpec},
+    final_direction_cache::wormhole_direction_cache::{
+        WormholeDirectionCache, WORMHOLE_CACHE_SIZE,
+    },
+    path_distance_cache::g_factor_cache::GFactorCache,
+    path_integration2::{ray_cast_config::RayCastConfig, structs::field::FieldModel},
+};
 use path_utils::analyze_paths;
 use serde::{Deserialize, Serialize};
 use view_bounds_utils::analyze_view_bounds;
@@ -14,15 +19,13 @@ use wire_structs::sampler::approximation_function::ApproximationFunction;
 use wire_structs::sampler::clean_approximation_functions::linearize_min_dist;
 use wire_structs::sampler::dimension_params::DimensionParams;
 
-use wire_structs::sampler::distance_velocity_paths::DistanceVelocityPaths;
+use wire_structs::sampler::distance_velocity_paths::{
+    DistanceVelocityParams, DistanceVelocityPaths,
+};
 use wire_structs::sampler::render_params::RenderParams;
 use wire_structs::sampler::simple_path_generator;
 use wire_structs::sampler::view_angle_parameter_cache::ViewAngleParameterCache;
 use wire_structs::sampler::view_bound::ViewBound;
-mod factory;
-mod final_direction_cache;
-mod path_distance_cache;
-mod path_integration2;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DirectionTestPoint {
@@ -33,19 +36,37 @@ pub struct DirectionTestPoint {
 
 const ALL_VIEW_SAMPLE_PATH: &str = "generate_artifacts/output/artifact/all_view.txt";
 const APPROX_FUNCTION_PATH: &str = "generate_artifacts/output/artifact/approx_function.txt";
-const VIEW_BOUNDS_PATH: &str = "generate_artifacts/output/artifact/view_bounds.txt";
+const VIEW_BOUNDS_PATH: &str = "generate_artifacts/output/artifact/view_bounds.bin";
 
-const ANGLE_CACHE_PATH: &str = "generate_artifacts/output/artifact/angle_cache.txt";
+const ANGLE_CACHE_PATH: &str = "generate_artifacts/output/artifact/angle_cache.bin";
 const DISTANCE_VELOCITY_CACHE_PATH: &str =
     "generate_artifacts/output/artifact/distance_velocity.txt";
+const TIME_DELAY_PATH: &str = "generate_artifacts/output/artifact/time_delay.bin";
+const G_FACTOR_PATH: &str = "generate_artifacts/output/artifact/g_factor.bin";
+const BLACK_HOLE_CACHE_PATH: &str = "generate_artifacts/output/artifact/black_hole_cache.bin";
+const BLACK_HOLE_CACHE_CHECKPOINT_DIR: &str =
+    "generate_artifacts/output/checkpoint/black_hole_cache";
+const WORMHOLE_DIRECTION_PATH: &str = "generate_artifacts/output/artifact/wormhole_directions.bin";
+
+// Matches the disc the web renderer draws.
+const DISC_BOUNDS: (f64, f64) = (2., 12.);
+const TIME_DELAY_DISC_SAMPLES: usize = 1 << 5;
+const G_FACTOR_DISC_SAMPLES: usize = 1 << 5;
+const WORMHOLE_THROAT_RADIUS: f64 = 1.;
 
 mod approximation_utils;
-mod artifact_utils;
 mod combined_approximation_utils;
 mod distance_velocity_utils;
 mod path_utils;
 mod view_bounds_utils;
 fn main() {
+    // With `--frozen`, artifacts that don't match their inputs are an error instead of regenerated.
+    let regeneration = if std::env::args().any(|arg| arg == "--frozen") {
+        Regeneration::Frozen
+    } else {
+        Regeneration::Allowed
+    };
+
     let dist = DimensionParams {
         size: 26,
         bounds: [5., 30.],
@@ -64,55 +85,175 @@ fn main() {
         black_hole_radius: 1.5,
         fov_degrees: 60.0,
     };
+    let dist_vel_params = DistanceVelocityParams::default();
     let dist_vel_paths;
     {
-        dist_vel_paths = get_or_generate_file(DISTANCE_VELOCITY_CACHE_PATH, &|| {
-            DistanceVelocityPaths::new()
-        });
+        dist_vel_paths = get_or_generate_file(
+            DISTANCE_VELOCITY_CACHE_PATH,
+            &dist_vel_params,
+            regeneration,
+            &|| DistanceVelocityPaths::new(&dist_vel_params),
+        );
     }
+    // Everything the sampled paths, and the approximations built from them, depend on, including
+    // the distance-velocity paths they start from.
+    let sample_inputs = (
+        dist,
+        view,
+        angle,
+        render_params,
+        inputs_hash(&dist_vel_params),
+    );
 
     let all_paths_sample;
     {
-        all_paths_sample = get_or_generate_file(ALL_VIEW_SAMPLE_PATH, &|| {
-            simple_path_generator::generate_paths(
-                &dist,
-                &view,
-                &angle,
-                &render_params,
-                &dist_vel_paths,
-            )
-        });
+        all_paths_sample =
+            get_or_generate_file(ALL_VIEW_SAMPLE_PATH, &sample_inputs, regeneration, &|| {
+                simple_path_generator::generate_paths(
+                    &dist,
+                    &view,
+                    &angle,
+                    &render_params,
+                    &dist_vel_paths,
+                )
+            });
     }
 
     let all_approx;
     {
-        all_approx = get_or_generate_file(APPROX_FUNCTION_PATH, &|| {
-            let mut func = all_paths_sample
-                .iter()
-                .map(|p| ApproximationFunction::generate(p, &angles, p.view))
-                .collect::<Vec<ApproximationFunction>>();
-            let width = all_paths_sample.len() / dist.size;
-            for d in 0..dist.size {
-                linearize_min_dist(
-                    &all_paths_sample[d * width..(d + 1) * width],
-                    &mut func[d * width..(d + 1) * width],
-                );
-            }
-
-            func
-        });
+        all_approx =
+            get_or_generate_file(APPROX_FUNCTION_PATH, &sample_inputs, regeneration, &|| {
+                let mut func = all_paths_sample
+                    .iter()
+                    .map(|p| ApproximationFunction::generate(p, &angles, p.view))
+                    .collect::<Vec<ApproximationFunction>>();
+                let width = all_paths_sample.len() / dist.size;
+                for d in 0..dist.size {
+                    linearize_min_dist(
+                        &all_paths_sample[d * width..(d + 1) * width],
+                        &mut func[d * width..(d + 1) * width],
+                    );
+                }
+
+                func
+            });
     };
 
     let view_bounds;
     {
-        view_bounds = get_or_generate_file(VIEW_BOUNDS_PATH, &|| ViewBound::generate(&all_approx));
+        view_bounds =
+            get_or_generate_artifact(VIEW_BOUNDS_PATH, &sample_inputs, regeneration, &|| {
+                ViewBound::generate(&all_approx)
+            });
     }
     let angle_cache;
     {
-        angle_cache = get_or_generate_file(ANGLE_CACHE_PATH, &|| {
-            ViewAngleParameterCache::new(dist.size as u32, &all_approx)
+        angle_cache =
+            get_or_generate_artifact(ANGLE_CACHE_PATH, &sample_inputs, regeneration, &|| {
+                ViewAngleParameterCache::new(dist.size as u32, &all_approx)
+            });
+    }
+
+    let distance_bounds = (dist.bounds[0] as f64, dist.bounds[1] as f64);
+    let black_hole_params = BlackHoleCacheParams {
+        distance_bounds,
+        black_hole_radius: render_params.black_hole_radius as f64,
+        disc_bounds: DISC_BOUNDS,
+        direction_spec: DirectionCacheSpec::default(),
+        distance_spec: DistanceCacheSpec::default(),
+        model: FieldModel::PseudoForce,
+        config: RayCastConfig::default(),
+    };
+    // The web renderer reads this directly; the time delays and the g factors are read off its
+    // distance cache.
+    let black_hole_cache;
+    {
+        black_hole_cache = get_or_generate_artifact(
+            BLACK_HOLE_CACHE_PATH,
+            &black_hole_params,
+            regeneration,
+            &|| {
+                let checkpoint_dir = Path::new(BLACK_HOLE_CACHE_CHECKPOINT_DIR);
+                let cache =
+                    BlackHoleCache::compute_checkpointed(&black_hole_params, checkpoint_dir)
+                        .unwrap_or_else(|error| panic!("{}", error));
+                match fs::remove_dir_all(checkpoint_dir) {
+                    Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                        println!("Couldn't remove {}: {}", checkpoint_dir.display(), error)
+                    }
+                    _ => {}
+                }
+                cache
+            },
+        );
+    }
+    let distance_cache_inputs = (
+        black_hole_params.distance_spec.clone(),
+        black_hole_params.distance_bounds,
+        black_hole_params.black_hole_radius,
+        black_hole_params.model,
+        black_hole_params.config,
+        black_hole_params.disc_bounds,
+    );
+    let _time_delay;
+    {
+        let (spec, distance_bounds, radius, field, config, disc_bounds) =
+            distance_cache_inputs.clone();
+        let inputs = (
+            spec,
+            distance_bounds,
+            radius,
+            field,
+            config,
+            disc_bounds,
+            TIME_DELAY_DISC_SAMPLES,
+        );
+        _time_delay = get_or_generate_artifact(TIME_DELAY_PATH, &inputs, regeneration, &|| {
+            black_hole_cache
+                .distance_cache
+                .time_delay_table(TIME_DELAY_DISC_SAMPLES)
         });
     }
+    let _g_factor;
+    {
+        let (spec, distance_bounds, radius, field, config, disc_bounds) = distance_cache_inputs;
+        let inputs = (
+            spec,
+            distance_bounds,
+            radius,
+            field,
+            config,
+            disc_bounds,
+            G_FACTOR_DISC_SAMPLES,
+        );
+        _g_factor = get_or_generate_artifact(G_FACTOR_PATH, &inputs, regeneration, &|| {
+            GFactorCache::table(&black_hole_cache.distance_cache, G_FACTOR_DISC_SAMPLES)
+        });
+    }
+
+    let _wormhole_directions;
+    {
+        let inputs = (
+            WORMHOLE_CACHE_SIZE,
+            distance_bounds,
+            (0., 1.),
+            WORMHOLE_THROAT_RADIUS,
+            RayCastConfig::default(),
+        );
+        _wormhole_directions =
+            get_or_generate_artifact(WORMHOLE_DIRECTION_PATH, &inputs, regeneration, &|| {
+                let (cache_size, distance_bounds, angle_bounds, throat_radius, config) = inputs;
+                WormholeDirectionCache::compute_new(
+                    cache_size,
+                    distance_bounds,
+                    angle_bounds,
+                    throat_radius,
+                    config,
+                )
+                .unwrap_or_else(|error| panic!("{}", error))
+                .direction_table()
+            });
+    }
 
     analyze_distance_velocity(&dist_vel_paths, &dist, &angle);
     analyze_approximations(&all_paths_sample, &all_approx, &dist, &angle);
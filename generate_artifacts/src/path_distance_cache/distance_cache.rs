@@ -1,124 +1,318 @@
-use std::f64::consts::TAU;
+use std::{f64::consts::TAU, path::Path};
 
 use serde::{Deserialize, Serialize};
+use wire_structs::sampler::time_delay_table::TimeDelayTable;
 
-
-
-use super::{
-    fixed_distance_distance_cache::{FixedDistanceDistanceCache, DISTANCE_CACHE_SIZE},
-    fixed_distance_fixed_angle_distance_cache::{
-        ANGLE_DISTANCE_CACHE_SIZE,
-    },
+use crate::{
+    cache_spec::{DistanceCacheSpec, SamplingCurve},
+    checkpoint::{checkpointed_map, CheckpointError},
+    path_integration2::{ray_cast_config::RayCastConfig, structs::field::FieldModel},
 };
 
+use super::fixed_distance_distance_cache::FixedDistanceDistanceCache;
+
 pub const ALL_DISTANCE_CACHE_SIZE: usize = 1 << 5;
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct DistanceCache {
     pub cache_size: (usize, usize, usize),
     pub distance_bounds: (f64, f64),
     pub black_hole_radius: f64,
+    #[serde(default)]
+    pub model: FieldModel,
+    #[serde(default)]
+    pub config: RayCastConfig,
     pub disc_bounds: (f64, f64),
+    #[serde(default)]
+    pub spec: DistanceCacheSpec,
     pub distance_angle_to_z_to_distance: Vec<FixedDistanceDistanceCache>,
 }
 
-fn float_01_to_left_index(float_01: f64, vec_len: usize) -> (usize, f64) {
-    let float_index = (vec_len - 1) as f64 * float_01;
-    let index = (float_index as usize).clamp(0, vec_len - 2);
-    let t = float_index - index as f64;
-    (index, t)
+// The disc distances resampled for the renderers, which look them up on evenly spaced camera
+// distance, angle and z axes, with z relative to the bounds for the camera distance and angle.
+// Rays that never reach the angle get a distance of 0, which is never on the disc.
+#[derive(Debug, PartialEq)]
+pub struct DistanceTexture {
+    // (z, angle, camera distance) sample counts, z varying fastest in `distances`.
+    pub dimensions: (usize, usize, usize),
+    pub camera_distances: Vec<f64>,
+    // Per camera distance and angle, angle varying fastest.
+    pub z_bounds: Vec<(f64, f64)>,
+    pub distances: Vec<f64>,
+}
+
+impl DistanceTexture {
+    // Where the texel's ray starts, for the `index`-th entry of `distances`.
+    pub fn z(&self, index: usize) -> f64 {
+        let width = self.dimensions.0;
+        let bounds = self.z_bounds[index / width];
+        let z_01 = SamplingCurve::Linear.index_to_float_01(index % width, width);
+        (bounds.1 - bounds.0) * z_01 + bounds.0
+    }
+
+    pub fn camera_distance(&self, index: usize) -> f64 {
+        self.camera_distances[index / (self.dimensions.0 * self.dimensions.1)]
+    }
 }
-fn index_to_float_01(index: usize, vec_len: usize) -> f64 {
-    let float_01 = (index as f64) / (vec_len - 1) as f64;
-    return float_01.clamp(0., 1.);
+
+fn lerp_rows(left: &[f32], right: &[f32], t: f64) -> Vec<f32> {
+    let t = t as f32;
+    left.iter()
+        .zip(right)
+        .map(|(left, right)| t * right + (1. - t) * left)
+        .collect()
 }
 impl DistanceCache {
     pub fn compute_new(
-        _cache_size: (usize, usize, usize),
+        spec: DistanceCacheSpec,
         distance_bounds: (f64, f64),
         black_hole_radius: f64,
+        model: FieldModel,
+        config: RayCastConfig,
         disc_bounds: (f64, f64),
     ) -> Self {
-        let cache_size = (
-            ALL_DISTANCE_CACHE_SIZE,
-            DISTANCE_CACHE_SIZE,
-            ANGLE_DISTANCE_CACHE_SIZE,
+        // Can't fail without checkpoints to read or write.
+        Self::compute(
+            spec,
+            distance_bounds,
+            black_hole_radius,
+            model,
+            config,
+            disc_bounds,
+            None,
+        )
+        .unwrap()
+    }
+
+    // Like `compute_new`, but each distance's slice is checkpointed in `checkpoint_dir`, so calling
+    // this again with the same arguments after an interruption resumes from the finished slices.
+    pub fn compute_checkpointed(
+        spec: DistanceCacheSpec,
+        distance_bounds: (f64, f64),
+        black_hole_radius: f64,
+        model: FieldModel,
+        config: RayCastConfig,
+        disc_bounds: (f64, f64),
+        checkpoint_dir: &Path,
+    ) -> Result<Self, CheckpointError> {
+        Self::compute(
+            spec,
+            distance_bounds,
+            black_hole_radius,
+            model,
+            config,
+            disc_bounds,
+            Some(checkpoint_dir),
+        )
+    }
+
+    fn compute(
+        spec: DistanceCacheSpec,
+        distance_bounds: (f64, f64),
+        black_hole_radius: f64,
+        model: FieldModel,
+        config: RayCastConfig,
+        disc_bounds: (f64, f64),
+        checkpoint_dir: Option<&Path>,
+    ) -> Result<Self, CheckpointError> {
+        let distances: Vec<f64> = (0..spec.distance.size)
+            .map(|i| spec.distance.index_to_value(i, distance_bounds))
+            .collect();
+        let parameters = (
+            spec.clone(),
+            distance_bounds,
+            black_hole_radius,
+            model,
+            config,
+            disc_bounds,
         );
-        let mut distance_angle_to_z_to_distance = Vec::new();
-
-        for i in 0..cache_size.0 {
-            let float_01 = index_to_float_01(i, cache_size.0);
-            let distance = (distance_bounds.1 - distance_bounds.0) * float_01 + distance_bounds.0;
-            println!("Generating: {:?}", (distance));
-            let angle_to_z_to_distance = FixedDistanceDistanceCache::compute_new(
-                (cache_size.1, cache_size.2),
-                distance,
-                black_hole_radius,
-                disc_bounds,
-            );
-            distance_angle_to_z_to_distance.push(angle_to_z_to_distance);
-        }
-        DistanceCache {
-            cache_size,
+        let distance_angle_to_z_to_distance =
+            checkpointed_map(checkpoint_dir, &parameters, &distances, |distance| {
+                println!("Generating: {:?}", (distance));
+                FixedDistanceDistanceCache::compute_new(
+                    &spec,
+                    distance,
+                    black_hole_radius,
+                    model,
+                    config,
+                    disc_bounds,
+                )
+            })?;
+        Ok(DistanceCache {
+            cache_size: spec.cache_size(),
             distance_bounds,
             black_hole_radius,
+            model,
+            config,
             disc_bounds,
+            spec,
             distance_angle_to_z_to_distance,
-        }
+        })
     }
 
     pub fn get_z_bounds(&self, distance_01: f64, angle: f64) -> (f64, f64) {
-        let (index, t) =
-            float_01_to_left_index(distance_01, self.distance_angle_to_z_to_distance.len());
+        let caches = &self.distance_angle_to_z_to_distance;
         let angle_01 = angle / TAU;
-        let left = &self.distance_angle_to_z_to_distance[index].get_z_bounds(angle_01);
-        let right = &self.distance_angle_to_z_to_distance[index + 1].get_z_bounds(angle_01);
-        let lower_z_bound = right.0 * t + (1. - t) * left.0;
-        let upper_z_bound = right.1 * t + (1. - t) * left.1;
-        (lower_z_bound, upper_z_bound)
+        let z_bounds = |i: usize| caches[i].get_z_bounds(angle_01);
+        let distance = &self.spec.distance;
+        (
+            distance.interpolate(|i| z_bounds(i).0, distance_01),
+            distance.interpolate(|i| z_bounds(i).1, distance_01),
+        )
     }
 
     pub fn get_dist(&self, distance_01: f64, angle: f64, z: f64) -> Option<f64> {
+        let caches = &self.distance_angle_to_z_to_distance;
         let angle_01 = angle / TAU;
-        let (index, t) =
-            float_01_to_left_index(distance_01, self.distance_angle_to_z_to_distance.len());
-        let left = &self.distance_angle_to_z_to_distance[index];
-        let right = &self.distance_angle_to_z_to_distance[index + 1];
-        Some(
-            t * right.get_dist(angle_01, z).unwrap()
-                + (1. - t) * left.get_dist(angle_01, z).unwrap(),
-        )
+        self.spec
+            .distance
+            .try_interpolate(|i| caches[i].get_dist(angle_01, z), distance_01)
+    }
+
+    pub fn get_time(&self, distance_01: f64, angle: f64, z: f64) -> Option<f64> {
+        let caches = &self.distance_angle_to_z_to_distance;
+        let angle_01 = angle / TAU;
+        self.spec
+            .distance
+            .try_interpolate(|i| caches[i].get_time(angle_01, z), distance_01)
+    }
+
+    // Has the cache's resolution along every axis.
+    pub fn texture(&self) -> DistanceTexture {
+        let (depth, height, width) = self.cache_size;
+        let mut camera_distances = Vec::new();
+        let mut z_bounds = Vec::new();
+        let mut distances = Vec::new();
+        for d in 0..depth {
+            let distance_01 = SamplingCurve::Linear.index_to_float_01(d, depth);
+            camera_distances.push(
+                (self.distance_bounds.1 - self.distance_bounds.0) * distance_01
+                    + self.distance_bounds.0,
+            );
+            for a in 0..height {
+                let angle = TAU * SamplingCurve::Linear.index_to_float_01(a, height);
+                let bounds = self.get_z_bounds(distance_01, angle);
+                z_bounds.push(bounds);
+                for i in 0..width {
+                    let z_01 = SamplingCurve::Linear.index_to_float_01(i, width);
+                    let z = (bounds.1 - bounds.0) * z_01 + bounds.0;
+                    distances.push(self.get_dist(distance_01, angle, z).unwrap_or(0.));
+                }
+            }
+        }
+        DistanceTexture {
+            dimensions: (width, height, depth),
+            camera_distances,
+            z_bounds,
+            distances,
+        }
+    }
+
+    // Resamples the travel times onto a (disc radius, angle, camera distance) grid for the disc
+    // shader, which knows where a ray hits the disc but not its z. The angle and camera distance
+    // axes have as many samples as the cache, but evenly spaced, since the shader doesn't know the
+    // cache's sampling curves; the disc radius axis has `disc_samples` evenly spaced samples over
+    // the disc bounds. Delays are relative to the camera distance.
+    //
+    // Angles without any samples repeat the previous angle's delays, so caches built before times
+    // were tracked give no delay at all.
+    pub fn time_delay_table(&self, disc_samples: usize) -> TimeDelayTable {
+        let sampled: Vec<Vec<Vec<f32>>> = self
+            .distance_angle_to_z_to_distance
+            .iter()
+            .map(|angle_to_z_to_distance| {
+                let mut previous = vec![0.; disc_samples];
+                let mut rows = Vec::new();
+                for z_to_distance in &angle_to_z_to_distance.angle_to_z_to_distance {
+                    let row: Vec<f32> = (0..disc_samples)
+                        .map(|i| {
+                            let float_01 = SamplingCurve::Linear.index_to_float_01(i, disc_samples);
+                            let dist = (self.disc_bounds.1 - self.disc_bounds.0) * float_01
+                                + self.disc_bounds.0;
+                            match z_to_distance.get_time_at_dist(dist) {
+                                Some(time) => (time - z_to_distance.camera_distance) as f32,
+                                None => previous[i],
+                            }
+                        })
+                        .collect();
+                    previous = row.clone();
+                    rows.push(row);
+                }
+                rows
+            })
+            .collect();
+
+        let distance_count = self.distance_angle_to_z_to_distance.len();
+        let angle_count = self.distance_angle_to_z_to_distance[0]
+            .angle_to_z_to_distance
+            .len();
+        let angle_row = |distance_index: usize, angle_01: f64| {
+            let angle_axis = &self.distance_angle_to_z_to_distance[distance_index].angle_axis;
+            let (index, t) = angle_axis.float_01_to_left_index(angle_01);
+            let rows = &sampled[distance_index];
+            lerp_rows(&rows[index], &rows[index + 1], t)
+        };
+        let mut delays = Vec::new();
+        for d in 0..distance_count {
+            let distance_01 = SamplingCurve::Linear.index_to_float_01(d, distance_count);
+            let (distance_index, distance_t) =
+                self.spec.distance.float_01_to_left_index(distance_01);
+            for a in 0..angle_count {
+                let angle_01 = SamplingCurve::Linear.index_to_float_01(a, angle_count);
+                let row = lerp_rows(
+                    &angle_row(distance_index, angle_01),
+                    &angle_row(distance_index + 1, angle_01),
+                    distance_t,
+                );
+                delays.extend_from_slice(&row);
+            }
+        }
+        TimeDelayTable {
+            dimensions: [
+                disc_samples as u32,
+                angle_count as u32,
+                distance_count as u32,
+            ],
+            disc_bounds: [self.disc_bounds.0 as f32, self.disc_bounds.1 as f32],
+            distance_bounds: [self.distance_bounds.0 as f32, self.distance_bounds.1 as f32],
+            delays,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{f64::consts::TAU};
+    use std::{f64::consts::TAU, fs, path::Path};
 
     use serde::{Deserialize, Serialize};
     use test_utils::plot_trajectories;
 
     use crate::{
-        path_distance_cache::{
-            fixed_distance_distance_cache::DISTANCE_CACHE_SIZE,
-            fixed_distance_fixed_angle_distance_cache::{ANGLE_DISTANCE_CACHE_SIZE, MIN_ANGLE},
+        cache_spec::{AxisSpec, DistanceCacheSpec, SamplingCurve},
+        checkpoint::CheckpointError,
+        path_distance_cache::fixed_distance_fixed_angle_distance_cache::MIN_ANGLE,
+        path_integration2::{
+            path::cast_ray_steps_response, ray_cast_config::RayCastConfig,
+            structs::field::FieldModel,
         },
-        path_integration2::path::cast_ray_steps_response,
     };
 
-    use super::{DistanceCache, FixedDistanceDistanceCache, ALL_DISTANCE_CACHE_SIZE};
+    use super::{DistanceCache, FixedDistanceDistanceCache};
     #[test]
     fn full_test_error() {
-        let cache_size = (
-            ALL_DISTANCE_CACHE_SIZE,
-            DISTANCE_CACHE_SIZE,
-            ANGLE_DISTANCE_CACHE_SIZE,
-        );
+        let spec = DistanceCacheSpec::default();
+        let cache_size = spec.cache_size();
         let black_hole_radius = 1.5;
         let distance = (5., 20.0);
         let max_disc_radius = (1.5, 12.0);
         let mut lines = Vec::new();
-        let cache =
-            DistanceCache::compute_new(cache_size, distance, black_hole_radius, max_disc_radius);
+        let cache = DistanceCache::compute_new(
+            spec,
+            distance,
+            black_hole_radius,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+            max_disc_radius,
+        );
         let distance_iterations = 2 * cache_size.0;
         let angle_iterations = 2 * cache_size.1;
         let z_iterations = 2 * cache_size.2;
@@ -143,8 +337,14 @@ mod tests {
                         z
                     );
                     let approx_dist = approx_dist.unwrap();
-                    let true_path =
-                        cast_ray_steps_response(z, dist, cache.black_hole_radius).get_angle_dist();
+                    let true_path = cast_ray_steps_response(
+                        z,
+                        dist,
+                        cache.black_hole_radius,
+                        cache.model,
+                        cache.config,
+                    )
+                    .get_angle_dist();
                     let true_dist = true_path.get_dist(angle);
                     if true_dist.is_none() {
                         continue;
@@ -183,14 +383,20 @@ mod tests {
 
     #[test]
     fn serialization() {
-        let cache_size = (16, 16);
         let distance = 5.0;
         let black_hole_radius = 1.5;
         let max_disc_radius = (1.5, 12.0);
+        let spec = DistanceCacheSpec {
+            angle: AxisSpec::linear(16).unwrap(),
+            z: AxisSpec::linear(16).unwrap(),
+            ..DistanceCacheSpec::default()
+        };
         let cache = FixedDistanceDistanceCache::compute_new(
-            cache_size,
+            &spec,
             distance,
             black_hole_radius,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
             max_disc_radius,
         );
 
@@ -208,4 +414,112 @@ mod tests {
         assert!(deserialized.is_ok());
         assert_eq!(cache, deserialized.unwrap());
     }
+
+    #[test]
+    fn honors_spec() {
+        let spec = DistanceCacheSpec {
+            distance: AxisSpec::new(3, SamplingCurve::Power { exponent: 2. }).unwrap(),
+            angle: AxisSpec::new(4, SamplingCurve::Log { scale: 10. }).unwrap(),
+            z: AxisSpec::linear(8).unwrap(),
+            z_refinement: None,
+        };
+        let cache = DistanceCache::compute_new(
+            spec.clone(),
+            (5., 20.),
+            1.5,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+            (3., 6.),
+        );
+        assert_eq!(cache.cache_size, (3, 4, 8));
+        assert_eq!(cache.distance_angle_to_z_to_distance.len(), 3);
+        // The power curve puts the middle distance a quarter of the way along.
+        assert_eq!(
+            cache.distance_angle_to_z_to_distance[1].camera_distance,
+            5. + 15. * 0.25
+        );
+        for fixed_distance in &cache.distance_angle_to_z_to_distance {
+            assert_eq!(fixed_distance.angle_axis, spec.angle);
+            assert_eq!(fixed_distance.angle_to_z_to_distance.len(), 4);
+            // Each angle keeps the z samples whose rays reach it, if there are enough to
+            // interpolate.
+            for fixed_angle in &fixed_distance.angle_to_z_to_distance {
+                let len = fixed_angle.z_to_distance.len();
+                assert!(len == 0 || fixed_angle.z_axis.size == len);
+                assert!(len <= spec.z.size);
+            }
+        }
+
+        let deserialized: DistanceCache =
+            serde_json::from_str(&serde_json::to_string(&cache).unwrap()).unwrap();
+        assert_eq!(deserialized.spec, spec);
+
+        // The delay table is evenly spaced whatever the spec, with the cache's resolution.
+        let table = cache.time_delay_table(5);
+        assert_eq!(table.dimensions, [5, 4, 3]);
+        assert_eq!(table.delays.len(), 5 * 4 * 3);
+
+        // So is the texture, with 0 wherever the rays miss the angle.
+        let texture = cache.texture();
+        assert_eq!(texture.dimensions, (8, 4, 3));
+        assert_eq!(texture.camera_distances, vec![5., 12.5, 20.]);
+        assert_eq!(texture.z_bounds.len(), 4 * 3);
+        assert_eq!(texture.distances.len(), 8 * 4 * 3);
+        for d in 0..3 {
+            for a in 0..4 {
+                for z in 0..8 {
+                    let i = 32 * d + 8 * a + z;
+                    let angle = TAU * a as f64 / 3.;
+                    let expected = cache.get_dist(0.5 * d as f64, angle, texture.z(i));
+                    assert_eq!(texture.distances[i], expected.unwrap_or(0.));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn resumes_from_checkpoints() {
+        let spec = DistanceCacheSpec {
+            distance: AxisSpec::linear(3).unwrap(),
+            angle: AxisSpec::linear(4).unwrap(),
+            z: AxisSpec::linear(8).unwrap(),
+            z_refinement: None,
+        };
+        let compute = |checkpoint_dir: &Path, radius: f64| {
+            DistanceCache::compute_checkpointed(
+                spec.clone(),
+                (5., 20.),
+                radius,
+                FieldModel::Schwarzschild,
+                RayCastConfig::default(),
+                (3., 6.),
+                checkpoint_dir,
+            )
+        };
+        let checkpoint_dir =
+            std::env::temp_dir().join(format!("distance_cache_{}", std::process::id()));
+        let cache = compute(&checkpoint_dir, 1.5).unwrap();
+
+        // A run stopped partway through picks the remaining slices back up.
+        fs::remove_file(checkpoint_dir.join("slice_0001.bin")).unwrap();
+        assert_eq!(compute(&checkpoint_dir, 1.5).unwrap(), cache);
+        assert_eq!(
+            cache,
+            DistanceCache::compute_new(
+                spec.clone(),
+                (5., 20.),
+                1.5,
+                FieldModel::Schwarzschild,
+                RayCastConfig::default(),
+                (3., 6.),
+            )
+        );
+
+        // Slices from a different hole aren't assembled into this one.
+        assert!(matches!(
+            compute(&checkpoint_dir, 1.),
+            Err(CheckpointError::Mismatch { .. })
+        ));
+        fs::remove_dir_all(&checkpoint_dir).unwrap();
+    }
 }
@@ -3,15 +3,17 @@ use std::f64::consts::TAU;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    path_distance_cache::fixed_distance_fixed_angle_distance_cache::{
-        ANGLE_DISTANCE_CACHE_SIZE, MIN_ANGLE,
+    cache_spec::{AxisSpec, DistanceCacheSpec},
+    path_distance_cache::fixed_distance_fixed_angle_distance_cache::MIN_ANGLE,
+    path_integration2::{
+        batch::parallel_map, ray_cast_config::RayCastConfig, ray_summary::RaySummary,
+        structs::field::FieldModel,
     },
-    path_integration2::response::Response,
 };
 
 use super::fixed_distance_fixed_angle_distance_cache::FixedDistanceFixedAngleDistanceCache;
 
-use crate::path_integration2::path::find_optimal_z;
+use crate::path_integration2::path::find_optimal_z_by_summary;
 pub const DISTANCE_CACHE_SIZE: usize = 1 << 5;
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct FixedDistanceDistanceCache {
@@ -19,122 +21,144 @@ pub struct FixedDistanceDistanceCache {
     pub min_z: f64,
     pub camera_distance: f64,
     pub black_hole_radius: f64,
+    #[serde(default)]
+    pub model: FieldModel,
+    #[serde(default)]
+    pub config: RayCastConfig,
     pub disc_bounds: (f64, f64),
+    #[serde(default = "DistanceCacheSpec::default_angle")]
+    pub angle_axis: AxisSpec,
     pub angle_to_z_to_distance: Vec<FixedDistanceFixedAngleDistanceCache>,
 }
 // use this find z values where we don't have to apply anti-aliasing
-fn find_grazing_z(camera_distance: f64, black_hole_radius: f64, target_dist: f64) -> f64 {
+fn find_grazing_z(
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
+    target_dist: f64,
+) -> f64 {
     // if we're too close, any direction could hit the disc.
     if camera_distance < target_dist {
         return -1.;
     }
-    let too_close = move |r: Response| {
-        r.hits_black_hole()
-            || r.path
-                .iter()
-                .map(|p| p.length())
-                .fold(f64::INFINITY, f64::min)
-                < target_dist
-    };
-    find_optimal_z(
+    let too_close = move |r: &RaySummary| r.hits_black_hole() || r.min_distance < target_dist;
+    find_optimal_z_by_summary(
         camera_distance as f32,
         black_hole_radius as f32,
+        model,
+        config,
         (-1., 1.),
         &too_close,
     )
     .0
 }
 
-fn float_01_to_left_index(float_01: f64, vec_len: usize) -> (usize, f64) {
-    let float_index = (vec_len - 1) as f64 * float_01;
-    let index = (float_index as usize).clamp(0, vec_len - 2);
-    let t = float_index - index as f64;
-    (index, t)
-}
-fn index_to_float_01(index: usize, vec_len: usize) -> f64 {
-    let float_01 = (index as f64) / (vec_len - 1) as f64;
-    float_01.clamp(0., 1.)
-}
 impl FixedDistanceDistanceCache {
+    // Uses every axis of `spec` but the distance.
     pub fn compute_new(
-        _cache_size: (usize, usize),
+        spec: &DistanceCacheSpec,
         camera_distance: f64,
         black_hole_radius: f64,
+        model: FieldModel,
+        config: RayCastConfig,
         disc_bounds: (f64, f64),
     ) -> Self {
-        let mut angle_to_z_to_distance = Vec::new();
-
-        for i in 0..DISTANCE_CACHE_SIZE {
-            let float_01 = index_to_float_01(i, DISTANCE_CACHE_SIZE);
-            let angle = (TAU - MIN_ANGLE) * float_01 + MIN_ANGLE;
+        let angles: Vec<f64> = (0..spec.angle.size)
+            .map(|i| spec.angle.index_to_value(i, (MIN_ANGLE, TAU)))
+            .collect();
+        // Runs inline when `DistanceCache` is already generating distances in parallel.
+        let angle_to_z_to_distance = parallel_map(&angles, |&angle| {
             println!("Generating: {:?}", (angle));
-            let z_to_distance_cache = FixedDistanceFixedAngleDistanceCache::compute_new(
-                ANGLE_DISTANCE_CACHE_SIZE,
+            FixedDistanceFixedAngleDistanceCache::compute_new(
+                spec,
                 camera_distance,
                 black_hole_radius,
+                model,
+                config,
                 disc_bounds,
                 angle,
-            );
-            angle_to_z_to_distance.push(z_to_distance_cache);
-        }
-        let _min_z = find_grazing_z(camera_distance, black_hole_radius, disc_bounds.1);
+            )
+        });
+        let _min_z = find_grazing_z(
+            camera_distance,
+            black_hole_radius,
+            model,
+            config,
+            disc_bounds.1,
+        );
         FixedDistanceDistanceCache {
             min_angle: MIN_ANGLE,
             min_z: 0.0,
             camera_distance,
             black_hole_radius,
+            model,
+            config,
             disc_bounds,
+            angle_axis: spec.angle.clone(),
             angle_to_z_to_distance,
         }
     }
 
     pub fn get_z_bounds(&self, angle_01: f64) -> (f64, f64) {
-        let (index, t) = float_01_to_left_index(angle_01, self.angle_to_z_to_distance.len());
-        let left = &self.angle_to_z_to_distance[index];
-        let right = &self.angle_to_z_to_distance[index + 1];
-        let lower_z_bound = right.z_bounds.0 * t + (1. - t) * left.z_bounds.0;
-        let upper_z_bound = right.z_bounds.1 * t + (1. - t) * left.z_bounds.1;
-        (lower_z_bound, upper_z_bound)
+        let caches = &self.angle_to_z_to_distance;
+        (
+            self.angle_axis
+                .interpolate(|i| caches[i].z_bounds.0, angle_01),
+            self.angle_axis
+                .interpolate(|i| caches[i].z_bounds.1, angle_01),
+        )
     }
 
     pub fn get_dist(&self, angle_01: f64, z: f64) -> Option<f64> {
-        let (index, t) = float_01_to_left_index(angle_01, self.angle_to_z_to_distance.len());
-        let left = &self.angle_to_z_to_distance[index];
-        let right = &self.angle_to_z_to_distance[index + 1];
+        let caches = &self.angle_to_z_to_distance;
         let z_bound = self.get_z_bounds(angle_01);
-        let _diff = z_bound.1 - z_bound.0;
         let z_01 = (z - z_bound.0) / (z_bound.1 - z_bound.0);
-        Some(t * right.get_dist(z_01) + (1. - t) * left.get_dist(z_01))
+        self.angle_axis
+            .try_interpolate(|i| caches[i].get_dist(z_01), angle_01)
+    }
+
+    pub fn get_time(&self, angle_01: f64, z: f64) -> Option<f64> {
+        let caches = &self.angle_to_z_to_distance;
+        let z_bound = self.get_z_bounds(angle_01);
+        let z_01 = (z - z_bound.0) / (z_bound.1 - z_bound.0);
+        self.angle_axis
+            .try_interpolate(|i| caches[i].get_time(z_01), angle_01)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{f64::consts::TAU};
+    use std::f64::consts::TAU;
 
     use serde::{Deserialize, Serialize};
     use test_utils::plot_trajectories;
 
     use crate::{
-        path_distance_cache::fixed_distance_fixed_angle_distance_cache::{
-            ANGLE_DISTANCE_CACHE_SIZE, MIN_ANGLE,
+        cache_spec::DistanceCacheSpec,
+        path_distance_cache::fixed_distance_fixed_angle_distance_cache::MIN_ANGLE,
+        path_integration2::{
+            path::cast_ray_steps_response, ray_cast_config::RayCastConfig,
+            structs::field::FieldModel,
         },
-        path_integration2::path::cast_ray_steps_response,
     };
 
-    use super::{FixedDistanceDistanceCache, DISTANCE_CACHE_SIZE};
+    use super::FixedDistanceDistanceCache;
     #[test]
     fn fixed_distance_test_error() {
-        let cache_size = (DISTANCE_CACHE_SIZE, ANGLE_DISTANCE_CACHE_SIZE);
+        let spec = DistanceCacheSpec::default();
+        let cache_size = (spec.angle.size, spec.z.size);
         let distance = 5.0;
         let black_hole_radius = 1.5;
         let max_disc_radius = (1.5, 12.0);
         let mut lines = Vec::new();
 
         let cache = FixedDistanceDistanceCache::compute_new(
-            cache_size,
+            &spec,
             distance,
             black_hole_radius,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
             max_disc_radius,
         );
         let angle_iterations = 2 * cache_size.0;
@@ -155,9 +179,14 @@ mod tests {
                     z
                 );
                 let approx_dist = approx_dist.unwrap();
-                let true_path =
-                    cast_ray_steps_response(z, cache.camera_distance, cache.black_hole_radius)
-                        .get_angle_dist();
+                let true_path = cast_ray_steps_response(
+                    z,
+                    cache.camera_distance,
+                    cache.black_hole_radius,
+                    cache.model,
+                    cache.config,
+                )
+                .get_angle_dist();
                 if true_path.get_max_angle() < angle {
                     continue;
                 }
@@ -192,14 +221,15 @@ mod tests {
 
     #[test]
     fn serialization() {
-        let cache_size = (DISTANCE_CACHE_SIZE, ANGLE_DISTANCE_CACHE_SIZE);
         let distance = 5.0;
         let black_hole_radius = 1.5;
         let max_disc_radius = (1.5, 12.0);
         let cache = FixedDistanceDistanceCache::compute_new(
-            cache_size,
+            &DistanceCacheSpec::default(),
             distance,
             black_hole_radius,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
             max_disc_radius,
         );
 
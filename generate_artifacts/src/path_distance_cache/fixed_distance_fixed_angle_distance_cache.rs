@@ -2,199 +2,254 @@ use std::f64::consts::TAU;
 
 use serde::{Deserialize, Serialize};
 
-use crate::path_integration2::{
-    path::cast_ray_steps_response, path::find_optimal_z, response::Response,
+use crate::{
+    cache_spec::{AxisSpec, DistanceCacheSpec},
+    path_integration2::{
+        path::cast_ray_steps_response,
+        path::{dist_at_angle, find_optimal_z_by_dist_at_angle, find_optimal_z_by_summary},
+        ray_cast_config::RayCastConfig,
+        ray_summary::RaySummary,
+        structs::{
+            drift::{interpolate_rejected, DriftPolicy},
+            field::FieldModel,
+        },
+    },
+    refinement::{refine, sample_axis},
 };
 
 pub const MIN_ANGLE: f64 = TAU * (0.1 / 360.);
 pub const ANGLE_DISTANCE_CACHE_SIZE: usize = 1 << 5;
+
+// Distance and time at which a ray reaches the angle, and what to do about its drift.
+type AngleSample = (f64, f64, Option<DriftPolicy>);
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct FixedDistanceFixedAngleDistanceCache {
     pub camera_distance: f64,
     pub black_hole_radius: f64,
+    #[serde(default)]
+    pub model: FieldModel,
+    #[serde(default)]
+    pub config: RayCastConfig,
     pub disc_bounds: (f64, f64),
     pub z_bounds: (f64, f64),
     pub angle: f64,
     pub z_to_distance: Vec<f64>,
-}
-
-fn float_01_to_index_01(float_01: f64) -> f64 {
-    return float_01.clamp(0., 1.);
-}
-
-fn float_01_to_left_index(float_01: f64, vec_len: usize) -> (usize, f64) {
-    let float_index = (vec_len - 1) as f64 * float_01_to_index_01(float_01);
-    let index = (float_index as usize).clamp(0, vec_len - 2);
-    let t = float_index - index as f64;
-    (index, t)
-}
-fn index_to_float_01(index: usize, vec_len: usize) -> f64 {
-    let float_01 = (index as f64) / (vec_len - 1) as f64;
-    return float_01.clamp(0., 1.);
+    #[serde(default = "DistanceCacheSpec::default_z")]
+    pub z_axis: AxisSpec,
+    // Coordinate time each sample takes to reach the angle; empty for caches built before times
+    // were tracked.
+    #[serde(default)]
+    pub z_to_time: Vec<f64>,
+    // Samples whose conserved quantities drifted past `config.drift_limit`.
+    #[serde(default)]
+    pub drift_flagged_z: Vec<f64>,
 }
 
 impl FixedDistanceFixedAngleDistanceCache {
+    // Only the z axis and refinement of `spec` are used. With a refinement, the z samples start
+    // from `spec.z` and are refined until interpolating the distances is within tolerance. Either
+    // way the cache stores an axis placing the samples it kept.
     pub fn compute_new(
-        _cache_size: usize,
+        spec: &DistanceCacheSpec,
         camera_distance: f64,
         black_hole_radius: f64,
+        model: FieldModel,
+        config: RayCastConfig,
         disc_bounds: (f64, f64),
         angle: f64,
     ) -> Self {
-        let cache_size = ANGLE_DISTANCE_CACHE_SIZE;
-        let z_bounds =
-            find_z_bounds_for_angle(camera_distance, black_hole_radius, disc_bounds, angle);
+        let z_bounds = find_z_bounds_for_angle(
+            camera_distance,
+            black_hole_radius,
+            model,
+            config,
+            disc_bounds,
+            angle,
+        );
 
-        let mut z_to_distance = Vec::new();
-        for i in 0..cache_size {
-            let float_01 = index_to_float_01(i, cache_size);
-            let z = (z_bounds.1 - z_bounds.0) * float_01 + z_bounds.0;
-            let response = cast_ray_steps_response(z, camera_distance, black_hole_radius);
-            let angle_path = response.get_angle_dist();
-            let dist = angle_path.get_dist(angle);
-            if dist.is_none() {
-                continue;
-                panic!(
-                    "Should always hit angle!\nz_range: {:?}\nangle: {}\nz: {}\nmax_angle: {}\nfinal_dist: {}\n",
-                    z_bounds,
-                    angle,
-                    z,
-                    angle_path.get_max_angle(),
-                    angle_path.get_final_dist(),
-                )
-            } else {
-                let dist = dist.unwrap();
-                if i > cache_size - 1 {
-                    assert!(
-                        (dist - disc_bounds.0).abs() < 0.1,
-                        "First ray doesn't hit outer edge!\nActual dist: {}\nDisc bounds: {:?}\nangle: {}\nz: {}\n",
-                        dist,
-                        disc_bounds,
-                        angle,
-                        z,
-                    );
-                } else if i < 0 {
-                    assert!(
-                        (dist - disc_bounds.1).abs() < 0.1,
-                        "Last ray doesn't hit inner edge!\nActual dist: {}\nDisc bounds: {:?}\nangle: {}\nz: {}\n",
-                        dist,
-                        disc_bounds,
-                        angle,
-                        z,
-                    );
+        // None for rays that never reach the angle, which are left out.
+        let sample = |z_01: f64| {
+            let z = (z_bounds.1 - z_bounds.0) * z_01 + z_bounds.0;
+            let response =
+                cast_ray_steps_response(z, camera_distance, black_hole_radius, model, config);
+            let dist = response.get_angle_dist().get_dist(angle)?;
+            Some((
+                dist,
+                response.get_time(angle).unwrap(),
+                config.drift_policy(&response),
+            ))
+        };
+        let samples = match spec.z_refinement {
+            Some(refinement) => refine(&spec.z, refinement, sample, |left, right, middle| {
+                match (left, right, middle) {
+                    (Some(left), Some(right), Some(middle)) => {
+                        (0.5 * (left.0 + right.0) - middle.0).abs()
+                    }
+                    // Where rays stop reaching the angle there's nothing to interpolate.
+                    _ => 0.,
                 }
-                z_to_distance.push(dist);
+            }),
+            None => sample_axis(&spec.z, sample),
+        };
+        let mut samples: Vec<(f64, AngleSample)> = samples
+            .into_iter()
+            .filter_map(|(z_01, sample)| Some((z_01, sample?)))
+            .collect();
+        // Fewer than two samples can't be interpolated, so the cache is left empty.
+        let positions: Vec<f64> = samples.iter().map(|(z_01, _)| *z_01).collect();
+        let z_axis = match AxisSpec::from_positions(&positions) {
+            Ok(positions) => AxisSpec {
+                interpolation: spec.z.interpolation,
+                ..positions
+            },
+            Err(_) => {
+                samples.clear();
+                spec.z.clone()
             }
+        };
+
+        let mut z_to_distance = Vec::new();
+        let mut z_to_time = Vec::new();
+        let mut rejected = Vec::new();
+        let mut drift_flagged_z = Vec::new();
+        for (z_01, (dist, time, drift_policy)) in samples {
+            if drift_policy.is_some() {
+                drift_flagged_z.push((z_bounds.1 - z_bounds.0) * z_01 + z_bounds.0);
+            }
+            z_to_distance.push(dist);
+            z_to_time.push(time);
+            rejected.push(drift_policy == Some(DriftPolicy::Reject));
         }
+        interpolate_rejected(&mut z_to_distance, &rejected, |a, b, t| a + t * (b - a));
+        interpolate_rejected(&mut z_to_time, &rejected, |a, b, t| a + t * (b - a));
         FixedDistanceFixedAngleDistanceCache {
             camera_distance,
             black_hole_radius,
+            model,
+            config,
             disc_bounds,
             z_bounds,
             angle,
             z_to_distance,
+            z_axis,
+            z_to_time,
+            drift_flagged_z,
+        }
+    }
+
+    // None if too few rays reach the angle to interpolate.
+    pub fn get_dist(&self, z_01: f64) -> Option<f64> {
+        if self.z_to_distance.is_empty() {
+            return None;
+        }
+        Some(
+            self.z_axis
+                .interpolate(|i| self.z_to_distance[i], z_01.clamp(0., 1.)),
+        )
+    }
+
+    // Where the i-th sample was cast, which the axis records.
+    pub fn sample_z(&self, index: usize) -> f64 {
+        self.z_axis.index_to_value(index, self.z_bounds)
+    }
+
+    pub fn get_time(&self, z_01: f64) -> Option<f64> {
+        if self.z_to_time.is_empty() {
+            return None;
         }
+        Some(
+            self.z_axis
+                .interpolate(|i| self.z_to_time[i], z_01.clamp(0., 1.)),
+        )
     }
 
-    pub fn get_dist(&self, z_01: f64) -> f64 {
-        let (index, t) = float_01_to_left_index(z_01, self.z_to_distance.len());
-        let left = self.z_to_distance[index];
-        let right = self.z_to_distance[index + 1];
-        right * t + (1. - t) * left
+    // Time taken by the ray that reaches the angle at `dist`. Distances that no sample brackets use
+    // the closest sample.
+    pub fn get_time_at_dist(&self, dist: f64) -> Option<f64> {
+        if self.z_to_time.is_empty() || self.z_to_distance.is_empty() {
+            return None;
+        }
+        for i in 0..(self.z_to_distance.len() - 1) {
+            let (left, right) = (self.z_to_distance[i], self.z_to_distance[i + 1]);
+            if left != right && (left - dist) * (right - dist) <= 0. {
+                let t = (dist - left) / (right - left);
+                return Some(t * self.z_to_time[i + 1] + (1. - t) * self.z_to_time[i]);
+            }
+        }
+        let closest = (0..self.z_to_distance.len())
+            .min_by(|a, b| {
+                let a = (self.z_to_distance[*a] - dist).abs();
+                let b = (self.z_to_distance[*b] - dist).abs();
+                a.partial_cmp(&b).unwrap()
+            })
+            .unwrap();
+        Some(self.z_to_time[closest])
     }
 }
 
 fn find_z_bounds_for_angle(
     camera_distance: f64,
     black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
     distance_bounds: (f64, f64),
     target_angle: f64,
 ) -> (f64, f64) {
-    let bound_predicate = |r: Response| r.hits_black_hole();
-    let valid_z = find_optimal_z(
+    let bound_predicate = |r: &RaySummary| r.hits_black_hole();
+    let valid_z = find_optimal_z_by_summary(
         camera_distance as f32,
         black_hole_radius as f32,
+        model,
+        config,
         (-1., 1.),
         &bound_predicate,
     );
 
-    let is_too_close = move |r: Response| {
-        let dist = r.get_angle_dist().get_dist(target_angle);
-        return dist.is_some() && dist.unwrap() <= distance_bounds.1;
+    let dist_at_target = |z: f64| {
+        dist_at_angle(
+            z,
+            camera_distance,
+            black_hole_radius,
+            model,
+            config,
+            target_angle,
+        )
+        .unwrap_or(100.)
     };
-    let lower_1 = find_optimal_z(
-        camera_distance as f32,
-        black_hole_radius as f32,
-        (-1., valid_z.0),
-        &is_too_close,
-    );
-    let lower_test_1 =
-        cast_ray_steps_response(lower_1.1, camera_distance as f64, black_hole_radius as f64)
-            .get_angle_dist()
-            .get_dist(target_angle)
-            .unwrap_or(100.);
-    let is_too_close = move |r: Response| {
-        let dist = r.get_angle_dist().get_dist(target_angle);
-        dist.is_none() || dist.unwrap() <= distance_bounds.1
+    let find_z = |z_bounds: (f64, f64), is_too_close: &dyn Fn(Option<f64>) -> bool| {
+        find_optimal_z_by_dist_at_angle(
+            camera_distance as f32,
+            black_hole_radius as f32,
+            model,
+            config,
+            z_bounds,
+            target_angle,
+            is_too_close,
+        )
     };
-    let lower_2 = find_optimal_z(
-        camera_distance as f32,
-        black_hole_radius as f32,
-        (valid_z.0, 1.0),
-        &is_too_close,
-    );
-    let lower_test_2 =
-        cast_ray_steps_response(lower_2.1, camera_distance as f64, black_hole_radius as f64)
-            .get_angle_dist()
-            .get_dist(target_angle)
-            .unwrap_or(100.);
+
+    let lower_1 = find_z((-1., valid_z.0), &|dist| {
+        dist.is_some() && dist.unwrap() <= distance_bounds.1
+    });
+    let lower_test_1 = dist_at_target(lower_1.1);
+    let lower_2 = find_z((valid_z.0, 1.0), &|dist| {
+        dist.is_none() || dist.unwrap() <= distance_bounds.1
+    });
+    let lower_test_2 = dist_at_target(lower_2.1);
 
     let mut lower = lower_1.1;
     if (lower_test_2 - distance_bounds.1).abs() < (lower_test_1 - distance_bounds.1).abs() {
         lower = lower_2.1;
     }
 
-    let _is_too_close = move |r: Response| {
-        let angle_d = r.get_angle_dist();
-        if angle_d.get_max_angle() < target_angle {
-            return true;
-        }
-        if angle_d.get_dist(target_angle).unwrap() < distance_bounds.0 {
-            return true;
-        }
-        false
-    };
-
-    let is_too_close = move |r: Response| {
-        let dist = r.get_angle_dist().get_dist(target_angle);
-        return !dist.is_none() && dist.unwrap() <= distance_bounds.0;
-    };
-    let upper_1 = find_optimal_z(
-        camera_distance as f32,
-        black_hole_radius as f32,
-        (-1., valid_z.0),
-        &is_too_close,
-    );
-    let upper_test_1 =
-        cast_ray_steps_response(upper_1.1, camera_distance as f64, black_hole_radius as f64)
-            .get_angle_dist()
-            .get_dist(target_angle)
-            .unwrap_or(100.);
-    let is_too_close = move |r: Response| {
-        let dist = r.get_angle_dist().get_dist(target_angle);
+    let upper_1 = find_z((-1., valid_z.0), &|dist| {
+        dist.is_some() && dist.unwrap() <= distance_bounds.0
+    });
+    let upper_test_1 = dist_at_target(upper_1.1);
+    let upper_2 = find_z((valid_z.0, 1.0), &|dist| {
         dist.is_none() || dist.unwrap() < distance_bounds.0
-    };
-    let upper_2 = find_optimal_z(
-        camera_distance as f32,
-        black_hole_radius as f32,
-        (valid_z.0, 1.0),
-        &is_too_close,
-    );
-    let upper_test_2 =
-        cast_ray_steps_response(upper_2.1, camera_distance as f64, black_hole_radius as f64)
-            .get_angle_dist()
-            .get_dist(target_angle)
-            .unwrap_or(100.);
+    });
+    let upper_test_2 = dist_at_target(upper_2.1);
 
     let mut upper = upper_1.0;
     if (upper_test_2 - distance_bounds.0).abs() < (upper_test_1 - distance_bounds.0).abs() {
@@ -213,21 +268,29 @@ mod tests {
 
     use test_utils::plot_trajectories;
 
-    use crate::path_integration2::path::cast_ray_steps_response;
-
-    use super::{
-        float_01_to_index_01, FixedDistanceFixedAngleDistanceCache, ANGLE_DISTANCE_CACHE_SIZE,
-        MIN_ANGLE,
+    use crate::{
+        cache_spec::{AxisSpec, DistanceCacheSpec, Interpolation, SamplingCurve},
+        path_integration2::{
+            analytic::{critical_impact_parameter, radius_at_angle},
+            impact_parameter::impact_parameter,
+            path::cast_ray_steps_response,
+            ray_cast_config::RayCastConfig,
+            structs::field::FieldModel,
+        },
+        refinement::Refinement,
     };
 
+    use super::{FixedDistanceFixedAngleDistanceCache, MIN_ANGLE};
+
     #[test]
     fn show_index_distribution() {
         let point_count = 1000;
         let mut lines = Vec::new();
         let mut line = Vec::new();
+        let curve = DistanceCacheSpec::default_z().curve;
         for i in 0..point_count {
             let z_01 = i as f64 / (point_count - 1) as f64;
-            let i_01 = float_01_to_index_01(z_01);
+            let i_01 = curve.float_01_to_index_01(z_01);
 
             line.push((z_01 as f32, i_01 as f32));
         }
@@ -242,7 +305,7 @@ mod tests {
 
     #[test]
     fn fixed_angle_test_error() {
-        let cache_size = ANGLE_DISTANCE_CACHE_SIZE;
+        let cache_size = DistanceCacheSpec::default_z().size;
         let distance = 5.0;
         let black_hole_radius = 1.5;
         let max_disc_radius = (1.5, 12.0);
@@ -262,18 +325,25 @@ mod tests {
         for angle in [MIN_ANGLE, FRAC_PI_2, PI, TAU] {
             let mut line = Vec::new();
             let cache = FixedDistanceFixedAngleDistanceCache::compute_new(
-                cache_size,
+                &DistanceCacheSpec::default(),
                 distance,
                 black_hole_radius,
+                FieldModel::PseudoForce,
+                RayCastConfig::default(),
                 max_disc_radius,
                 angle,
             );
             for z_01 in &samples {
-                let approx_dist = cache.get_dist(*z_01);
+                let approx_dist = cache.get_dist(*z_01).unwrap();
                 let z = (cache.z_bounds.1 - cache.z_bounds.0) * z_01 + cache.z_bounds.0;
-                let true_path =
-                    cast_ray_steps_response(z, cache.camera_distance, cache.black_hole_radius)
-                        .get_angle_dist();
+                let true_path = cast_ray_steps_response(
+                    z,
+                    cache.camera_distance,
+                    cache.black_hole_radius,
+                    cache.model,
+                    cache.config,
+                )
+                .get_angle_dist();
                 assert!(
                     true_path.get_max_angle() >= cache.angle,
                     "\nTrue path is too shallow!\nMax angle: {}\nCache angle: {}\nz: {}",
@@ -303,15 +373,16 @@ mod tests {
 
     #[test]
     fn serialization() {
-        let cache_size = ANGLE_DISTANCE_CACHE_SIZE;
         let distance = 10.0;
         let black_hole_radius = 1.5;
         let max_disc_radius = (3.0, 6.0);
         let angle = PI;
         let cache = FixedDistanceFixedAngleDistanceCache::compute_new(
-            cache_size,
+            &DistanceCacheSpec::default(),
             distance,
             black_hole_radius,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
             max_disc_radius,
             angle,
         );
@@ -328,4 +399,284 @@ mod tests {
         let deserialized = deserialized.unwrap();
         assert_eq!(deserialized, cache);
     }
+
+    #[test]
+    fn fixed_angle_time_matches_rays() {
+        let cache_size = DistanceCacheSpec::default_z().size;
+        let distance = 10.0;
+        let black_hole_radius = 1.5;
+        let disc_bounds = (3.0, 6.0);
+        for angle in [FRAC_PI_2, PI] {
+            let cache = FixedDistanceFixedAngleDistanceCache::compute_new(
+                &DistanceCacheSpec::default(),
+                distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+                RayCastConfig::default(),
+                disc_bounds,
+                angle,
+            );
+            assert_eq!(cache.z_to_time.len(), cache.z_to_distance.len());
+            let mut checked = 0;
+            for i in 0..(2 * cache_size) {
+                let z_01 = SamplingCurve::Linear.index_to_float_01(i, 2 * cache_size);
+                let z = (cache.z_bounds.1 - cache.z_bounds.0) * z_01 + cache.z_bounds.0;
+                let response = cast_ray_steps_response(
+                    z,
+                    cache.camera_distance,
+                    cache.black_hole_radius,
+                    cache.model,
+                    cache.config,
+                );
+                // Samples that never reach the angle are left out of the cache.
+                let dist = match response.get_angle_dist().get_dist(angle) {
+                    Some(dist) if disc_bounds.0 <= dist && dist <= disc_bounds.1 => dist,
+                    _ => continue,
+                };
+                let expected = response.get_time(angle).unwrap();
+                let actual = cache.get_time_at_dist(dist).unwrap();
+                assert!(
+                    (actual - expected).abs() < 0.01 * expected,
+                    "angle: {}\nz: {}\nexpected: {}\nactual: {}",
+                    angle,
+                    z,
+                    expected,
+                    actual
+                );
+                checked += 1;
+            }
+            assert!(checked > 0, "angle: {}", angle);
+        }
+    }
+
+    #[test]
+    fn fixed_angle_matches_analytic() {
+        let distance = 10.0;
+        let black_hole_radius = 1.5;
+        let disc_bounds = (3.0, 6.0);
+        for angle in [FRAC_PI_2, 0.75 * PI, PI] {
+            let cache = FixedDistanceFixedAngleDistanceCache::compute_new(
+                &DistanceCacheSpec {
+                    z: AxisSpec::linear(256).unwrap(),
+                    ..DistanceCacheSpec::default()
+                },
+                distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+                RayCastConfig::default(),
+                disc_bounds,
+                angle,
+            );
+            let analytic = |z_01: f64| {
+                let z = (cache.z_bounds.1 - cache.z_bounds.0) * z_01 + cache.z_bounds.0;
+                let b = impact_parameter(z, distance, black_hole_radius, FieldModel::Schwarzschild);
+                // Only rays that escape have a closed form.
+                if b <= critical_impact_parameter(black_hole_radius) {
+                    return None;
+                }
+                let r_max = cache.config.escape_radius(distance);
+                radius_at_angle(b, black_hole_radius, distance, r_max, z > 0., angle)
+                    // Far from the hole the path is sampled too coarsely for the disc lookup.
+                    .filter(|&expected| expected <= disc_bounds.1)
+            };
+            let positions: Vec<f64> = (0..cache.z_to_distance.len())
+                .map(|i| cache.z_axis.index_to_float_01(i))
+                .collect();
+            let (mut sample_error, mut lookup_error): (f64, f64) = (0., 0.);
+            for (i, z_01) in positions.iter().enumerate() {
+                if let Some(expected) = analytic(*z_01) {
+                    let dist = cache.get_dist(*z_01).unwrap();
+                    sample_error = sample_error.max((dist - expected).abs() / expected);
+                }
+                // Halfway to the next sample, where the lookup interpolates.
+                let z_01 = match positions.get(i + 1) {
+                    Some(next) => 0.5 * (z_01 + next),
+                    None => continue,
+                };
+                if let Some(expected) = analytic(z_01) {
+                    let dist = cache.get_dist(z_01).unwrap();
+                    lookup_error = lookup_error.max((dist - expected).abs() / expected);
+                }
+            }
+            assert!(!positions.is_empty(), "angle: {}", angle);
+            assert!(
+                sample_error < 0.0001,
+                "angle: {}, error: {}",
+                angle,
+                sample_error
+            );
+            assert!(
+                lookup_error < 0.005,
+                "angle: {}, error: {}",
+                angle,
+                lookup_error
+            );
+        }
+    }
+
+    #[test]
+    fn honors_z_axis() {
+        let z_axis = AxisSpec::new(
+            12,
+            SamplingCurve::Piecewise {
+                knots: vec![(0., 0.), (0.5, 0.8), (1., 1.)],
+            },
+        )
+        .unwrap();
+        let cache = FixedDistanceFixedAngleDistanceCache::compute_new(
+            &DistanceCacheSpec {
+                z: z_axis.clone(),
+                ..DistanceCacheSpec::default()
+            },
+            10.0,
+            1.5,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+            (3.0, 6.0),
+            0.75 * PI,
+        );
+        // Rays that miss the angle are left out, and the stored axis places the rest where they
+        // were cast rather than spreading the curve over fewer samples.
+        assert!(cache.z_to_distance.len() >= 2 && cache.z_to_distance.len() < z_axis.size);
+        assert_eq!(cache.z_axis.size, cache.z_to_distance.len());
+        let positions: Vec<f64> = (0..z_axis.size)
+            .map(|i| z_axis.index_to_float_01(i))
+            .collect();
+        for (i, dist) in cache.z_to_distance.iter().enumerate() {
+            let z_01 = cache.z_axis.index_to_float_01(i);
+            assert!(
+                positions.iter().any(|x| (x - z_01).abs() < 1e-12),
+                "i: {}",
+                i
+            );
+            let expected = cast_ray_steps_response(
+                cache.sample_z(i),
+                cache.camera_distance,
+                cache.black_hole_radius,
+                cache.model,
+                cache.config,
+            )
+            .get_angle_dist()
+            .get_dist(cache.angle)
+            .unwrap();
+            assert!((dist - expected).abs() < 1e-9, "i: {}", i);
+            assert!(
+                (cache.get_dist(z_01).unwrap() - dist).abs() < 1e-9,
+                "i: {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn refined_matches_rays() {
+        let cache = FixedDistanceFixedAngleDistanceCache::compute_new(
+            &DistanceCacheSpec {
+                z: AxisSpec::linear(8).unwrap(),
+                z_refinement: Some(Refinement {
+                    tolerance: 0.001,
+                    max_size: 128,
+                }),
+                ..DistanceCacheSpec::default()
+            },
+            10.0,
+            1.5,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+            (3.0, 6.0),
+            FRAC_PI_2,
+        );
+        assert_eq!(cache.z_axis.size, cache.z_to_distance.len());
+        assert!(cache.z_to_distance.len() > 8);
+        // Only the samples that reach the angle are kept, and the refined axis places each of them.
+        for (i, dist) in cache.z_to_distance.iter().enumerate() {
+            let z = cache.z_axis.index_to_value(i, cache.z_bounds);
+            let expected = cast_ray_steps_response(
+                z,
+                cache.camera_distance,
+                cache.black_hole_radius,
+                cache.model,
+                cache.config,
+            )
+            .get_angle_dist()
+            .get_dist(cache.angle)
+            .unwrap();
+            assert_eq!(*dist, expected);
+            let z_01 = cache.z_axis.index_to_float_01(i);
+            assert!(
+                (cache.get_dist(z_01).unwrap() - dist).abs() < 1e-9,
+                "i: {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn cubic_beats_linear() {
+        let distance = 10.0;
+        let black_hole_radius = 1.5;
+        let disc_bounds = (3.0, 6.0);
+        // A tolerance nothing exceeds keeps the axis' samples, but records where the kept ones are.
+        let mut cache = FixedDistanceFixedAngleDistanceCache::compute_new(
+            &DistanceCacheSpec {
+                z: AxisSpec::linear(16).unwrap(),
+                z_refinement: Some(Refinement {
+                    tolerance: f64::INFINITY,
+                    max_size: 16,
+                }),
+                ..DistanceCacheSpec::default()
+            },
+            distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+            disc_bounds,
+            FRAC_PI_2,
+        );
+        let escape_radius = cache.config.escape_radius(distance);
+        let (first, last) = (
+            cache.z_axis.index_to_float_01(0),
+            cache.z_axis.index_to_float_01(cache.z_axis.size - 1),
+        );
+        let samples = 500;
+        let expected: Vec<(f64, f64)> = (0..samples)
+            .filter_map(|i| {
+                let z_01 = first + (last - first) * (i as f64 + 0.5) / samples as f64;
+                let z = (cache.z_bounds.1 - cache.z_bounds.0) * z_01 + cache.z_bounds.0;
+                let b = impact_parameter(z, distance, black_hole_radius, FieldModel::Schwarzschild);
+                if b <= critical_impact_parameter(black_hole_radius) {
+                    return None;
+                }
+                let expected = radius_at_angle(
+                    b,
+                    black_hole_radius,
+                    distance,
+                    escape_radius,
+                    z > 0.,
+                    cache.angle,
+                )?;
+                (expected <= disc_bounds.1).then_some((z_01, expected))
+            })
+            .collect();
+        // Same samples, so the same memory; only the lookup changes.
+        let mut rms_error = |interpolation: Interpolation| {
+            cache.z_axis.interpolation = interpolation;
+            let total: f64 = expected
+                .iter()
+                .map(|(z_01, dist)| (cache.get_dist(*z_01).unwrap() - dist).powi(2))
+                .sum();
+            (total / expected.len() as f64).sqrt()
+        };
+        let linear = rms_error(Interpolation::Linear);
+        for interpolation in [Interpolation::CatmullRom, Interpolation::MonotoneHermite] {
+            let error = rms_error(interpolation);
+            assert!(
+                error < linear,
+                "{:?}: {}, linear: {}",
+                interpolation,
+                error,
+                linear
+            );
+        }
+    }
 }
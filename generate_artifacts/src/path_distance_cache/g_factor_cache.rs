@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use wire_structs::sampler::g_factor_table::GFactorTable;
+
+use crate::{cache_spec::SamplingCurve, path_integration2::impact_parameter::impact_parameter};
+
+use super::distance_cache::DistanceCache;
+
+// Frequency shift g = observed / emitted for light from a disc on Keplerian orbits, for every
+// entry of a `DistanceCache`.
+//
+// g depends on how the ray's plane is tilted against the disc, which only the renderer knows, so
+// each entry stores the two factors of
+//
+//     g = gravitational_shift / (1 - doppler_coefficient * alignment)
+//
+// where alignment is the cosine between the disc's rotation axis and the angular momentum of the
+// light travelling to the camera. The approaching side of the disc has alignment > 0.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct GFactorCache {
+    pub cache_size: (usize, usize, usize),
+    pub distance_bounds: (f64, f64),
+    pub black_hole_radius: f64,
+    pub disc_bounds: (f64, f64),
+    // (gravitational shift, Doppler coefficient) for each entry of `DistanceCache::texture`.
+    pub shifts: Vec<(f64, f64)>,
+}
+
+// Time dilation of an emitter on a circular orbit at `radius`, seen by a static camera. Orbits
+// inside the photon sphere don't exist, so they get no light at all.
+pub fn gravitational_shift(radius: f64, camera_distance: f64, black_hole_radius: f64) -> f64 {
+    let emitter = 1. - 1.5 * black_hole_radius / radius;
+    if emitter <= 0. {
+        return 0.;
+    }
+    (emitter / (1. - black_hole_radius / camera_distance)).sqrt()
+}
+
+// Orbital angular velocity times the impact parameter of the light.
+pub fn doppler_coefficient(radius: f64, impact_parameter: f64, black_hole_radius: f64) -> f64 {
+    let angular_velocity = (0.5 * black_hole_radius / radius.powi(3)).sqrt();
+    angular_velocity * impact_parameter
+}
+
+// 0 where the disc doesn't emit, rather than dividing by a Doppler term that may vanish too.
+pub fn g_factor(gravitational_shift: f64, doppler_coefficient: f64, alignment: f64) -> f64 {
+    if gravitational_shift <= 0. {
+        return 0.;
+    }
+    gravitational_shift / (1. - doppler_coefficient * alignment)
+}
+
+// Impact parameter of the rays whose radii bracket `radius`, or of the closest ray if none do.
+// Entries with a radius of 0 missed the angle.
+fn impact_parameter_at_radius(
+    radii: &[f64],
+    impact_parameters: &[f64],
+    radius: f64,
+) -> Option<f64> {
+    for i in 0..(radii.len() - 1) {
+        let (left, right) = (radii[i], radii[i + 1]);
+        if left != 0. && right != 0. && left != right && (left - radius) * (right - radius) <= 0. {
+            let t = (radius - left) / (right - left);
+            return Some(t * impact_parameters[i + 1] + (1. - t) * impact_parameters[i]);
+        }
+    }
+    (0..radii.len())
+        .filter(|i| radii[*i] != 0.)
+        .min_by(|a, b| {
+            (radii[*a] - radius)
+                .abs()
+                .total_cmp(&(radii[*b] - radius).abs())
+        })
+        .map(|i| impact_parameters[i])
+}
+
+impl GFactorCache {
+    // The shifts are closed form given where the ray hits the disc, so no rays are cast. Texels
+    // whose rays miss get no shift at all.
+    pub fn compute_new(distance_cache: &DistanceCache) -> Self {
+        let black_hole_radius = distance_cache.black_hole_radius;
+        let model = distance_cache.model;
+        let texture = distance_cache.texture();
+        let mut shifts = Vec::new();
+        for (i, radius) in texture.distances.iter().enumerate() {
+            if *radius == 0. {
+                shifts.push((0., 0.));
+                continue;
+            }
+            let camera_distance = texture.camera_distance(i);
+            let b = impact_parameter(texture.z(i), camera_distance, black_hole_radius, model);
+            shifts.push((
+                gravitational_shift(*radius, camera_distance, black_hole_radius),
+                doppler_coefficient(*radius, b, black_hole_radius),
+            ));
+        }
+        GFactorCache {
+            cache_size: distance_cache.cache_size,
+            distance_bounds: distance_cache.distance_bounds,
+            black_hole_radius,
+            disc_bounds: distance_cache.disc_bounds,
+            shifts,
+        }
+    }
+
+    // The same shifts on the grid of `DistanceCache::time_delay_table`, for renderers that know
+    // where a ray hits the disc but not its z. The impact parameter comes from the rays that reach
+    // each disc radius, or from the previous angle where none do; the rest is closed form in the
+    // radius.
+    pub fn table(distance_cache: &DistanceCache, disc_samples: usize) -> GFactorTable {
+        let black_hole_radius = distance_cache.black_hole_radius;
+        let disc_bounds = distance_cache.disc_bounds;
+        let texture = distance_cache.texture();
+        let (width, height, depth) = texture.dimensions;
+        let mut gravitational_shifts = Vec::new();
+        let mut doppler_coefficients = Vec::new();
+        let mut previous = Vec::new();
+        for row in 0..(height * depth) {
+            let camera_distance = texture.camera_distance(row * width);
+            if row % height == 0 {
+                previous = vec![0.; disc_samples];
+            }
+            let radii = &texture.distances[row * width..(row + 1) * width];
+            let impact_parameters: Vec<f64> = (row * width..(row + 1) * width)
+                .map(|i| {
+                    impact_parameter(
+                        texture.z(i),
+                        camera_distance,
+                        black_hole_radius,
+                        distance_cache.model,
+                    )
+                })
+                .collect();
+            for (i, b) in previous.iter_mut().enumerate() {
+                let float_01 = SamplingCurve::Linear.index_to_float_01(i, disc_samples);
+                let radius = (disc_bounds.1 - disc_bounds.0) * float_01 + disc_bounds.0;
+                if let Some(impact_parameter) =
+                    impact_parameter_at_radius(radii, &impact_parameters, radius)
+                {
+                    *b = impact_parameter;
+                }
+                gravitational_shifts.push(gravitational_shift(
+                    radius,
+                    camera_distance,
+                    black_hole_radius,
+                ) as f32);
+                doppler_coefficients
+                    .push(doppler_coefficient(radius, *b, black_hole_radius) as f32);
+            }
+        }
+        GFactorTable {
+            dimensions: [disc_samples as u32, height as u32, depth as u32],
+            disc_bounds: [disc_bounds.0 as f32, disc_bounds.1 as f32],
+            distance_bounds: [
+                distance_cache.distance_bounds.0 as f32,
+                distance_cache.distance_bounds.1 as f32,
+            ],
+            gravitational_shifts,
+            doppler_coefficients,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cache_spec::{AxisSpec, DistanceCacheSpec},
+        path_distance_cache::distance_cache::DistanceCache,
+        path_integration2::{ray_cast_config::RayCastConfig, structs::field::FieldModel},
+    };
+
+    use super::{doppler_coefficient, g_factor, gravitational_shift, GFactorCache};
+
+    #[test]
+    fn approaching_side_is_blueshifted() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let radius = 6.;
+        let b = 5.;
+        let gravitational = gravitational_shift(radius, camera_distance, black_hole_radius);
+        let doppler = doppler_coefficient(radius, b, black_hole_radius);
+        let approaching = g_factor(gravitational, doppler, 1.);
+        let face_on = g_factor(gravitational, doppler, 0.);
+        let receding = g_factor(gravitational, doppler, -1.);
+        assert!(approaching > face_on && face_on > receding);
+        assert_eq!(face_on, gravitational);
+        // Beaming makes the approaching side brighter.
+        assert!(approaching.powi(3) > 2. * receding.powi(3));
+        assert_eq!(
+            gravitational_shift(2., camera_distance, black_hole_radius),
+            0.
+        );
+    }
+
+    #[test]
+    fn far_from_hole_matches_special_relativity() {
+        let black_hole_radius = 1.5;
+        let radius: f64 = 1000000.;
+        let camera_distance = 1e12;
+        let speed = (0.5 * black_hole_radius / radius).sqrt();
+        // Light leaving along the orbit, straight towards the camera.
+        let g = g_factor(
+            gravitational_shift(radius, camera_distance, black_hole_radius),
+            doppler_coefficient(radius, radius, black_hole_radius),
+            1.,
+        );
+        let expected = ((1. + speed) / (1. - speed)).sqrt();
+        assert!(
+            (g - expected).abs() < 0.00001,
+            "g: {}, expected: {}",
+            g,
+            expected
+        );
+    }
+
+    #[test]
+    fn no_light_from_inside_the_photon_sphere() {
+        let black_hole_radius = 1.5;
+        for radius in [1.6, 2., 2.25] {
+            let gravitational = gravitational_shift(radius, 20., black_hole_radius);
+            // Close enough that the Doppler term alone would divide by 0.
+            let doppler = 1.;
+            for alignment in [-1., 0., 1.] {
+                assert_eq!(g_factor(gravitational, doppler, alignment), 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn table_matches_closed_form() {
+        let spec = DistanceCacheSpec {
+            distance: AxisSpec::linear(3).unwrap(),
+            angle: AxisSpec::linear(4).unwrap(),
+            z: AxisSpec::linear(16).unwrap(),
+            z_refinement: None,
+        };
+        let black_hole_radius = 1.5;
+        // Reaches inside the photon sphere, where the disc is dark.
+        let disc_bounds = (1.6, 6.);
+        let distance_cache = DistanceCache::compute_new(
+            spec,
+            (5., 20.),
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+            disc_bounds,
+        );
+        let cache = GFactorCache::compute_new(&distance_cache);
+        assert_eq!(cache.shifts.len(), 16 * 4 * 3);
+        assert!(cache
+            .shifts
+            .iter()
+            .all(|(gravitational, doppler)| gravitational.is_finite() && doppler.is_finite()));
+
+        let table = GFactorCache::table(&distance_cache, 5);
+        assert_eq!(table.dimensions, [5, 4, 3]);
+        assert_eq!(table.gravitational_shifts.len(), 5 * 4 * 3);
+        assert_eq!(table.doppler_coefficients.len(), 5 * 4 * 3);
+        for (i, gravitational) in table.gravitational_shifts.iter().enumerate() {
+            let radius = 1.6 + 1.1 * (i % 5) as f64;
+            let camera_distance = 5. + 7.5 * (i / 20) as f64;
+            let expected = gravitational_shift(radius, camera_distance, black_hole_radius);
+            assert!((*gravitational as f64 - expected).abs() < 1e-6);
+            let doppler = table.doppler_coefficients[i] as f64;
+            assert!(doppler.is_finite() && doppler >= 0.);
+        }
+        // The inner edge is inside the photon sphere, so it stays dark whatever the alignment.
+        assert_eq!(table.gravitational_shifts[0], 0.);
+    }
+}
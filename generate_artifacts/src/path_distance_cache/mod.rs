@@ -1,3 +1,4 @@
 pub mod distance_cache;
 pub mod fixed_distance_distance_cache;
 pub mod fixed_distance_fixed_angle_distance_cache;
+pub mod g_factor_cache;
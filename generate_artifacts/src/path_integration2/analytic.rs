@@ -0,0 +1,203 @@
+use std::f64::consts::PI;
+
+// Closed form Schwarzschild light bending, used as ground truth for the integrators.
+//
+// With u = 1 / r, photon orbits satisfy (du/dphi)^2 = 1/b^2 - u^2 + r_s u^3. For b above the
+// critical impact parameter the cubic has roots u_1 < 0 < u_2 = 1 / r_0 < u_3, and the angle swept
+// between u and the closest approach u_2 is an incomplete elliptic integral, which we evaluate
+// with Carlson's symmetric form.
+
+const RF_TOLERANCE: f64 = 0.0000000001;
+
+// Carlson's symmetric elliptic integral of the first kind.
+pub fn carlson_rf(x: f64, y: f64, z: f64) -> f64 {
+    let (mut x, mut y, mut z) = (x, y, z);
+    loop {
+        let mean = (x + y + z) / 3.;
+        let dx = 1. - x / mean;
+        let dy = 1. - y / mean;
+        let dz = 1. - z / mean;
+        if dx.abs().max(dy.abs()).max(dz.abs()) < RF_TOLERANCE.powf(1. / 6.) {
+            let e2 = dx * dy - dz * dz;
+            let e3 = dx * dy * dz;
+            return (1. - e2 / 10. + e3 / 14. + e2 * e2 / 24. - 3. * e2 * e3 / 44.) / mean.sqrt();
+        }
+        let lambda = (x * y).sqrt() + (x * z).sqrt() + (y * z).sqrt();
+        x = 0.25 * (x + lambda);
+        y = 0.25 * (y + lambda);
+        z = 0.25 * (z + lambda);
+    }
+}
+
+// Incomplete elliptic integral of the first kind F(phi | k^2), for |phi| <= pi / 2.
+pub fn elliptic_f(phi: f64, k: f64) -> f64 {
+    let (sin, cos) = phi.sin_cos();
+    sin * carlson_rf(cos * cos, 1. - k * k * sin * sin, 1.)
+}
+
+// Complete elliptic integral of the first kind K(k^2).
+pub fn elliptic_k(k: f64) -> f64 {
+    carlson_rf(0., 1. - k * k, 1.)
+}
+
+pub fn critical_impact_parameter(black_hole_radius: f64) -> f64 {
+    1.5 * 3_f64.sqrt() * black_hole_radius
+}
+
+// Largest root of r^3 - b^2 r + b^2 r_s = 0; None if the ray is captured.
+pub fn closest_approach(b: f64, black_hole_radius: f64) -> Option<f64> {
+    if b <= critical_impact_parameter(black_hole_radius) {
+        return None;
+    }
+    let angle = (-1.5 * 3_f64.sqrt() * black_hole_radius / b).acos() / 3.;
+    Some(2. * b / 3_f64.sqrt() * angle.cos())
+}
+
+// The three roots (u_1, u_2, u_3) of r_s u^3 - u^2 + 1/b^2.
+fn roots(b: f64, black_hole_radius: f64) -> Option<(f64, f64, f64)> {
+    let u_2 = 1. / closest_approach(b, black_hole_radius)?;
+    // Dividing out (u - u_2) leaves u^2 + p u + p u_2.
+    let p = u_2 - 1. / black_hole_radius;
+    let discriminant = (p * p - 4. * p * u_2).sqrt();
+    Some((0.5 * (-p - discriminant), u_2, 0.5 * (-p + discriminant)))
+}
+
+// Angle swept between radius r and the closest approach.
+fn angle_to_closest_approach(b: f64, black_hole_radius: f64, r: f64) -> Option<f64> {
+    let (u_1, u_2, u_3) = roots(b, black_hole_radius)?;
+    let u = (1. / r).min(u_2);
+    if u_2 - u <= 0. {
+        return Some(0.);
+    }
+    // Carlson's reduction of the integral of ((t - u_1)(u_2 - t)(u_3 - t))^(-1/2) over [u, u_2].
+    let (x_1, x_2, x_3) = ((u_2 - u_1).sqrt(), 0., (u_3 - u_2).sqrt());
+    let (y_1, y_2, y_3) = ((u - u_1).sqrt(), (u_2 - u).sqrt(), (u_3 - u).sqrt());
+    let width = u_2 - u;
+    let u_12 = (x_1 * x_2 * y_3 + y_1 * y_2 * x_3) / width;
+    let u_13 = (x_1 * x_3 * y_2 + y_1 * y_3 * x_2) / width;
+    let u_23 = (x_2 * x_3 * y_1 + y_2 * y_3 * x_1) / width;
+    Some(2. * carlson_rf(u_12 * u_12, u_13 * u_13, u_23 * u_23) / black_hole_radius.sqrt())
+}
+
+// Total deflection of a ray coming in from and leaving to infinity.
+pub fn deflection(b: f64, black_hole_radius: f64) -> Option<f64> {
+    Some(2. * angle_to_closest_approach(b, black_hole_radius, f64::INFINITY)? - PI)
+}
+
+// Angle swept around the hole by a ray between r_start and r_end. `incoming` says whether the ray
+// is heading towards the hole at r_start.
+pub fn swept_angle(
+    b: f64,
+    black_hole_radius: f64,
+    r_start: f64,
+    r_end: f64,
+    incoming: bool,
+) -> Option<f64> {
+    let start = angle_to_closest_approach(b, black_hole_radius, r_start)?;
+    let end = angle_to_closest_approach(b, black_hole_radius, r_end)?;
+    Some(match incoming {
+        true => start + end,
+        false => end - start,
+    })
+}
+
+// Angle between the direction of travel and the outward radial direction, at radius r.
+fn heading(b: f64, black_hole_radius: f64, r: f64, incoming: bool) -> f64 {
+    let u = 1. / r;
+    let sin = (u / (1. / (b * b) + black_hole_radius * u * u * u).sqrt()).min(1.);
+    match incoming {
+        true => PI - sin.asin(),
+        false => sin.asin(),
+    }
+}
+
+// Angle between the initial and final directions of a ray travelling from r_start to r_end.
+pub fn finite_deflection(
+    b: f64,
+    black_hole_radius: f64,
+    r_start: f64,
+    r_end: f64,
+    incoming: bool,
+) -> Option<f64> {
+    let swept = swept_angle(b, black_hole_radius, r_start, r_end, incoming)?;
+    Some(
+        swept + heading(b, black_hole_radius, r_end, false)
+            - heading(b, black_hole_radius, r_start, incoming),
+    )
+}
+
+// Radius reached after sweeping `angle` around the hole from r_start, if the ray gets that far
+// before reaching r_max.
+pub fn radius_at_angle(
+    b: f64,
+    black_hole_radius: f64,
+    r_start: f64,
+    r_max: f64,
+    incoming: bool,
+    angle: f64,
+) -> Option<f64> {
+    let r_0 = closest_approach(b, black_hole_radius)?;
+    let start = angle_to_closest_approach(b, black_hole_radius, r_start)?;
+    let (mut near, mut far, target) = match incoming && angle <= start {
+        // Still on the way in.
+        true => (r_0, r_start, start - angle),
+        false => {
+            let target = match incoming {
+                true => angle - start,
+                false => angle + start,
+            };
+            if target > angle_to_closest_approach(b, black_hole_radius, r_max)? {
+                return None;
+            }
+            (r_0, r_max, target)
+        }
+    };
+    // The angle to closest approach grows with r, so we bisect on it.
+    while far - near > RF_TOLERANCE * far {
+        let r = 0.5 * (near + far);
+        if angle_to_closest_approach(b, black_hole_radius, r)? < target {
+            near = r;
+        } else {
+            far = r;
+        }
+    }
+    Some(0.5 * (near + far))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{closest_approach, critical_impact_parameter, deflection, elliptic_f, elliptic_k};
+
+    #[test]
+    fn elliptic_integrals() {
+        assert!((elliptic_k(0.) - 0.5 * PI).abs() < 0.000000001);
+        // K(k^2 = 0.5) from tables.
+        assert!((elliptic_k(0.5_f64.sqrt()) - 1.8540746773013719).abs() < 0.000000001);
+        assert!((elliptic_f(0.5 * PI, 0.8) - elliptic_k(0.8)).abs() < 0.000000001);
+        // F(phi | 0) = phi.
+        assert!((elliptic_f(0.3, 0.) - 0.3).abs() < 0.000000001);
+    }
+
+    #[test]
+    fn schwarzschild_deflection_limits() {
+        let black_hole_radius = 1.5;
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        assert!(closest_approach(0.999 * critical_b, black_hole_radius).is_none());
+        // Just above critical, the ray skims the photon sphere.
+        let r_0 = closest_approach(1.000001 * critical_b, black_hole_radius).unwrap();
+        assert!((r_0 - 1.5 * black_hole_radius).abs() < 0.01);
+
+        // Weak field: 2 r_s / b + 15 pi / 16 (r_s / b)^2.
+        for b in [1000., 10000.] {
+            let ratio = black_hole_radius / b;
+            let expected = 2. * ratio + 15. * PI / 16. * ratio * ratio;
+            let actual = deflection(b, black_hole_radius).unwrap();
+            assert!((actual - expected).abs() < 10. * ratio.powi(3));
+        }
+
+        // Deflection diverges logarithmically at the critical impact parameter.
+        assert!(deflection(1.0001 * critical_b, black_hole_radius).unwrap() > 2. * PI);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    cell::Cell,
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use super::{
+    path::{cast_ray_steps_response, cast_ray_summary},
+    ray_cast_config::RayCastConfig,
+    ray_summary::RaySummary,
+    response::Response,
+    structs::field::FieldModel,
+};
+
+thread_local! {
+    // Set on worker threads, so nested batches run inline instead of spawning threads per thread.
+    static IN_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+// Maps f over items using every core, returning the results in the same order as items.
+//
+// Rays near the photon sphere take far longer than the rest, so rather than splitting items into
+// fixed chunks, workers take the next unclaimed item until there are none left.
+pub fn parallel_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+        .min(items.len());
+    if threads <= 1 || IN_WORKER.with(Cell::get) {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut indexed: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    IN_WORKER.with(|in_worker| in_worker.set(true));
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= items.len() {
+                            return results;
+                        }
+                        results.push((index, f(&items[index])));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    indexed.sort_unstable_by_key(|(index, _)| *index);
+    indexed.into_iter().map(|(_, result)| result).collect()
+}
+
+// Casts every (camera_distance, z) ray, in order.
+pub fn cast_ray_batch(
+    rays: &[(f64, f64)],
+    black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
+) -> Vec<Response> {
+    parallel_map(rays, |&(camera_distance, z)| {
+        cast_ray_steps_response(z, camera_distance, black_hole_radius, model, config)
+    })
+}
+
+// Like `cast_ray_batch`, without keeping the paths.
+pub fn cast_ray_summary_batch(
+    rays: &[(f64, f64)],
+    black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
+) -> Vec<RaySummary> {
+    parallel_map(rays, |&(camera_distance, z)| {
+        cast_ray_summary(z, camera_distance, black_hole_radius, model, config)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::path_integration2::{
+        path::cast_ray_steps_response, ray_cast_config::RayCastConfig, structs::field::FieldModel,
+    };
+
+    use super::{cast_ray_batch, cast_ray_summary_batch, parallel_map};
+
+    #[test]
+    fn parallel_map_keeps_order() {
+        let items: Vec<usize> = (0..1000).collect();
+        // Uneven work, so items finish out of order.
+        let results = parallel_map(&items, |&i| (0..(i % 7) * 1000).fold(i, |acc, _| acc));
+        assert_eq!(results, items);
+
+        // Nested maps run inline on the workers and still keep their order.
+        let nested = parallel_map(&items[..16], |&i| parallel_map(&items[..i], |&j| i + j));
+        for (i, inner) in nested.iter().enumerate() {
+            assert_eq!(*inner, (0..i).map(|j| i + j).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn batch_matches_sequential() {
+        let black_hole_radius = 1.5;
+        let rays: Vec<(f64, f64)> = [5., 10., 20.]
+            .iter()
+            .flat_map(|&camera_distance| [-0.5, 0.5, 0.9, 0.99].map(|z: f64| (camera_distance, z)))
+            .collect();
+        let model = FieldModel::Schwarzschild;
+        let config = RayCastConfig::default();
+        let responses = cast_ray_batch(&rays, black_hole_radius, model, config);
+        let summaries = cast_ray_summary_batch(&rays, black_hole_radius, model, config);
+        for (i, &(camera_distance, z)) in rays.iter().enumerate() {
+            let expected =
+                cast_ray_steps_response(z, camera_distance, black_hole_radius, model, config);
+            assert_eq!(responses[i].path, expected.path);
+            assert_eq!(responses[i].final_dir, expected.final_dir);
+            assert_eq!(summaries[i].final_dir, expected.final_dir);
+            assert_eq!(summaries[i].steps, expected.path.len());
+        }
+    }
+}
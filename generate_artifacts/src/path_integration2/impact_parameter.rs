@@ -0,0 +1,83 @@
+use glam::DVec3;
+
+use super::{
+    path::RAY_START_DIR,
+    structs::field::{Field, FieldModel},
+};
+
+// The impact parameter b = h / v_inf of the ray `cast_ray_steps_response` casts for (camera
+// distance, z). Unlike z it is conserved along the ray, so it doesn't depend on where the camera is.
+pub fn impact_parameter(
+    z: f64,
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+) -> f64 {
+    let field = Field::new(black_hole_radius, camera_distance, model);
+    let s = (1. - z * z).max(0.).sqrt();
+    let particle = field.spawn_particle(camera_distance * RAY_START_DIR, DVec3::new(s, 0., z));
+    particle.angular_momentum / (2. * field.energy(&particle)).sqrt()
+}
+
+// The z of the ray heading towards the hole with impact parameter b, if the camera can see it.
+pub fn z_for_impact_parameter(
+    b: f64,
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+) -> Option<f64> {
+    let field = Field::new(black_hole_radius, camera_distance, model);
+    let s_sq = match model {
+        // The speed at infinity doesn't depend on the direction.
+        FieldModel::PseudoForce => {
+            let start = camera_distance * RAY_START_DIR;
+            let particle = field.spawn_particle(start, DVec3::new(0., 0., 1.));
+            let speed = field.initial_speed(&start);
+            (b * (2. * field.energy(&particle)).sqrt() / (camera_distance * speed)).powi(2)
+        }
+        FieldModel::Schwarzschild | FieldModel::ReissnerNordstrom { .. } => {
+            let f = field.metric_factor(camera_distance);
+            b * b / (camera_distance * camera_distance + (1. - f) * b * b)
+        }
+    };
+    match s_sq <= 1. {
+        true => Some((1. - s_sq).sqrt()),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::path_integration2::structs::field::FieldModel;
+
+    use super::{impact_parameter, z_for_impact_parameter};
+
+    #[test]
+    fn impact_parameter_round_trip() {
+        for model in [
+            FieldModel::PseudoForce,
+            FieldModel::Schwarzschild,
+            FieldModel::ReissnerNordstrom { charge: 0.8 },
+        ] {
+            for camera_distance in [5., 20., 100.] {
+                for z in [0.1, 0.5, 0.9, 0.999] {
+                    let b = impact_parameter(z, camera_distance, 1.5, model);
+                    let round_trip =
+                        z_for_impact_parameter(b, camera_distance, 1.5, model).unwrap();
+                    assert!(
+                        (round_trip - z).abs() < 0.000001,
+                        "{:?}: {} vs {}",
+                        model,
+                        z,
+                        round_trip
+                    );
+                }
+            }
+        }
+        assert!(z_for_impact_parameter(30., 20., 1.5, FieldModel::Schwarzschild).is_none());
+        // Schwarzschild's closed form, b = D s / sqrt(1 - r_s s^2 / D) with s = sqrt(1 - z^2).
+        let b = impact_parameter(0.7, 20., 1.5, FieldModel::Schwarzschild);
+        let s_sq: f64 = 1. - 0.7 * 0.7;
+        assert!((b - 20. * s_sq.sqrt() / (1. - 1.5 * s_sq / 20.).sqrt()).abs() < 0.000001);
+    }
+}
@@ -1,3 +1,9 @@
+pub mod analytic;
+pub mod batch;
+pub mod impact_parameter;
 pub mod path;
+pub mod ray_cast_config;
+pub mod ray_summary;
 pub mod response;
-mod structs;
+pub mod shadow;
+pub mod structs;
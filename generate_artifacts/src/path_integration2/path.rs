@@ -1,80 +1,285 @@
+use std::fmt;
+
 use glam::DVec3;
 
 use super::{
+    ray_cast_config::RayCastConfig,
+    ray_summary::{AngleCrossing, RaySummary, StepControl, Termination},
     response::Response,
     structs::{
-        field::Field,
-        step::{hit, step_particle},
+        drift::DriftTracker,
+        field::{Field, FieldModel, Particle},
+        integrator::{Integrator, UnsupportedIntegrator},
+        kerr_field::{KerrField, SpinOutOfRange},
+        kerr_step::{kerr_hit, step_kerr_particle},
+        multi_field::MultiField,
+        multi_step::multi_hit,
+        step::hit,
+        wormhole_field::WormholeField,
+        wormhole_step::step_wormhole_particle,
     },
 };
 type TooClosePredicate = dyn Fn(Response) -> bool;
+type TooCloseSummaryPredicate = dyn Fn(&RaySummary) -> bool;
 
 pub const RAY_START_DIR: DVec3 = DVec3::new(0.0, 0.0, -1.0);
-// Takes in a ray and a parameterization of the black hole; returns the path taken.
-// Also returns the final direction if it doesn't hit the black hole.
-fn cast_ray_steps(
+// Steps a ray without keeping its path, calling `visitor` at every point before it's stepped.
+// The visitor sees the summary so far and can stop the ray early, in which case it's reported
+// as hitting the black hole. The summary matches the `Response` the same ray would produce.
+pub fn cast_ray_visit<F>(
     camera_distance: f64,
     start_dir: DVec3,
     field: &Field,
-    escape_radius: f64,
-    max_distance: f64,
-) -> (Vec<DVec3>, Option<DVec3>) {
+    config: RayCastConfig,
+    mut visitor: F,
+) -> RaySummary
+where
+    F: FnMut(&Particle, &RaySummary) -> StepControl,
+{
     let mut particle = field.spawn_particle(camera_distance * RAY_START_DIR, start_dir);
+    let mut integrator = config.integrator.integrator(&particle);
     let mut distance = 0.0;
-    let mut steps = Vec::new();
+    let mut summary = RaySummary::new();
+    let escape_radius = config.escape_radius(camera_distance);
+    let max_distance = config.max_distance(camera_distance);
     while particle.p.length() < escape_radius && distance < max_distance {
-        steps.push(particle.p);
+        summary.visit(particle.p);
         if hit(&particle, field) {
-            return (steps, None);
+            return summary.terminate(Termination::Captured, None);
+        }
+        if visitor(&particle, &summary) == StepControl::Stop {
+            return summary.terminate(Termination::Stopped, None);
         }
         let prev = particle.p;
-        step_particle(&mut particle, field);
+        if integrator.step(&mut particle, field).is_err() {
+            return summary.terminate(Termination::Failed, None);
+        }
         distance += (particle.p - prev).length();
     }
     if distance >= max_distance {
-        return (steps, None);
+        return summary.terminate(Termination::MaxDistance, None);
     }
-    steps.push(particle.p);
-    (steps, Some(particle.v))
+    summary.visit(particle.p);
+    summary.terminate(Termination::Escaped, Some(particle.v.normalize()))
+}
+
+// Same ray as `cast_ray_steps_response`, summarized.
+pub fn cast_ray_summary(
+    z: f64,
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
+) -> RaySummary {
+    let test = DVec3::new((1.0 - z * z).sqrt(), 0.0, z);
+    let field = Field::new(black_hole_radius, camera_distance, model);
+    cast_ray_visit(camera_distance, test, &field, config, |_, _| {
+        StepControl::Continue
+    })
 }
 
 // Takes in a ray and a parameterization of the black hole; returns the path taken.
 // Also returns the final direction if it doesn't hit the black hole.
-pub fn cast_ray_steps_response(z: f64, camera_distance: f64, black_hole_radius: f64) -> Response {
+pub fn cast_ray_steps_response(
+    z: f64,
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
+) -> Response {
     let test = DVec3::new((1.0 - z * z).sqrt(), 0.0, z);
-    let field = Field::new(black_hole_radius, camera_distance);
+    let field = Field::new(black_hole_radius, camera_distance, model);
     let mut particle = field.spawn_particle(camera_distance * RAY_START_DIR, test);
+    let mut integrator = config.integrator.integrator(&particle);
+    let mut drift = DriftTracker::new(&particle, &field);
     let mut distance = 0.0;
+    let mut time = 0.0;
     let mut steps = Vec::new();
-    let escape_radius = 5. * camera_distance;
-    let max_distance = 20. * escape_radius;
+    let mut times = Vec::new();
+    let escape_radius = config.escape_radius(camera_distance);
+    let max_distance = config.max_distance(camera_distance);
     while particle.p.length() < escape_radius && distance < max_distance {
         steps.push(particle.p);
-        if hit(&particle, &field) {
-            return Response::new(steps, None);
-        }
+        times.push(time);
         let prev = particle.p;
-        step_particle(&mut particle, &field);
+        if hit(&particle, &field) || integrator.step(&mut particle, &field).is_err() {
+            return Response::new(steps, None)
+                .with_step_stats(integrator.stats())
+                .with_drift(drift.drift())
+                .with_times(times);
+        }
+        drift.update(&particle, &field);
         distance += (particle.p - prev).length();
+        time += field.travel_time(&prev, &particle.p);
     }
     if distance >= max_distance {
-        return Response::new(steps, None);
+        return Response::new(steps, None)
+            .with_step_stats(integrator.stats())
+            .with_drift(drift.drift())
+            .with_times(times);
     }
     steps.push(particle.p);
+    times.push(time);
     Response::new(steps, Some(particle.v.normalize()))
+        .with_step_stats(integrator.stats())
+        .with_drift(drift.drift())
+        .with_times(times)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KerrRayError {
+    Spin(SpinOutOfRange),
+    Integrator(UnsupportedIntegrator),
+}
+
+impl fmt::Display for KerrRayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KerrRayError::Spin(error) => error.fmt(f),
+            KerrRayError::Integrator(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for KerrRayError {}
+
+// Casts a ray through the image plane point (x, y) of a camera at `inclination` from the spin
+// axis of a Kerr black hole. The image plane sits at unit distance in front of the camera, with x
+// along the direction of rotation and y towards the spin axis.
+//
+// The path and final direction are in the Cartesian frame with the spin along +y and the camera
+// at (0, D cos(inclination), -D sin(inclination)), so an equatorial camera matches RAY_START_DIR.
+//
+// Kerr rays are stepped with RK4 in Boyer-Lindquist coordinates, so any other integrator in
+// `config` is an error.
+pub fn cast_kerr_ray_response(
+    x: f64,
+    y: f64,
+    camera_distance: f64,
+    inclination: f64,
+    black_hole_radius: f64,
+    spin: f64,
+    config: RayCastConfig,
+) -> Result<Response, KerrRayError> {
+    config
+        .integrator
+        .require_rk4()
+        .map_err(KerrRayError::Integrator)?;
+    let field = KerrField::new(black_hole_radius, spin).map_err(KerrRayError::Spin)?;
+    let mut particle = field.spawn_particle(camera_distance, inclination, DVec3::new(x, y, 1.));
+    let mut distance = 0.0;
+    let mut steps = Vec::new();
+    let escape_radius = config.escape_radius(camera_distance);
+    let max_distance = config.max_distance(camera_distance);
+    let mut prev = field.to_cartesian(&particle);
+    while particle.r < escape_radius && distance < max_distance {
+        steps.push(prev);
+        if kerr_hit(&particle, &field) {
+            return Ok(Response::new(steps, None));
+        }
+        step_kerr_particle(&mut particle, &field);
+        let p = field.to_cartesian(&particle);
+        distance += (p - prev).length();
+        prev = p;
+    }
+    if distance >= max_distance {
+        return Ok(Response::new(steps, None));
+    }
+    steps.push(prev);
+    let final_dir = (steps[steps.len() - 1] - steps[steps.len() - 2]).normalize();
+    Ok(Response::new(steps, Some(final_dir)))
+}
+
+// Casts a ray from `start` along `start_dir` through several point masses. Nothing is symmetric,
+// so the ray is fully 3D and distances are measured from the center of mass.
+pub fn cast_multi_ray_response(
+    start: DVec3,
+    start_dir: DVec3,
+    field: &MultiField,
+    config: RayCastConfig,
+) -> Response {
+    let center = field.center();
+    let camera_distance = (start - center).length();
+    let mut particle = field.spawn_particle(start, start_dir);
+    let mut integrator = config.integrator.integrator(&particle);
+    let mut distance = 0.0;
+    let mut steps = Vec::new();
+    let escape_radius = config.escape_radius(camera_distance);
+    let max_distance = config.max_distance(camera_distance);
+    while (particle.p - center).length() < escape_radius && distance < max_distance {
+        steps.push(particle.p);
+        let prev = particle.p;
+        if multi_hit(&particle, field) || integrator.step(&mut particle, field).is_err() {
+            return Response::new(steps, None).with_step_stats(integrator.stats());
+        }
+        distance += (particle.p - prev).length();
+    }
+    if distance >= max_distance {
+        return Response::new(steps, None).with_step_stats(integrator.stats());
+    }
+    steps.push(particle.p);
+    Response::new(steps, Some(particle.v.normalize())).with_step_stats(integrator.stats())
+}
+
+// Casts a ray from a camera `camera_distance` from the throat of an Ellis wormhole, in the same
+// frame and with the same z as `cast_ray_steps_response`. Rays with an impact parameter below the
+// throat radius pass through to the far side; the rest bend around the throat and come back.
+// `Response::universe` says which, and the far side of the path is mirrored through the throat.
+//
+// Nothing falls in, so the final direction is only missing for rays that circle the throat until
+// they run out of distance. The ray is stepped with RK4 in its own coordinates, so any other
+// integrator in `config` is an error.
+pub fn cast_wormhole_ray_response(
+    z: f64,
+    camera_distance: f64,
+    throat_radius: f64,
+    config: RayCastConfig,
+) -> Result<Response, UnsupportedIntegrator> {
+    config.integrator.require_rk4()?;
+    let field = WormholeField::new(throat_radius);
+    let start_dir = DVec3::new((1.0 - z * z).sqrt(), 0.0, z);
+    let mut particle = field.spawn_particle(camera_distance, start_dir);
+    let mut distance = 0.0;
+    let mut steps = Vec::new();
+    let escape_radius = config.escape_radius(camera_distance);
+    let max_distance = config.max_distance(camera_distance);
+    let mut prev = field.to_cartesian(&particle);
+    while particle.l.abs() < escape_radius && distance < max_distance {
+        steps.push(prev);
+        // The affine parameter is proper distance along the ray, which doesn't jump when the
+        // path crosses the throat.
+        distance += step_wormhole_particle(&mut particle, &field);
+        prev = field.to_cartesian(&particle);
+    }
+    if distance >= max_distance {
+        return Ok(Response::new(steps, None).with_universe(particle.universe()));
+    }
+    steps.push(prev);
+    let angle = field.direction_angle(&particle);
+    let final_dir = DVec3::new(angle.sin(), 0., angle.cos());
+    Ok(Response::new(steps, Some(final_dir)).with_universe(particle.universe()))
 }
+
 const Z_EPSILON: f64 = 0.000000001;
 
 pub fn find_optimal_z(
     camera_distance: f32,
     black_hole_radius: f32,
+    model: FieldModel,
+    config: RayCastConfig,
     z_bounds: (f64, f64),
     is_too_close: &TooClosePredicate,
 ) -> (f64, f64) {
     let mut z_bounds = z_bounds;
     while z_bounds.1 - z_bounds.0 > Z_EPSILON {
         let z = 0.5 * (z_bounds.0 + z_bounds.1);
-        let response = cast_ray_steps_response(z, camera_distance as f64, black_hole_radius as f64);
+        let response = cast_ray_steps_response(
+            z,
+            camera_distance as f64,
+            black_hole_radius as f64,
+            model,
+            config,
+        );
         if is_too_close(response) {
             // too close
             z_bounds.1 = z;
@@ -85,50 +290,107 @@ pub fn find_optimal_z(
     z_bounds
 }
 
-fn find_bound_with_grazing_distance(
+// Like `find_optimal_z`, for predicates that only need the summary of a ray. The predicate is
+// also checked after every step to stop rays early, so once it holds for part of a ray it has to
+// hold for the whole ray, like a minimum distance or maximum angle does.
+pub fn find_optimal_z_by_summary(
     camera_distance: f32,
-    field: &Field,
-    epsilon: f64,
-    max_distance: f64,
-    grazing_distance: f64,
-) -> f64 {
-    let mut far_z = -1.0;
-    let mut close_z = find_bound(camera_distance, field, epsilon, max_distance);
-    while close_z - far_z > epsilon {
-        let z = 0.5 * (close_z + far_z);
+    black_hole_radius: f32,
+    model: FieldModel,
+    config: RayCastConfig,
+    z_bounds: (f64, f64),
+    is_too_close: &TooCloseSummaryPredicate,
+) -> (f64, f64) {
+    let field = Field::new(black_hole_radius as f64, camera_distance as f64, model);
+    let mut z_bounds = z_bounds;
+    while z_bounds.1 - z_bounds.0 > Z_EPSILON {
+        let z = 0.5 * (z_bounds.0 + z_bounds.1);
         let test = DVec3::new((1.0 - z * z).sqrt(), 0.0, z);
-        let (path, _) = cast_ray_steps(
+        let summary = cast_ray_visit(
             camera_distance as f64,
             test,
-            field,
-            max_distance,
-            10.0 * max_distance,
+            &field,
+            config,
+            |_, summary| match is_too_close(summary) {
+                true => StepControl::Stop,
+                false => StepControl::Continue,
+            },
         );
-        let min_dis = path.iter().map(|v| v.length()).reduce(f64::min).unwrap();
-        if min_dis < grazing_distance {
+        if is_too_close(&summary) {
             // too close
-            close_z = test.z;
+            z_bounds.1 = z;
         } else {
-            far_z = test.z;
+            z_bounds.0 = z;
         }
     }
-    far_z
+    z_bounds
 }
 
-fn find_bound(camera_distance: f32, field: &Field, epsilon: f64, max_distance: f64) -> f64 {
+// How far from the hole the ray `cast_ray_steps_response` casts for z is when it first sweeps
+// through `target_angle`, like `get_angle_dist().get_dist(target_angle)` without keeping the path.
+pub fn dist_at_angle(
+    z: f64,
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
+    target_angle: f64,
+) -> Option<f64> {
+    let test = DVec3::new((1.0 - z * z).sqrt(), 0.0, z);
+    let field = Field::new(black_hole_radius, camera_distance, model);
+    let mut crossing = AngleCrossing::new(target_angle);
+    let summary = cast_ray_visit(camera_distance, test, &field, config, |_, summary| {
+        crossing.visit(summary);
+        // Nothing after the crossing matters.
+        match crossing.distance {
+            Some(_) => StepControl::Stop,
+            None => StepControl::Continue,
+        }
+    });
+    crossing.visit(&summary);
+    crossing.distance
+}
+
+// Like `find_optimal_z`, for predicates on `dist_at_angle`.
+pub fn find_optimal_z_by_dist_at_angle(
+    camera_distance: f32,
+    black_hole_radius: f32,
+    model: FieldModel,
+    config: RayCastConfig,
+    z_bounds: (f64, f64),
+    target_angle: f64,
+    is_too_close: &dyn Fn(Option<f64>) -> bool,
+) -> (f64, f64) {
+    let mut z_bounds = z_bounds;
+    while z_bounds.1 - z_bounds.0 > Z_EPSILON {
+        let z = 0.5 * (z_bounds.0 + z_bounds.1);
+        let dist = dist_at_angle(
+            z,
+            camera_distance as f64,
+            black_hole_radius as f64,
+            model,
+            config,
+            target_angle,
+        );
+        if is_too_close(dist) {
+            // too close
+            z_bounds.1 = z;
+        } else {
+            z_bounds.0 = z;
+        }
+    }
+    z_bounds
+}
+
+pub fn find_bound(camera_distance: f32, field: &Field, epsilon: f64, config: RayCastConfig) -> f64 {
     let (mut miss_z, mut hit_z) = (-1.0, 1.0);
     while hit_z - miss_z > epsilon {
         let z = 0.5 * (hit_z + miss_z);
         let test = DVec3::new((1.0 - z * z).sqrt(), 0.0, z);
-        let final_dir = cast_ray_steps(
-            camera_distance as f64,
-            test,
-            field,
-            max_distance,
-            10.0 * max_distance,
-        )
-        .1;
-        if final_dir.is_none() {
+        let summary = cast_ray_visit(camera_distance as f64, test, field, config, |_, _| {
+            StepControl::Continue
+        });
+        if summary.hits_black_hole() {
             // hit the black hole
             hit_z = test.z;
         } else {
@@ -137,3 +399,887 @@ fn find_bound(camera_distance: f32, field: &Field, epsilon: f64, max_distance: f
     }
     miss_z
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use glam::DVec3;
+
+    use crate::path_integration2::{
+        analytic::{
+            closest_approach, critical_impact_parameter, finite_deflection, radius_at_angle,
+        },
+        impact_parameter::{impact_parameter, z_for_impact_parameter},
+        ray_cast_config::RayCastConfig,
+        ray_summary::{StepControl, Termination},
+        response::{CrossingDirection, Response},
+        structs::{
+            adaptive_step::Tolerance,
+            field::{Field, FieldModel},
+            integrator::{IntegratorKind, UnsupportedIntegrator},
+            kerr_field::SpinOutOfRange,
+            wormhole_field::Universe,
+        },
+    };
+
+    use super::{
+        cast_kerr_ray_response, cast_ray_steps_response, cast_ray_summary, cast_ray_visit,
+        cast_wormhole_ray_response, dist_at_angle, find_optimal_z, find_optimal_z_by_dist_at_angle,
+        find_optimal_z_by_summary, KerrRayError, RAY_START_DIR,
+    };
+
+    #[test]
+    fn schwarzschild_weak_field_deflection() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 1000.;
+        for b in [50., 100., 200.] {
+            let z = z_for_impact_parameter(
+                b,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+            )
+            .unwrap();
+            let response = cast_ray_steps_response(
+                z,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+                RayCastConfig::default(),
+            );
+            let final_dir = response.final_dir.unwrap();
+            let initial_dir = (response.path[1] - response.path[0]).normalize();
+            let deflection = initial_dir.dot(final_dir).clamp(-1., 1.).acos();
+
+            // Weak field deflection accumulated between the camera and the escape radius.
+            let final_distance = response.path.last().unwrap().length();
+            let start = (1. - (b / camera_distance).powi(2)).sqrt();
+            let end = (1. - (b / final_distance).powi(2)).sqrt();
+            let expected = black_hole_radius / b * (start + end);
+            assert!(
+                (deflection - expected).abs() < 0.05 * expected,
+                "b: {}\ndeflection: {}\nexpected: {}",
+                b,
+                deflection,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn schwarzschild_matches_analytic_deflection() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        for b in [
+            1.01 * critical_b,
+            1.2 * critical_b,
+            2. * critical_b,
+            4. * critical_b,
+        ] {
+            let z = z_for_impact_parameter(
+                b,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+            )
+            .unwrap();
+            let response = cast_ray_steps_response(
+                z,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+                RayCastConfig::with_integrator(IntegratorKind::Rk45(Tolerance::default())),
+            );
+            let final_distance = response.path.last().unwrap().length();
+            let expected =
+                finite_deflection(b, black_hole_radius, camera_distance, final_distance, true)
+                    .unwrap();
+            // Gravity turns the direction clockwise in the x-z plane. Unwrap the final angle so
+            // loops around the hole aren't lost.
+            let initial_angle = f64::atan2((1. - z * z).sqrt(), z);
+            let final_dir = response.final_dir.unwrap();
+            let mut final_angle = f64::atan2(final_dir.x, final_dir.z);
+            while final_angle > initial_angle - expected + std::f64::consts::PI {
+                final_angle -= std::f64::consts::TAU;
+            }
+            let actual = initial_angle - final_angle;
+            assert!(
+                (actual - expected).abs() < 0.0001,
+                "b: {}\nexpected: {}\nactual: {}",
+                b,
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn plane_crossings_follow_swept_angle() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        for b in [1.0001 * critical_b, 1.01 * critical_b, 4. * critical_b] {
+            let z = z_for_impact_parameter(
+                b,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+            )
+            .unwrap();
+            let response = cast_ray_steps_response(
+                z,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+                RayCastConfig::default(),
+            );
+            // The ray starts on the x = 0 plane at angle 0 and crosses it every half turn.
+            let angle_path = response.get_angle_dist();
+            let crossings = response.plane_crossings(DVec3::X);
+            let expected_count = (angle_path.get_max_angle() / PI).floor() as usize;
+            assert_eq!(crossings.len(), expected_count, "b: {}", b);
+            for (n, crossing) in crossings.iter().enumerate() {
+                assert_eq!(crossing.order, n);
+                let expected_direction = match n % 2 {
+                    0 => CrossingDirection::Downward,
+                    _ => CrossingDirection::Upward,
+                };
+                assert_eq!(crossing.direction, expected_direction);
+                assert!(crossing.position.x.abs() < 1e-9);
+                let expected_radius = angle_path.get_dist((n + 1) as f64 * PI).unwrap();
+                assert!(
+                    (crossing.radius - expected_radius).abs() < 0.001 * expected_radius,
+                    "b: {}\nn: {}\nexpected: {}\nactual: {}",
+                    b,
+                    n,
+                    expected_radius,
+                    crossing.radius
+                );
+                let (start, end) = (
+                    response.path[crossing.step_index],
+                    response.path[crossing.step_index + 1],
+                );
+                assert!(start.x * end.x <= 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn schwarzschild_shapiro_delay() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 1000.;
+        for b in [50., 100., 200.] {
+            let z = z_for_impact_parameter(
+                b,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+            )
+            .unwrap();
+            let response = cast_ray_steps_response(
+                z,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+                RayCastConfig::default(),
+            );
+            let final_distance = response.path.last().unwrap().length();
+            let flat = |r: f64| (r * r - b * b).sqrt();
+            // First order coordinate time from closest approach out to r.
+            let r_0 = closest_approach(b, black_hole_radius).unwrap();
+            let time = |r: f64| {
+                let leg = (r * r - r_0 * r_0).sqrt();
+                leg + black_hole_radius * ((r + leg) / r_0).ln()
+                    + 0.5 * black_hole_radius * ((r - r_0) / (r + r_0)).sqrt()
+            };
+            let expected = time(camera_distance) + time(final_distance)
+                - flat(camera_distance)
+                - flat(final_distance);
+            let actual =
+                response.times.last().unwrap() - flat(camera_distance) - flat(final_distance);
+            assert!(
+                (actual - expected).abs() < 0.02 * expected,
+                "b: {}\nexpected: {}\nactual: {}",
+                b,
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn pseudo_force_time_is_path_length() {
+        let response = cast_ray_steps_response(
+            0.1,
+            10.,
+            1.5,
+            FieldModel::PseudoForce,
+            RayCastConfig::default(),
+        );
+        let length: f64 = response
+            .path
+            .windows(2)
+            .map(|w| (w[1] - w[0]).length())
+            .sum();
+        assert_eq!(response.times.len(), response.path.len());
+        assert!((response.times.last().unwrap() - length).abs() < 1e-9);
+        let max_angle = response.get_angle_dist().get_max_angle();
+        assert!(
+            response.get_time(0.5 * max_angle).unwrap() < response.get_time(max_angle).unwrap()
+        );
+    }
+
+    #[test]
+    fn schwarzschild_critical_impact_parameter() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        let inside = z_for_impact_parameter(
+            0.99 * critical_b,
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+        )
+        .unwrap();
+        let outside = z_for_impact_parameter(
+            1.01 * critical_b,
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+        )
+        .unwrap();
+        assert!(cast_ray_steps_response(
+            inside,
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+        )
+        .hits_black_hole());
+        assert!(!cast_ray_steps_response(
+            outside,
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+        )
+        .hits_black_hole());
+    }
+
+    #[test]
+    fn adaptive_tolerance_controls_error() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        let z = z_for_impact_parameter(
+            1.2 * critical_b,
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+        )
+        .unwrap();
+        let cast = |tolerance: f64| {
+            cast_ray_steps_response(
+                z,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+                RayCastConfig::with_integrator(IntegratorKind::Rk45(Tolerance {
+                    absolute: tolerance,
+                    relative: tolerance,
+                })),
+            )
+        };
+        let reference = cast(0.000000000001).final_dir.unwrap();
+        let mut previous_error = f64::MAX;
+        let mut previous_steps = 0;
+        for tolerance in [0.0001, 0.0000001, 0.0000000001] {
+            let response = cast(tolerance);
+            let error = (response.final_dir.unwrap() - reference).length();
+            let stats = response.step_stats;
+            assert_eq!(stats.accepted + 1, response.path.len());
+            assert!(stats.min_step <= stats.max_step);
+            assert!(stats.accepted > previous_steps);
+            assert!(error < previous_error);
+            // The summed local estimates should bound the global error of the final direction.
+            assert!(error < 10. * stats.error_estimate);
+            previous_error = error;
+            previous_steps = stats.accepted;
+        }
+    }
+
+    #[test]
+    fn adaptive_spends_steps_near_photon_sphere() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        let grazing = z_for_impact_parameter(
+            1.001 * critical_b,
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+        )
+        .unwrap();
+        let far = z_for_impact_parameter(
+            5. * critical_b,
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+        )
+        .unwrap();
+        let cast = |z| {
+            cast_ray_steps_response(
+                z,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+                RayCastConfig::with_integrator(IntegratorKind::Rk45(Tolerance::default())),
+            )
+        };
+        let grazing = cast(grazing);
+        let far = cast(far);
+        assert!(!grazing.hits_black_hole());
+        assert!(grazing.step_stats.min_step < far.step_stats.min_step);
+        assert!(grazing.step_stats.accepted > far.step_stats.accepted);
+
+        // The grazing ray loops around the hole before escaping, like with the fixed stepper.
+        let fixed = cast_ray_steps_response(
+            z_for_impact_parameter(
+                1.001 * critical_b,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+            )
+            .unwrap(),
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+        );
+        let angle_error = (grazing.get_angle_dist().get_max_angle()
+            - fixed.get_angle_dist().get_max_angle())
+        .abs();
+        assert!(angle_error < 0.05, "angle error: {}", angle_error);
+    }
+
+    #[test]
+    fn integrators_agree() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        for b in [1.2 * critical_b, 3. * critical_b] {
+            let z = z_for_impact_parameter(
+                b,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+            )
+            .unwrap();
+            let cast = |integrator| {
+                cast_ray_steps_response(
+                    z,
+                    camera_distance,
+                    black_hole_radius,
+                    FieldModel::Schwarzschild,
+                    RayCastConfig::with_integrator(integrator),
+                )
+            };
+            let reference = cast(IntegratorKind::Rk45(Tolerance::default()));
+            for integrator in [IntegratorKind::Rk4, IntegratorKind::Leapfrog] {
+                let response = cast(integrator);
+                let error = (response.final_dir.unwrap() - reference.final_dir.unwrap()).length();
+                assert!(error < 0.01, "{:?}, b: {}, error: {}", integrator, b, error);
+                assert_eq!(response.step_stats.accepted + 1, response.path.len());
+            }
+        }
+    }
+
+    #[test]
+    fn escape_radius_is_configurable() {
+        let config = RayCastConfig {
+            escape_radius_scale: 2.,
+            ..RayCastConfig::default()
+        };
+        let response = cast_ray_steps_response(0., 20., 1.5, FieldModel::Schwarzschild, config);
+        let final_distance = response.path.last().unwrap().length();
+        assert!((40. ..50.).contains(&final_distance), "{final_distance}");
+    }
+
+    #[test]
+    fn conserved_quantities_drift() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        let z = z_for_impact_parameter(
+            1.05 * critical_b,
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+        )
+        .unwrap();
+        let cast = |model, integrator| {
+            cast_ray_steps_response(
+                z,
+                camera_distance,
+                black_hole_radius,
+                model,
+                RayCastConfig::with_integrator(integrator),
+            )
+            .drift
+        };
+        for model in [FieldModel::PseudoForce, FieldModel::Schwarzschild] {
+            let drift = cast(model, IntegratorKind::Rk4);
+            assert!(drift.max() < 0.0001, "{:?}: {:?}", model, drift);
+        }
+
+        let loose = Tolerance {
+            absolute: 0.01,
+            relative: 0.01,
+        };
+        let loose = cast(FieldModel::Schwarzschild, IntegratorKind::Rk45(loose));
+        let tight = cast(
+            FieldModel::Schwarzschild,
+            IntegratorKind::Rk45(Tolerance::default()),
+        );
+        assert!(loose.energy > tight.energy);
+        assert!(loose.max() > 10. * tight.max());
+    }
+
+    // Image plane x for a ray with impact parameter b, for a static camera outside a
+    // non-rotating hole.
+    fn x_for_impact_parameter(b: f64, camera_distance: f64, black_hole_radius: f64) -> f64 {
+        let sin = b * (1. - black_hole_radius / camera_distance).sqrt() / camera_distance;
+        sin / (1. - sin * sin).sqrt()
+    }
+
+    #[test]
+    fn kerr_without_spin_matches_schwarzschild() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let critical_b = critical_impact_parameter(black_hole_radius);
+        let kerr_response = |b: f64| {
+            let x = x_for_impact_parameter(b, camera_distance, black_hole_radius);
+            cast_kerr_ray_response(
+                x,
+                0.,
+                camera_distance,
+                std::f64::consts::FRAC_PI_2,
+                black_hole_radius,
+                0.,
+                RayCastConfig::default(),
+            )
+            .unwrap()
+        };
+        assert!(kerr_response(0.99 * critical_b).hits_black_hole());
+        assert!(!kerr_response(1.01 * critical_b).hits_black_hole());
+
+        for b in [1.1 * critical_b, 2. * critical_b, 5. * critical_b] {
+            let z = z_for_impact_parameter(
+                b,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+            )
+            .unwrap();
+            let expected = cast_ray_steps_response(
+                z,
+                camera_distance,
+                black_hole_radius,
+                FieldModel::Schwarzschild,
+                RayCastConfig::default(),
+            )
+            .final_dir
+            .unwrap();
+            let actual = kerr_response(b).final_dir.unwrap();
+            assert!(
+                (expected - actual).length() < 0.01,
+                "b: {}\nexpected: {}\nactual: {}",
+                b,
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn kerr_shadow_is_shifted_by_spin() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 50.;
+        let m = 0.5 * black_hole_radius;
+        // Between the prograde (~2.9M) and retrograde (~6.9M) photon orbits for a = 0.9M.
+        let x = x_for_impact_parameter(4.5 * m, camera_distance, black_hole_radius);
+        let cast = |x: f64| {
+            cast_kerr_ray_response(
+                x,
+                0.,
+                camera_distance,
+                std::f64::consts::FRAC_PI_2,
+                black_hole_radius,
+                0.9,
+                RayCastConfig::default(),
+            )
+            .unwrap()
+        };
+        assert!(!cast(x).hits_black_hole());
+        assert!(cast(-x).hits_black_hole());
+
+        // Frame dragging breaks the mirror symmetry of the deflection, but not the symmetry
+        // across the equatorial plane.
+        let prograde = cast(3. * x).final_dir.unwrap();
+        let retrograde = cast(-3. * x).final_dir.unwrap();
+        assert!((prograde.x + retrograde.x).abs() > 0.01);
+        let above = cast_kerr_ray_response(
+            x,
+            3. * x,
+            camera_distance,
+            std::f64::consts::FRAC_PI_2,
+            black_hole_radius,
+            0.9,
+            RayCastConfig::default(),
+        )
+        .unwrap()
+        .final_dir
+        .unwrap();
+        let below = cast_kerr_ray_response(
+            x,
+            -3. * x,
+            camera_distance,
+            std::f64::consts::FRAC_PI_2,
+            black_hole_radius,
+            0.9,
+            RayCastConfig::default(),
+        )
+        .unwrap()
+        .final_dir
+        .unwrap();
+        assert!((above.y + below.y).abs() < 0.0001);
+        assert!((above.x - below.x).abs() < 0.0001);
+    }
+
+    #[test]
+    fn wormhole_throat_splits_rays() {
+        let throat_radius: f64 = 1.;
+        let camera_distance: f64 = 20.;
+        let camera_radius =
+            (camera_distance * camera_distance + throat_radius * throat_radius).sqrt();
+        for (b, universe) in [
+            (0., Universe::Far),
+            (0.5, Universe::Far),
+            (0.95, Universe::Far),
+            (1.05, Universe::Near),
+            (2., Universe::Near),
+            (5., Universe::Near),
+        ] {
+            let z = (1. - (b / camera_radius).powi(2)).sqrt();
+            let response = cast_wormhole_ray_response(
+                z,
+                camera_distance,
+                throat_radius,
+                RayCastConfig::default(),
+            )
+            .unwrap();
+            assert_eq!(response.universe, universe, "b: {}", b);
+            assert!(response.final_dir.is_some(), "b: {}", b);
+            if universe == Universe::Near {
+                // Rays that come back turn around where the areal radius equals b.
+                let closest_approach = response
+                    .path
+                    .iter()
+                    .map(|p| p.length())
+                    .reduce(f64::min)
+                    .unwrap();
+                assert!(
+                    (closest_approach - b).abs() < 0.01 * b,
+                    "b: {}, closest approach: {}",
+                    b,
+                    closest_approach
+                );
+            }
+        }
+        // A ray through the middle of the throat isn't deflected at all.
+        let straight = cast_wormhole_ray_response(
+            1.,
+            camera_distance,
+            throat_radius,
+            RayCastConfig::default(),
+        )
+        .unwrap();
+        assert!((straight.final_dir.unwrap() - DVec3::Z).length() < 0.000001);
+    }
+
+    #[test]
+    fn kerr_rejects_bad_parameters() {
+        let cast = |spin, config| {
+            cast_kerr_ray_response(0.1, 0., 20., std::f64::consts::FRAC_PI_2, 1.5, spin, config)
+        };
+        assert_eq!(
+            cast(1.5, RayCastConfig::default()).err(),
+            Some(KerrRayError::Spin(SpinOutOfRange(1.5)))
+        );
+        let config = RayCastConfig::with_integrator(IntegratorKind::Leapfrog);
+        assert_eq!(
+            cast(0.5, config).err(),
+            Some(KerrRayError::Integrator(UnsupportedIntegrator(
+                IntegratorKind::Leapfrog
+            )))
+        );
+    }
+
+    #[test]
+    fn wormhole_rejects_other_integrators() {
+        for integrator in [
+            IntegratorKind::Leapfrog,
+            IntegratorKind::Rk45(Tolerance::default()),
+        ] {
+            let config = RayCastConfig::with_integrator(integrator);
+            assert_eq!(
+                cast_wormhole_ray_response(0.5, 20., 1., config).err(),
+                Some(UnsupportedIntegrator(integrator))
+            );
+        }
+    }
+
+    #[test]
+    fn wormhole_weak_field_deflection() {
+        let throat_radius: f64 = 1.;
+        let camera_distance: f64 = 1000.;
+        for b in [10_f64, 20., 40.] {
+            let z = (1. - (b / camera_distance).powi(2)).sqrt();
+            let response = cast_wormhole_ray_response(
+                z,
+                camera_distance,
+                throat_radius,
+                RayCastConfig::default(),
+            )
+            .unwrap();
+            let final_dir = response.final_dir.unwrap();
+            let deflection =
+                f64::atan2((1. - z * z).sqrt(), z) - f64::atan2(final_dir.x, final_dir.z);
+            let expected = 0.25 * PI * (throat_radius / b).powi(2);
+            assert!(
+                (deflection - expected).abs() < 0.02 * expected,
+                "b: {}\ndeflection: {}\nexpected: {}",
+                b,
+                deflection,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn summary_matches_response() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        for model in [
+            FieldModel::PseudoForce,
+            FieldModel::Schwarzschild,
+            FieldModel::ReissnerNordstrom { charge: 0.8 },
+        ] {
+            for z in [-0.5, 0.3, 0.9, 0.96, 0.97, 0.99] {
+                let response = cast_ray_steps_response(
+                    z,
+                    camera_distance,
+                    black_hole_radius,
+                    model,
+                    RayCastConfig::default(),
+                );
+                let summary = cast_ray_summary(
+                    z,
+                    camera_distance,
+                    black_hole_radius,
+                    model,
+                    RayCastConfig::default(),
+                );
+                let min_distance = response
+                    .path
+                    .iter()
+                    .map(|p| p.length())
+                    .fold(f64::INFINITY, f64::min);
+                assert_eq!(
+                    summary.final_dir, response.final_dir,
+                    "{:?}, z: {}",
+                    model, z
+                );
+                assert_eq!(summary.hits_black_hole(), response.hits_black_hole());
+                assert_eq!(summary.steps, response.path.len());
+                assert_eq!(
+                    summary.initial_dir,
+                    Some((response.path[1] - response.path[0]).normalize())
+                );
+                assert_eq!(summary.min_distance, min_distance);
+                assert_eq!(summary.max_angle, response.get_angle_dist().get_max_angle());
+                let expected_termination = match response.final_dir {
+                    Some(_) => Termination::Escaped,
+                    None => Termination::Captured,
+                };
+                assert_eq!(summary.termination, expected_termination);
+            }
+        }
+    }
+
+    #[test]
+    fn visitor_stops_early() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let field = Field::new(
+            black_hole_radius,
+            camera_distance,
+            FieldModel::Schwarzschild,
+        );
+        let start_dir = DVec3::new(0.6, 0., 0.8);
+        let full = cast_ray_visit(
+            camera_distance,
+            start_dir,
+            &field,
+            RayCastConfig::default(),
+            |_, _| StepControl::Continue,
+        );
+        assert_eq!(full.termination, Termination::Escaped);
+
+        let mut visited = Vec::new();
+        let stopped = cast_ray_visit(
+            camera_distance,
+            start_dir,
+            &field,
+            RayCastConfig::default(),
+            |particle, summary| {
+                visited.push(particle.p);
+                match summary.min_distance < 15. {
+                    true => StepControl::Stop,
+                    false => StepControl::Continue,
+                }
+            },
+        );
+        assert_eq!(stopped.termination, Termination::Stopped);
+        assert!(stopped.hits_black_hole());
+        assert!(stopped.steps < full.steps);
+        assert_eq!(stopped.steps, visited.len());
+        assert_eq!(visited[0], camera_distance * RAY_START_DIR);
+        assert!(stopped.min_distance < 15.);
+    }
+
+    #[test]
+    fn failed_steps_end_the_ray() {
+        let (camera_distance, black_hole_radius) = (20., 1.5);
+        let cast = |z: f64, integrator| {
+            let config = RayCastConfig::with_integrator(integrator);
+            let model = FieldModel::Schwarzschild;
+            (
+                cast_ray_summary(z, camera_distance, black_hole_radius, model, config),
+                cast_ray_steps_response(z, camera_distance, black_hole_radius, model, config),
+            )
+        };
+        // A NaN state isn't an escape.
+        for integrator in [
+            IntegratorKind::Rk4,
+            IntegratorKind::Rk45(Tolerance::default()),
+            IntegratorKind::Leapfrog,
+        ] {
+            let (summary, response) = cast(f64::NAN, integrator);
+            assert_eq!(summary.termination, Termination::Failed, "{:?}", integrator);
+            assert!(summary.hits_black_hole());
+            assert_eq!(response.final_dir, None);
+        }
+
+        // No step meets a zero tolerance, so the step size bottoms out instead of shrinking
+        // forever.
+        let exact = Tolerance {
+            absolute: 0.,
+            relative: 0.,
+        };
+        let (summary, response) = cast(0.5, IntegratorKind::Rk45(exact));
+        assert_eq!(summary.termination, Termination::Failed);
+        assert_eq!(response.final_dir, None);
+        assert!(response.step_stats.rejected > 0);
+    }
+
+    #[test]
+    fn summary_bisection_matches_response_bisection() {
+        let (camera_distance, black_hole_radius) = (17., 1.5);
+        let z_bounds = find_optimal_z(
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+            (-1., 1.),
+            &|r: Response| r.hits_black_hole() || r.get_angle_dist().get_max_angle() > 1.5 * PI,
+        );
+        // Stopping early on the running max angle doesn't move the bound.
+        let summary_bounds = find_optimal_z_by_summary(
+            camera_distance,
+            black_hole_radius,
+            FieldModel::Schwarzschild,
+            RayCastConfig::default(),
+            (-1., 1.),
+            &|r| r.hits_black_hole() || r.max_angle > 1.5 * PI,
+        );
+        assert_eq!(z_bounds, summary_bounds);
+    }
+
+    #[test]
+    fn dist_at_angle_matches_analytic() {
+        let (camera_distance, black_hole_radius) = (17., 1.5);
+        let model = FieldModel::Schwarzschild;
+        let config = RayCastConfig::default();
+        let r_max = config.escape_radius(camera_distance);
+        for z in [0.3, 0.9, 0.96] {
+            let response =
+                cast_ray_steps_response(z, camera_distance, black_hole_radius, model, config);
+            let b = impact_parameter(z, camera_distance, black_hole_radius, model);
+            for angle in [0.5, 0.5 * PI, PI, 1.5 * PI] {
+                let actual =
+                    dist_at_angle(z, camera_distance, black_hole_radius, model, config, angle);
+                let reaches = response.get_angle_dist().get_dist(angle).is_some();
+                assert_eq!(actual.is_some(), reaches, "z: {}, angle: {}", z, angle);
+                let expected = match radius_at_angle(
+                    b,
+                    black_hole_radius,
+                    camera_distance,
+                    r_max,
+                    true,
+                    angle,
+                ) {
+                    Some(expected) if reaches => expected,
+                    _ => continue,
+                };
+                let actual = actual.unwrap();
+                assert!(
+                    (expected - actual).abs() < 0.002 * expected,
+                    "z: {}, angle: {}, expected: {}, actual: {}",
+                    z,
+                    angle,
+                    expected,
+                    actual
+                );
+            }
+        }
+
+        // Bisecting on the crossing finds the same z as bisecting on the full path.
+        let z_bounds = find_optimal_z(
+            camera_distance as f32,
+            black_hole_radius as f32,
+            model,
+            config,
+            (0., 1.),
+            &|r: Response| match r.get_angle_dist().get_dist(PI) {
+                Some(dist) => dist <= 6.,
+                None => true,
+            },
+        );
+        let crossing_bounds = find_optimal_z_by_dist_at_angle(
+            camera_distance as f32,
+            black_hole_radius as f32,
+            model,
+            config,
+            (0., 1.),
+            PI,
+            &|dist| match dist {
+                Some(dist) => dist <= 6.,
+                None => true,
+            },
+        );
+        assert!((z_bounds.0 - crossing_bounds.0).abs() < 0.0001);
+    }
+}
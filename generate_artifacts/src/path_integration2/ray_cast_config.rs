@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    response::Response,
+    structs::{
+        drift::{DriftLimit, DriftPolicy},
+        integrator::IntegratorKind,
+    },
+};
+
+// How rays are stepped, and when we give up on them.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct RayCastConfig {
+    pub integrator: IntegratorKind,
+    // Rays further than escape_radius_scale * camera_distance have escaped.
+    pub escape_radius_scale: f64,
+    // Rays that travel further than max_distance_scale * escape radius are treated as captured.
+    pub max_distance_scale: f64,
+    // What cache builders do with samples whose conserved quantities drift too far.
+    #[serde(default)]
+    pub drift_limit: Option<DriftLimit>,
+}
+
+impl Default for RayCastConfig {
+    fn default() -> Self {
+        Self {
+            integrator: IntegratorKind::default(),
+            escape_radius_scale: 5.,
+            max_distance_scale: 20.,
+            drift_limit: None,
+        }
+    }
+}
+
+impl RayCastConfig {
+    pub fn with_integrator(integrator: IntegratorKind) -> Self {
+        Self {
+            integrator,
+            ..Self::default()
+        }
+    }
+
+    pub fn escape_radius(&self, camera_distance: f64) -> f64 {
+        self.escape_radius_scale * camera_distance
+    }
+
+    pub fn max_distance(&self, camera_distance: f64) -> f64 {
+        self.max_distance_scale * self.escape_radius(camera_distance)
+    }
+
+    // The policy to apply to this response, if its drift is over the limit.
+    pub fn drift_policy(&self, response: &Response) -> Option<DriftPolicy> {
+        self.drift_limit
+            .filter(|limit| response.drift.max() > limit.threshold)
+            .map(|limit| limit.policy)
+    }
+}
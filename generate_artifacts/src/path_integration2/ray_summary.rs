@@ -0,0 +1,137 @@
+use std::f64::consts::TAU;
+
+use glam::DVec3;
+
+use super::response::ToAngle;
+
+// Why a ray stopped being stepped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    // Still being stepped; only visitors see this.
+    InFlight,
+    Escaped,
+    Captured,
+    // Travelled further than `RayCastConfig::max_distance`, so it's treated as captured.
+    MaxDistance,
+    // The visitor asked to stop.
+    Stopped,
+    // The integrator couldn't take another step, so the ray is treated as captured.
+    Failed,
+}
+
+// Whether the visitor wants the ray stepped any further.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepControl {
+    Continue,
+    Stop,
+}
+
+// What most callers need from a ray, without keeping its path around. Matches what the
+// corresponding `Response` would report for the points stepped so far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaySummary {
+    pub final_dir: Option<DVec3>,
+    // Closest approach to the black hole.
+    pub min_distance: f64,
+    // Unwrapped angle swept around the black hole, like `AnglePath::get_max_angle`.
+    pub max_angle: f64,
+    pub termination: Termination,
+    // Number of points visited, which is the length the `Response` path would have.
+    pub steps: usize,
+    // Direction of the first step, once there's been one.
+    pub initial_dir: Option<DVec3>,
+    // Most recently visited point.
+    pub last: DVec3,
+    // Multiple of TAU added to the last angle to unwrap it.
+    angle_offset: f64,
+}
+
+impl RaySummary {
+    pub fn new() -> Self {
+        RaySummary {
+            final_dir: None,
+            min_distance: f64::INFINITY,
+            max_angle: 0.,
+            termination: Termination::InFlight,
+            steps: 0,
+            initial_dir: None,
+            last: DVec3::ZERO,
+            angle_offset: 0.,
+        }
+    }
+
+    // Rays still in flight haven't hit anything yet.
+    pub fn hits_black_hole(&self) -> bool {
+        self.termination != Termination::InFlight && self.final_dir.is_none()
+    }
+
+    pub fn visit(&mut self, p: DVec3) {
+        self.min_distance = self.min_distance.min(p.length());
+        let angle = p.get_angle();
+        // Same unwrapping as `AnglePath::new`.
+        if self.steps > 0 && self.max_angle > angle + self.angle_offset {
+            self.angle_offset += TAU;
+        }
+        self.max_angle = angle + self.angle_offset;
+        if self.steps == 1 {
+            self.initial_dir = Some((p - self.last).normalize());
+        }
+        self.last = p;
+        self.steps += 1;
+    }
+
+    pub fn terminate(self, termination: Termination, final_dir: Option<DVec3>) -> Self {
+        RaySummary {
+            termination,
+            final_dir,
+            ..self
+        }
+    }
+}
+
+// Where a ray first sweeps through `angle` around the hole, like `AnglePath::get_dist`, tracked
+// from a visitor so the path doesn't have to be kept. Visit it with the summary at every point,
+// including the last one, which visitors don't see.
+pub struct AngleCrossing {
+    angle: f64,
+    previous: Option<(f64, f64)>,
+    steps: usize,
+    // Distance from the hole at the crossing, once the ray gets there.
+    pub distance: Option<f64>,
+}
+
+impl AngleCrossing {
+    pub fn new(angle: f64) -> Self {
+        AngleCrossing {
+            angle,
+            previous: None,
+            steps: 0,
+            distance: None,
+        }
+    }
+
+    pub fn visit(&mut self, summary: &RaySummary) {
+        if self.distance.is_some() || summary.steps == self.steps {
+            return;
+        }
+        self.steps = summary.steps;
+        let (angle, distance) = (summary.max_angle, summary.last.length());
+        if angle >= self.angle {
+            self.distance = Some(match self.previous {
+                // Linear in angle between the points either side.
+                Some((previous_angle, previous_distance)) if previous_angle < self.angle => {
+                    let t = (self.angle - previous_angle) / (angle - previous_angle);
+                    previous_distance + t * (distance - previous_distance)
+                }
+                _ => distance,
+            });
+        }
+        self.previous = Some((angle, distance));
+    }
+}
+
+impl Default for RaySummary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,9 +2,18 @@ use std::f64::consts::TAU;
 
 use glam::DVec3;
 
+use super::structs::{adaptive_step::StepStats, drift::Drift, wormhole_field::Universe};
+
 pub struct Response {
     pub path: Vec<DVec3>,
     pub final_dir: Option<DVec3>,
+    // Kerr, wormhole and multi-mass rays leave the step statistics, drift and times empty.
+    pub step_stats: StepStats,
+    pub drift: Drift,
+    // Coordinate time at each point of `path`, starting from 0 at the camera.
+    pub times: Vec<f64>,
+    // Side of the throat the ray ends on. Only wormhole rays can reach the far side.
+    pub universe: Universe,
 }
 
 pub trait ToAngle<T> {
@@ -13,7 +22,30 @@ pub trait ToAngle<T> {
 
 impl Response {
     pub fn new(path: Vec<DVec3>, final_dir: Option<DVec3>) -> Self {
-        Response { path, final_dir }
+        Response {
+            path,
+            final_dir,
+            step_stats: StepStats::default(),
+            drift: Drift::default(),
+            times: Vec::new(),
+            universe: Universe::Near,
+        }
+    }
+
+    pub fn with_step_stats(self, step_stats: StepStats) -> Self {
+        Response { step_stats, ..self }
+    }
+
+    pub fn with_drift(self, drift: Drift) -> Self {
+        Response { drift, ..self }
+    }
+
+    pub fn with_times(self, times: Vec<f64>) -> Self {
+        Response { times, ..self }
+    }
+
+    pub fn with_universe(self, universe: Universe) -> Self {
+        Response { universe, ..self }
     }
 }
 
@@ -31,11 +63,56 @@ impl Response {
         AnglePath::new(&self.path)
     }
 
-    pub fn get_final_angle(&self) -> Option<f64> {
-        if self.final_dir.is_none() {
+    // Every crossing of the plane through the black hole with the given normal, in path order. The
+    // n = 0 crossing is the direct image of a disc lying in that plane, n = 1 the secondary image
+    // and so on. A path that starts on the plane doesn't count its starting point.
+    pub fn plane_crossings(&self, normal: DVec3) -> Vec<PlaneCrossing> {
+        let normal = normal.normalize();
+        let mut crossings = Vec::new();
+        for (step_index, segment) in self.path.windows(2).enumerate() {
+            let (start, end) = (segment[0], segment[1]);
+            let (start_height, end_height) = (start.dot(normal), end.dot(normal));
+            let direction = if start_height < 0. && end_height >= 0. {
+                CrossingDirection::Upward
+            } else if start_height > 0. && end_height <= 0. {
+                CrossingDirection::Downward
+            } else {
+                continue;
+            };
+            let t = start_height / (start_height - end_height);
+            let position = start.lerp(end, t);
+            crossings.push(PlaneCrossing {
+                position,
+                radius: position.length(),
+                order: crossings.len(),
+                direction,
+                step_index,
+            });
+        }
+        crossings
+    }
+
+    // Coordinate time at which the path first reaches the angle, if it does.
+    pub fn get_time(&self, angle: f64) -> Option<f64> {
+        if self.times.is_empty() {
+            return None;
+        }
+        let angle_path = self.get_angle_dist();
+        let angles = &angle_path.angle_dist;
+        if angle < 0. || angle > angles.last().unwrap().angle {
             return None;
         }
-        let mut final_angle = self.final_dir.unwrap().get_angle();
+        let right = angles.partition_point(|v| v.angle < angle);
+        if right == 0 {
+            return Some(self.times[0]);
+        }
+        let left = right - 1;
+        let t = (angle - angles[left].angle) / (angles[right].angle - angles[left].angle);
+        Some(t * self.times[right] + (1. - t) * self.times[left])
+    }
+
+    pub fn get_final_angle(&self) -> Option<f64> {
+        let mut final_angle = self.final_dir?.get_angle();
         while final_angle < self.get_angle_dist().get_max_angle() {
             final_angle += TAU;
         }
@@ -43,6 +120,24 @@ impl Response {
     }
 }
 
+// Which way a path passes through a plane, relative to the plane's normal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossingDirection {
+    Upward,
+    Downward,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaneCrossing {
+    pub position: DVec3,
+    pub radius: f64,
+    // Number of earlier crossings of the same plane.
+    pub order: usize,
+    pub direction: CrossingDirection,
+    // The crossing lies between `path[step_index]` and `path[step_index + 1]`.
+    pub step_index: usize,
+}
+
 pub struct AnglePath {
     angle_dist: Vec<AngleDist>,
 }
@@ -57,7 +152,7 @@ impl ToAngle<f64> for DVec3 {
     }
 }
 impl AnglePath {
-    pub fn new(path: &Vec<DVec3>) -> Self {
+    pub fn new(path: &[DVec3]) -> Self {
         let mut angle_dist: Vec<AngleDist> = path
             .iter()
             .map(|pos| AngleDist {
@@ -111,7 +206,7 @@ impl AnglePath {
         let right = &self.angle_dist[index + 1];
         let t = (angle - left.angle) / (right.angle - left.angle);
 
-        return Some(t * right.distance + (1. - t) * left.distance);
+        Some(t * right.distance + (1. - t) * left.distance)
     }
 }
 
@@ -0,0 +1,211 @@
+use std::f64::consts::PI;
+
+use super::{
+    impact_parameter::impact_parameter,
+    path::{cast_ray_steps_response, find_bound},
+    ray_cast_config::RayCastConfig,
+    structs::field::{Field, FieldModel},
+};
+
+const Z_EPSILON: f64 = 0.000000001;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSize {
+    pub photon_sphere_radius: f64,
+    pub critical_impact_parameter: f64,
+    // Angle between the direction to the hole and the shadow edge, as seen by a static camera.
+    pub angular_radius: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowComparison {
+    pub analytic: ShadowSize,
+    pub numerical: ShadowSize,
+}
+
+impl ShadowComparison {
+    // Numerical minus analytic, per field.
+    pub fn difference(&self) -> ShadowSize {
+        ShadowSize {
+            photon_sphere_radius: self.numerical.photon_sphere_radius
+                - self.analytic.photon_sphere_radius,
+            critical_impact_parameter: self.numerical.critical_impact_parameter
+                - self.analytic.critical_impact_parameter,
+            angular_radius: self.numerical.angular_radius - self.analytic.angular_radius,
+        }
+    }
+}
+
+// Angle from the direction to the hole of the ray with impact parameter b, for a static camera.
+// Inside the photon sphere the shadow covers more than half the sky, so the edge is past 90 degrees.
+fn view_angle(b: f64, camera_distance: f64, field: &Field) -> f64 {
+    let sin = (b * field.metric_factor(camera_distance).sqrt() / camera_distance).min(1.);
+    match camera_distance < field.photon_sphere_radius() {
+        true => PI - sin.asin(),
+        false => sin.asin(),
+    }
+}
+
+pub fn angular_shadow_radius(
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+) -> f64 {
+    let field = Field::new(black_hole_radius, camera_distance, model);
+    view_angle(field.critical_impact_parameter(), camera_distance, &field)
+}
+
+// The pseudo force has no metric of its own, so it gets the Schwarzschild shadow.
+pub fn analytic_shadow(
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+) -> ShadowSize {
+    let field = Field::new(black_hole_radius, camera_distance, model);
+    ShadowSize {
+        photon_sphere_radius: field.photon_sphere_radius(),
+        critical_impact_parameter: field.critical_impact_parameter(),
+        angular_radius: view_angle(field.critical_impact_parameter(), camera_distance, &field),
+    }
+}
+
+// Finds the shadow edge by bisecting with `find_bound`. The photon sphere is the closest approach
+// of the last ray that escapes.
+pub fn numerical_shadow(
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
+) -> ShadowSize {
+    let field = Field::new(black_hole_radius, camera_distance, model);
+    let z = find_bound(camera_distance as f32, &field, Z_EPSILON, config);
+    let b = impact_parameter(z, camera_distance, black_hole_radius, model);
+    let response = cast_ray_steps_response(z, camera_distance, black_hole_radius, model, config);
+    let closest_approach = response
+        .path
+        .iter()
+        .map(|p| p.length())
+        .reduce(f64::min)
+        .unwrap();
+    ShadowSize {
+        photon_sphere_radius: closest_approach,
+        critical_impact_parameter: b,
+        angular_radius: view_angle(b, camera_distance, &field),
+    }
+}
+
+pub fn compare_shadow(
+    camera_distance: f64,
+    black_hole_radius: f64,
+    model: FieldModel,
+    config: RayCastConfig,
+) -> ShadowComparison {
+    ShadowComparison {
+        analytic: analytic_shadow(camera_distance, black_hole_radius, model),
+        numerical: numerical_shadow(camera_distance, black_hole_radius, model, config),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::path_integration2::{
+        ray_cast_config::RayCastConfig,
+        structs::field::{Field, FieldModel},
+    };
+
+    use super::{angular_shadow_radius, compare_shadow};
+
+    #[test]
+    fn shadow_numerical_matches_analytic() {
+        let black_hole_radius = 1.5;
+        for (camera_distance, model) in [
+            (5., FieldModel::Schwarzschild),
+            (10., FieldModel::Schwarzschild),
+            (20., FieldModel::Schwarzschild),
+            (10., FieldModel::ReissnerNordstrom { charge: 0.5 }),
+            (10., FieldModel::ReissnerNordstrom { charge: 0.9 }),
+        ] {
+            let comparison = compare_shadow(
+                camera_distance,
+                black_hole_radius,
+                model,
+                RayCastConfig::default(),
+            );
+            let difference = comparison.difference();
+            assert!(
+                difference.critical_impact_parameter.abs()
+                    < 0.001 * comparison.analytic.critical_impact_parameter,
+                "{:?}",
+                comparison
+            );
+            assert!(difference.angular_radius.abs() < 0.001, "{:?}", comparison);
+            assert!(
+                difference.photon_sphere_radius.abs()
+                    < 0.01 * comparison.analytic.photon_sphere_radius,
+                "{:?}",
+                comparison
+            );
+        }
+    }
+
+    #[test]
+    fn shadow_angular_radius_limits() {
+        let black_hole_radius = 1.5;
+        let model = FieldModel::Schwarzschild;
+        // Far away the shadow shrinks like b_c / D.
+        let far = 100000.;
+        let expected = 1.5 * 3_f64.sqrt() * black_hole_radius / far;
+        assert!(
+            (angular_shadow_radius(far, black_hole_radius, model) - expected).abs() < 0.0000001
+        );
+        // At the photon sphere the shadow covers half the sky.
+        let at_sphere = angular_shadow_radius(1.5 * black_hole_radius, black_hole_radius, model);
+        assert!((at_sphere - 0.5 * std::f64::consts::PI).abs() < 0.000001);
+        // Inside it, the shadow covers more than half.
+        assert!(
+            angular_shadow_radius(1.2 * black_hole_radius, black_hole_radius, model) > at_sphere
+        );
+    }
+
+    #[test]
+    fn charge_shrinks_shadow() {
+        let black_hole_radius = 1.5;
+        let camera_distance = 20.;
+        let charged = |charge: f64| {
+            Field::new(
+                black_hole_radius,
+                camera_distance,
+                FieldModel::ReissnerNordstrom { charge },
+            )
+        };
+        let uncharged = charged(0.);
+        let schwarzschild = Field::new(
+            black_hole_radius,
+            camera_distance,
+            FieldModel::Schwarzschild,
+        );
+        assert_eq!(uncharged.horizon_radius(), schwarzschild.horizon_radius());
+        assert_eq!(uncharged.photon_sphere_radius(), 1.5 * black_hole_radius);
+        assert_eq!(
+            uncharged.critical_impact_parameter(),
+            schwarzschild.critical_impact_parameter()
+        );
+        // An extremal hole has its horizon at M, its photon sphere at 2M and b_c = 4M.
+        let extremal = charged(1.);
+        let m = 0.5 * black_hole_radius;
+        assert!((extremal.horizon_radius() - m).abs() < 0.000001);
+        assert!((extremal.photon_sphere_radius() - 2. * m).abs() < 0.000001);
+        assert!((extremal.critical_impact_parameter() - 4. * m).abs() < 0.000001);
+
+        let mut previous = f64::INFINITY;
+        for charge in [0., 0.25, 0.5, 0.75, 1.] {
+            let angular_radius = angular_shadow_radius(
+                camera_distance,
+                black_hole_radius,
+                FieldModel::ReissnerNordstrom { charge },
+            );
+            assert!(angular_radius < previous, "charge: {}", charge);
+            previous = angular_radius;
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use glam::DVec3;
+use serde::{Deserialize, Serialize};
+
+use super::{field::Particle, step::ParticleField};
+
+// Error bounds for a single step; a component passes if its error is below
+// absolute + relative * |value|.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Tolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            absolute: 0.000001,
+            relative: 0.000001,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct StepStats {
+    pub accepted: usize,
+    pub rejected: usize,
+    pub min_step: f64,
+    pub max_step: f64,
+    // Sum of the local position error estimates of the accepted steps.
+    pub error_estimate: f64,
+}
+
+// Why a ray couldn't be stepped any further.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepError {
+    // The state became NaN or infinite, which no step size can fix.
+    NotFinite,
+    // The error was still over tolerance at the smallest step allowed.
+    StepTooSmall,
+}
+
+impl StepStats {
+    pub fn record_accepted(&mut self, h: f64, error: f64) {
+        if self.accepted == 0 {
+            self.min_step = h;
+            self.max_step = h;
+        }
+        self.accepted += 1;
+        self.min_step = self.min_step.min(h);
+        self.max_step = self.max_step.max(h);
+        self.error_estimate += error;
+    }
+}
+
+// Dormand-Prince 5(4) tableau. The force doesn't depend on time, so the nodes aren't needed.
+const A: [[f64; 6]; 7] = [
+    [0., 0., 0., 0., 0., 0.],
+    [1. / 5., 0., 0., 0., 0., 0.],
+    [3. / 40., 9. / 40., 0., 0., 0., 0.],
+    [44. / 45., -56. / 15., 32. / 9., 0., 0., 0.],
+    [
+        19372. / 6561.,
+        -25360. / 2187.,
+        64448. / 6561.,
+        -212. / 729.,
+        0.,
+        0.,
+    ],
+    [
+        9017. / 3168.,
+        -355. / 33.,
+        46732. / 5247.,
+        49. / 176.,
+        -5103. / 18656.,
+        0.,
+    ],
+    [
+        35. / 384.,
+        0.,
+        500. / 1113.,
+        125. / 192.,
+        -2187. / 6784.,
+        11. / 84.,
+    ],
+];
+const B: [f64; 7] = [
+    35. / 384.,
+    0.,
+    500. / 1113.,
+    125. / 192.,
+    -2187. / 6784.,
+    11. / 84.,
+    0.,
+];
+const B_STAR: [f64; 7] = [
+    5179. / 57600.,
+    0.,
+    7571. / 16695.,
+    393. / 640.,
+    -92097. / 339200.,
+    187. / 2100.,
+    1. / 40.,
+];
+
+const SAFETY: f64 = 0.9;
+const MIN_SCALE: f64 = 0.2;
+const MAX_SCALE: f64 = 5.0;
+// Keeps the path dense enough for the angle lookups in `AnglePath`.
+const MAX_STEP_PER_DISTANCE: f64 = 0.5;
+// Below this the step makes no progress in floating point, so shrinking it further would loop
+// forever.
+const MIN_STEP_PER_DISTANCE: f64 = 1e-12;
+
+pub struct AdaptiveStepper {
+    pub tolerance: Tolerance,
+    pub h: f64,
+    pub stats: StepStats,
+}
+
+impl AdaptiveStepper {
+    pub fn new(particle: &Particle, tolerance: Tolerance) -> Self {
+        Self {
+            tolerance,
+            h: 0.01 * particle.p.length() / particle.v.length(),
+            stats: StepStats::default(),
+        }
+    }
+
+    // Advances the particle by one accepted step, retrying with a smaller step until the error
+    // estimate is within tolerance. Fails if the state isn't finite or the step would have to
+    // shrink below the floor, leaving the particle where it was.
+    pub fn step<F: ParticleField>(
+        &mut self,
+        particle: &mut Particle,
+        field: &F,
+    ) -> Result<(), StepError> {
+        let max_step = MAX_STEP_PER_DISTANCE * particle.p.length() / particle.v.length();
+        let min_step = MIN_STEP_PER_DISTANCE * particle.p.length() / particle.v.length();
+        loop {
+            let h = self.h.min(max_step).max(min_step);
+            let (delta_p, delta_v, error_p, error_v) = dormand_prince(particle, field, h);
+            if !(delta_p.is_finite() && delta_v.is_finite()) {
+                return Err(StepError::NotFinite);
+            }
+            let error = self.error_ratio(particle, delta_p, delta_v, error_p, error_v);
+            let scale = match error > 0. {
+                true => (SAFETY * error.powf(-0.2)).clamp(MIN_SCALE, MAX_SCALE),
+                false => MAX_SCALE,
+            };
+            self.h = h * scale;
+            if error <= 1. {
+                particle.p += delta_p;
+                particle.v += delta_v;
+                self.stats.record_accepted(h, error_p.length());
+                return Ok(());
+            }
+            self.stats.rejected += 1;
+            if h <= min_step {
+                return Err(StepError::StepTooSmall);
+            }
+        }
+    }
+
+    fn error_ratio(
+        &self,
+        particle: &Particle,
+        delta_p: DVec3,
+        delta_v: DVec3,
+        error_p: DVec3,
+        error_v: DVec3,
+    ) -> f64 {
+        let scale = |old: DVec3, new: DVec3| {
+            DVec3::splat(self.tolerance.absolute)
+                + self.tolerance.relative * old.abs().max(new.abs())
+        };
+        let p_ratio = error_p.abs() / scale(particle.p, particle.p + delta_p);
+        let v_ratio = error_v.abs() / scale(particle.v, particle.v + delta_v);
+        p_ratio.max_element().max(v_ratio.max_element())
+    }
+}
+
+// Returns the fifth order position and velocity deltas, and the difference from the embedded
+// fourth order solution.
+fn dormand_prince<F: ParticleField>(
+    particle: &Particle,
+    field: &F,
+    h: f64,
+) -> (DVec3, DVec3, DVec3, DVec3) {
+    let mut k_p = [DVec3::ZERO; 7];
+    let mut k_v = [DVec3::ZERO; 7];
+    for stage in 0..7 {
+        let mut p = particle.p;
+        let mut v = particle.v;
+        for prev in 0..stage {
+            p += h * A[stage][prev] * k_p[prev];
+            v += h * A[stage][prev] * k_v[prev];
+        }
+        k_p[stage] = v;
+        k_v[stage] = field.acceleration(p, v, particle.angular_momentum);
+    }
+
+    let mut delta_p = DVec3::ZERO;
+    let mut delta_v = DVec3::ZERO;
+    let mut error_p = DVec3::ZERO;
+    let mut error_v = DVec3::ZERO;
+    for stage in 0..7 {
+        delta_p += h * B[stage] * k_p[stage];
+        delta_v += h * B[stage] * k_v[stage];
+        error_p += h * (B[stage] - B_STAR[stage]) * k_p[stage];
+        error_v += h * (B[stage] - B_STAR[stage]) * k_v[stage];
+    }
+    (delta_p, delta_v, error_p, error_v)
+}
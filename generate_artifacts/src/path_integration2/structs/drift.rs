@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+use super::field::{Field, Particle};
+
+// Largest relative change of the conserved quantities seen along a path.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Drift {
+    pub energy: f64,
+    pub angular_momentum: f64,
+}
+
+impl Drift {
+    pub fn max(&self) -> f64 {
+        self.energy.max(self.angular_momentum)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum DriftPolicy {
+    // Keep the sample, but record it on the cache.
+    Flag,
+    // Record the sample and replace it by interpolating its accepted neighbours.
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct DriftLimit {
+    // Maximum relative drift before the policy applies.
+    pub threshold: f64,
+    pub policy: DriftPolicy,
+}
+
+pub struct DriftTracker {
+    initial_energy: f64,
+    initial_angular_momentum: f64,
+    drift: Drift,
+}
+
+fn relative_change(initial: f64, current: f64) -> f64 {
+    (current - initial).abs() / initial.abs().max(f64::EPSILON)
+}
+
+impl DriftTracker {
+    pub fn new(particle: &Particle, field: &Field) -> Self {
+        Self {
+            initial_energy: field.energy(particle),
+            initial_angular_momentum: particle.p.cross(particle.v).length(),
+            drift: Drift::default(),
+        }
+    }
+
+    pub fn update(&mut self, particle: &Particle, field: &Field) {
+        let energy = relative_change(self.initial_energy, field.energy(particle));
+        let angular_momentum = relative_change(
+            self.initial_angular_momentum,
+            particle.p.cross(particle.v).length(),
+        );
+        self.drift.energy = self.drift.energy.max(energy);
+        self.drift.angular_momentum = self.drift.angular_momentum.max(angular_momentum);
+    }
+
+    pub fn drift(&self) -> Drift {
+        self.drift
+    }
+}
+
+// Overwrites the rejected entries by linearly interpolating (by index) between the closest
+// accepted entries on either side. Entries past the last accepted one copy it.
+pub fn interpolate_rejected<T: Clone>(
+    values: &mut [T],
+    rejected: &[bool],
+    lerp: impl Fn(&T, &T, f64) -> T,
+) {
+    let accepted: Vec<usize> = (0..values.len()).filter(|&i| !rejected[i]).collect();
+    if accepted.is_empty() {
+        return;
+    }
+    for i in 0..values.len() {
+        if !rejected[i] {
+            continue;
+        }
+        let right = accepted.partition_point(|&a| a < i);
+        values[i] = match (right.checked_sub(1), accepted.get(right)) {
+            (Some(left), Some(&right)) => {
+                let left = accepted[left];
+                let t = (i - left) as f64 / (right - left) as f64;
+                lerp(&values[left], &values[right], t)
+            }
+            (Some(left), None) => values[accepted[left]].clone(),
+            (None, Some(&right)) => values[right].clone(),
+            (None, None) => unreachable!(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::interpolate_rejected;
+
+    #[test]
+    fn interpolates_rejected_entries() {
+        let mut values = vec![9., 1., 9., 9., 4., 9.];
+        let rejected = [true, false, true, true, false, true];
+        interpolate_rejected(&mut values, &rejected, |a, b, t| a + t * (b - a));
+        assert_eq!(values, vec![1., 1., 2., 3., 4., 4.]);
+    }
+}
@@ -1,40 +1,191 @@
+use std::fmt;
+
 use glam::DVec3;
+use serde::{Deserialize, Serialize};
+use wire_structs::physics::{
+    pseudo_force_magnitude, pseudo_force_scale, pseudo_initial_speed, pseudo_potential,
+};
+
+// Which force law the particles follow.
+//
+// `PseudoForce` is the tuned `magnitude / r^5` force the caches were originally built with.
+// `Schwarzschild` integrates the exact photon orbit equation u'' + u = 3Mu^2 by using the
+// equivalent central force -1.5 * r_s * h^2 * p / r^5, where h is the (conserved) angular momentum.
+// `ReissnerNordstrom` adds the -2Q^2u^3 term of a charged hole, a repulsive 2 * Q^2 * h^2 * p / r^6.
+// `charge` is the dimensionless Q / M, so 0 is Schwarzschild and 1 is an extremal hole.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum FieldModel {
+    #[default]
+    PseudoForce,
+    Schwarzschild,
+    ReissnerNordstrom {
+        charge: f64,
+    },
+}
+
+// Charges past extremal have no horizon, so there's no black hole to cast rays around.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChargeOutOfRange(pub f64);
+
+impl fmt::Display for ChargeOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "charge {} is outside [-1, 1]", self.0)
+    }
+}
+
+impl std::error::Error for ChargeOutOfRange {}
+
+impl FieldModel {
+    pub fn validate(&self) -> Result<(), ChargeOutOfRange> {
+        match *self {
+            FieldModel::ReissnerNordstrom { charge } if !(-1. ..=1.).contains(&charge) => {
+                Err(ChargeOutOfRange(charge))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Keeps the travel time finite for steps that end inside the horizon.
+const MIN_LAPSE: f64 = 0.000001;
 
 pub struct Field {
     pub magnitude: f64,
     pub m: f64,
+    pub model: FieldModel,
 }
 
 impl Field {
-    pub fn new(radius: f64, camera_distance: f64) -> Self {
-        let magnitude = 2.0 / ((2.0 / radius.powi(4)) - (1.0 / camera_distance.powi(4)));
+    pub fn new(radius: f64, camera_distance: f64, model: FieldModel) -> Self {
+        let magnitude = pseudo_force_magnitude(radius, camera_distance);
         Self {
             magnitude,
             m: 0.5 * radius,
+            model,
         }
     }
 
-    pub fn force(&self, pos: &DVec3) -> DVec3 {
+    pub fn force(&self, pos: &DVec3, angular_momentum: f64) -> DVec3 {
         let diff: DVec3 = -1.0 * *pos;
-
-        self.magnitude * diff.normalize() / diff.length().powi(5)
+        match self.model {
+            FieldModel::PseudoForce => {
+                pseudo_force_scale(diff.length_squared(), self.magnitude) * diff
+            }
+            FieldModel::Schwarzschild => {
+                1.5 * self.schwarzchild_radius() * angular_momentum * angular_momentum * diff
+                    / diff.length().powi(5)
+            }
+            FieldModel::ReissnerNordstrom { .. } => {
+                let r = diff.length();
+                let q = self.charge();
+                angular_momentum
+                    * angular_momentum
+                    * (1.5 * self.schwarzchild_radius() - 2. * q * q / r)
+                    * diff
+                    / r.powi(5)
+            }
+        }
     }
 
     pub fn schwarzchild_radius(&self) -> f64 {
         2.0 * self.m
     }
 
+    // Charge Q in length units; 0 for uncharged models. Callers validate the model first.
+    pub fn charge(&self) -> f64 {
+        match self.model {
+            FieldModel::ReissnerNordstrom { charge } => charge * self.m,
+            _ => 0.,
+        }
+    }
+
+    // f(r) = 1 - r_s / r + Q^2 / r^2, the factor in front of dt^2 in the metric. The pseudo force
+    // has no metric, so it uses Schwarzschild's.
+    pub fn metric_factor(&self, r: f64) -> f64 {
+        let q = self.charge();
+        1. - self.schwarzchild_radius() / r + q * q / (r * r)
+    }
+
+    // Outer horizon, the larger root of f(r).
+    pub fn horizon_radius(&self) -> f64 {
+        let q = self.charge();
+        self.m + (self.m * self.m - q * q).sqrt()
+    }
+
+    // Radius of the unstable circular photon orbit, where f(r) / r^2 peaks.
+    pub fn photon_sphere_radius(&self) -> f64 {
+        let q = self.charge();
+        0.5 * (3. * self.m + (9. * self.m * self.m - 8. * q * q).sqrt())
+    }
+
+    // Rays with a smaller impact parameter fall in.
+    pub fn critical_impact_parameter(&self) -> f64 {
+        let r = self.photon_sphere_radius();
+        r / self.metric_factor(r).sqrt()
+    }
+
+    // Particles closer than this are treated as having fallen in.
+    pub fn capture_radius(&self) -> f64 {
+        match self.model {
+            // We add some error so that the geodesics that are on the edge of the schwarzchild radius don't get pulled in accidentally.
+            FieldModel::PseudoForce => 0.85 * self.schwarzchild_radius(),
+            // Anything below the photon sphere falls in, so the horizon is a safe cutoff.
+            FieldModel::Schwarzschild | FieldModel::ReissnerNordstrom { .. } => {
+                self.horizon_radius()
+            }
+        }
+    }
+
     pub fn initial_speed(&self, particle_start: &DVec3) -> f64 {
-        let diff = particle_start.length();
+        match self.model {
+            FieldModel::PseudoForce => pseudo_initial_speed(
+                particle_start.length(),
+                self.schwarzchild_radius(),
+                self.magnitude,
+            ),
+            // The orbit shape doesn't depend on the speed, so we use c = 1.
+            FieldModel::Schwarzschild | FieldModel::ReissnerNordstrom { .. } => 1.0,
+        }
+    }
 
-        (0.5 * self.magnitude * (2.0 / self.schwarzchild_radius().powi(4) - 1.0 / diff.powi(4)))
-            .sqrt()
+    // Conserved along a path; the force is -grad of the potential term.
+    pub fn energy(&self, particle: &Particle) -> f64 {
+        let r = particle.p.length();
+        let kinetic = 0.5 * particle.v.length_squared();
+        match self.model {
+            FieldModel::PseudoForce => kinetic + pseudo_potential(r, self.magnitude),
+            FieldModel::Schwarzschild | FieldModel::ReissnerNordstrom { .. } => {
+                let h = particle.angular_momentum;
+                let q = self.charge();
+                kinetic - 0.5 * self.schwarzchild_radius() * h * h / r.powi(3)
+                    + 0.5 * q * q * h * h / r.powi(4)
+            }
+        }
+    }
+
+    // Coordinate time light takes along the (short) segment between two points on its path. The
+    // pseudo force has no metric, so it uses flat space.
+    pub fn travel_time(&self, from: &DVec3, to: &DVec3) -> f64 {
+        let length = (*to - *from).length();
+        match self.model {
+            FieldModel::PseudoForce => length,
+            FieldModel::Schwarzschild | FieldModel::ReissnerNordstrom { .. } => {
+                // Null geodesics satisfy f dt^2 = dr^2 / f + r^2 dphi^2.
+                let r = 0.5 * (from.length() + to.length());
+                let f = self.metric_factor(r).max(MIN_LAPSE);
+                let radial = to.length() - from.length();
+                let tangential_sq = (length * length - radial * radial).max(0.);
+                (radial * radial / (f * f) + tangential_sq / f).sqrt()
+            }
+        }
     }
 
     pub fn spawn_particle(&self, p: DVec3, velocity_direction: DVec3) -> Particle {
+        let v = velocity_direction.normalize() * self.initial_speed(&p);
         Particle {
             p,
-            v: velocity_direction.normalize() * self.initial_speed(&p),
+            v,
+            angular_momentum: p.cross(v).length(),
         }
     }
 }
@@ -42,4 +193,5 @@ impl Field {
 pub struct Particle {
     pub p: DVec3,
     pub v: DVec3,
+    pub angular_momentum: f64,
 }
@@ -0,0 +1,158 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    adaptive_step::{AdaptiveStepper, StepError, StepStats, Tolerance},
+    field::Particle,
+    step::{leapfrog_step_particle, step_particle, ParticleField},
+};
+
+pub trait Integrator {
+    // Advances the particle by one step. Once it fails the ray can't be continued.
+    fn step<F: ParticleField>(
+        &mut self,
+        particle: &mut Particle,
+        field: &F,
+    ) -> Result<(), StepError>;
+
+    fn stats(&self) -> StepStats;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum IntegratorKind {
+    #[default]
+    Rk4,
+    Rk45(Tolerance),
+    Leapfrog,
+}
+
+impl IntegratorKind {
+    pub fn integrator(&self, particle: &Particle) -> AnyIntegrator {
+        match *self {
+            IntegratorKind::Rk4 => AnyIntegrator::Rk4(Rk4::default()),
+            IntegratorKind::Rk45(tolerance) => {
+                AnyIntegrator::Rk45(Rk45(AdaptiveStepper::new(particle, tolerance)))
+            }
+            IntegratorKind::Leapfrog => AnyIntegrator::Leapfrog(Leapfrog::default()),
+        }
+    }
+
+    // For spacetimes stepped in their own coordinates, which only have an RK4 stepper.
+    pub fn require_rk4(&self) -> Result<(), UnsupportedIntegrator> {
+        match self {
+            IntegratorKind::Rk4 => Ok(()),
+            _ => Err(UnsupportedIntegrator(*self)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnsupportedIntegrator(pub IntegratorKind);
+
+impl fmt::Display for UnsupportedIntegrator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "integrator {:?} isn't supported here, only Rk4", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedIntegrator {}
+
+// Whichever integrator `IntegratorKind` picked. An enum rather than a `Box<dyn Integrator>`, so
+// casting a ray doesn't allocate.
+pub enum AnyIntegrator {
+    Rk4(Rk4),
+    Rk45(Rk45),
+    Leapfrog(Leapfrog),
+}
+
+impl Integrator for AnyIntegrator {
+    fn step<F: ParticleField>(
+        &mut self,
+        particle: &mut Particle,
+        field: &F,
+    ) -> Result<(), StepError> {
+        match self {
+            AnyIntegrator::Rk4(integrator) => integrator.step(particle, field),
+            AnyIntegrator::Rk45(integrator) => integrator.step(particle, field),
+            AnyIntegrator::Leapfrog(integrator) => integrator.step(particle, field),
+        }
+    }
+
+    fn stats(&self) -> StepStats {
+        match self {
+            AnyIntegrator::Rk4(integrator) => integrator.stats(),
+            AnyIntegrator::Rk45(integrator) => integrator.stats(),
+            AnyIntegrator::Leapfrog(integrator) => integrator.stats(),
+        }
+    }
+}
+
+// Fixed step integrators can't retry, so a NaN state just ends the ray.
+fn check_finite(particle: &Particle) -> Result<(), StepError> {
+    match particle.p.is_finite() && particle.v.is_finite() {
+        true => Ok(()),
+        false => Err(StepError::NotFinite),
+    }
+}
+
+// Fixed order RK4 with the heuristic step size from `step`.
+#[derive(Default)]
+pub struct Rk4 {
+    stats: StepStats,
+}
+
+impl Integrator for Rk4 {
+    fn step<F: ParticleField>(
+        &mut self,
+        particle: &mut Particle,
+        field: &F,
+    ) -> Result<(), StepError> {
+        let h = step_particle(particle, field);
+        self.stats.record_accepted(h, 0.);
+        check_finite(particle)
+    }
+
+    fn stats(&self) -> StepStats {
+        self.stats
+    }
+}
+
+// Dormand-Prince with error control.
+pub struct Rk45(pub AdaptiveStepper);
+
+impl Integrator for Rk45 {
+    fn step<F: ParticleField>(
+        &mut self,
+        particle: &mut Particle,
+        field: &F,
+    ) -> Result<(), StepError> {
+        self.0.step(particle, field)
+    }
+
+    fn stats(&self) -> StepStats {
+        self.0.stats
+    }
+}
+
+// Symplectic leapfrog with the heuristic step size from `step`.
+#[derive(Default)]
+pub struct Leapfrog {
+    stats: StepStats,
+}
+
+impl Integrator for Leapfrog {
+    fn step<F: ParticleField>(
+        &mut self,
+        particle: &mut Particle,
+        field: &F,
+    ) -> Result<(), StepError> {
+        let h = leapfrog_step_particle(particle, field);
+        self.stats.record_accepted(h, 0.);
+        check_finite(particle)
+    }
+
+    fn stats(&self) -> StepStats {
+        self.stats
+    }
+}
@@ -0,0 +1,150 @@
+use std::fmt;
+
+use glam::DVec3;
+
+// Photon state in Boyer-Lindquist coordinates. Time translation and rotation around the spin axis
+// are symmetries, so p_t = -1 and p_phi = angular_momentum stay fixed for the whole path.
+#[derive(Debug, Clone, Copy)]
+pub struct KerrParticle {
+    pub r: f64,
+    pub theta: f64,
+    pub phi: f64,
+    pub p_r: f64,
+    pub p_theta: f64,
+    pub angular_momentum: f64,
+}
+
+pub struct KerrField {
+    pub m: f64,
+    // Spin parameter `a` in length units; |a| <= m.
+    pub a: f64,
+}
+
+// Derivatives of (r, theta, phi, p_r, p_theta) with respect to the affine parameter.
+pub type KerrDerivative = [f64; 5];
+
+const MIN_SIN_THETA: f64 = 0.000001;
+
+// Spins past extremal have no horizon, so there's no black hole to cast rays around.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpinOutOfRange(pub f64);
+
+impl fmt::Display for SpinOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "spin {} is outside [-1, 1]", self.0)
+    }
+}
+
+impl std::error::Error for SpinOutOfRange {}
+
+impl KerrField {
+    // `spin` is the dimensionless a / M, so 0 is Schwarzschild and 1 is an extremal hole.
+    pub fn new(radius: f64, spin: f64) -> Result<Self, SpinOutOfRange> {
+        if !(-1. ..=1.).contains(&spin) {
+            return Err(SpinOutOfRange(spin));
+        }
+        let m = 0.5 * radius;
+        Ok(Self { m, a: spin * m })
+    }
+
+    pub fn schwarzchild_radius(&self) -> f64 {
+        2.0 * self.m
+    }
+
+    pub fn horizon_radius(&self) -> f64 {
+        self.m + (self.m * self.m - self.a * self.a).sqrt()
+    }
+
+    fn sigma(&self, r: f64, theta: f64) -> f64 {
+        r * r + self.a * self.a * theta.cos().powi(2)
+    }
+
+    fn delta(&self, r: f64) -> f64 {
+        r * r - 2. * self.m * r + self.a * self.a
+    }
+
+    // Spawns a photon at (r, theta, phi = 0) travelling along `direction`, which is expressed in
+    // the frame of a locally non-rotating observer: x is along phi, y is along -theta ("up" for
+    // an observer above the equator) and z points at the black hole.
+    pub fn spawn_particle(&self, r: f64, theta: f64, direction: DVec3) -> KerrParticle {
+        let n = direction.normalize();
+        let (n_phi, n_theta, n_r) = (n.x, -n.y, -n.z);
+        let a = self.a;
+        let sin_theta = theta.sin().abs().max(MIN_SIN_THETA);
+        let sigma = self.sigma(r, theta);
+        let delta = self.delta(r);
+        let big_a = (r * r + a * a).powi(2) - a * a * delta * sin_theta * sin_theta;
+        let lapse = (sigma * delta / big_a).sqrt();
+        let frame_dragging = 2. * self.m * a * r / big_a;
+        let cylindrical_radius = (big_a / sigma).sqrt() * sin_theta;
+
+        // Scale the local photon energy so that the conserved energy is 1.
+        let local_energy = 1. / (lapse + frame_dragging * cylindrical_radius * n_phi);
+        KerrParticle {
+            r,
+            theta,
+            phi: 0.,
+            p_r: local_energy * (sigma / delta).sqrt() * n_r,
+            p_theta: local_energy * sigma.sqrt() * n_theta,
+            angular_momentum: local_energy * cylindrical_radius * n_phi,
+        }
+    }
+
+    pub fn derivative(&self, particle: &KerrParticle) -> KerrDerivative {
+        let KerrParticle {
+            r,
+            theta,
+            p_r,
+            p_theta,
+            angular_momentum: l,
+            ..
+        } = *particle;
+        let a = self.a;
+        let m = self.m;
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let sin_theta = match sin_theta.abs() < MIN_SIN_THETA {
+            true => MIN_SIN_THETA.copysign(sin_theta),
+            false => sin_theta,
+        };
+        let sin_sq = sin_theta * sin_theta;
+        let sigma = self.sigma(r, theta);
+        let delta = self.delta(r);
+        let energy_term = r * r + a * a - a * l;
+        let axial_term = l - a * sin_sq;
+
+        // H = n / (2 sigma), which is 0 along a null geodesic.
+        let n = delta * p_r * p_r + p_theta * p_theta - energy_term * energy_term / delta
+            + axial_term * axial_term / sin_sq;
+
+        let d_delta_dr = 2. * r - 2. * m;
+        let dn_dr = d_delta_dr * p_r * p_r
+            - (4. * r * energy_term * delta - energy_term * energy_term * d_delta_dr)
+                / (delta * delta);
+        let dh_dr = dn_dr / (2. * sigma) - n * 2. * r / (2. * sigma * sigma);
+
+        let d_axial_dtheta = -2. * a * sin_theta * cos_theta;
+        let dn_dtheta = 2. * axial_term * d_axial_dtheta / sin_sq
+            - 2. * axial_term * axial_term * cos_theta / (sin_sq * sin_theta);
+        let d_sigma_dtheta = -2. * a * a * cos_theta * sin_theta;
+        let dh_dtheta = dn_dtheta / (2. * sigma) - n * d_sigma_dtheta / (2. * sigma * sigma);
+
+        [
+            delta * p_r / sigma,
+            p_theta / sigma,
+            (a * energy_term / delta + axial_term / sin_sq) / sigma,
+            -dh_dr,
+            -dh_dtheta,
+        ]
+    }
+
+    // Maps Boyer-Lindquist coordinates to the Cartesian frame with the spin along +y, so that the
+    // equatorial plane matches the x-z plane used by the non-rotating caches.
+    pub fn to_cartesian(&self, particle: &KerrParticle) -> DVec3 {
+        let cylindrical = (particle.r * particle.r + self.a * self.a).sqrt() * particle.theta.sin();
+        DVec3::new(
+            cylindrical * particle.phi.sin(),
+            particle.r * particle.theta.cos(),
+            -cylindrical * particle.phi.cos(),
+        )
+    }
+}
@@ -0,0 +1,47 @@
+use super::kerr_field::{KerrDerivative, KerrField, KerrParticle};
+
+const MIN_STEP: f64 = 0.0002;
+
+pub fn step_kerr_particle(particle: &mut KerrParticle, field: &KerrField) {
+    let h = step_size(particle, field);
+
+    let delta = rk4(particle, field, h);
+
+    particle.r += delta[0];
+    particle.theta += delta[1];
+    particle.phi += delta[2];
+    particle.p_r += delta[3];
+    particle.p_theta += delta[4];
+}
+
+// The affine parameter is roughly coordinate distance far away, so we take steps proportional to
+// the distance from the horizon.
+fn step_size(particle: &KerrParticle, field: &KerrField) -> f64 {
+    0.02 * (particle.r - field.horizon_radius()).max(0.) + MIN_STEP
+}
+
+fn offset(particle: &KerrParticle, k: &KerrDerivative, scale: f64) -> KerrParticle {
+    KerrParticle {
+        r: particle.r + scale * k[0],
+        theta: particle.theta + scale * k[1],
+        phi: particle.phi + scale * k[2],
+        p_r: particle.p_r + scale * k[3],
+        p_theta: particle.p_theta + scale * k[4],
+        angular_momentum: particle.angular_momentum,
+    }
+}
+
+fn rk4(particle: &KerrParticle, field: &KerrField, h: f64) -> KerrDerivative {
+    let k_0 = field.derivative(particle);
+    let k_1 = field.derivative(&offset(particle, &k_0, 0.5 * h));
+    let k_2 = field.derivative(&offset(particle, &k_1, 0.5 * h));
+    let k_3 = field.derivative(&offset(particle, &k_2, h));
+
+    std::array::from_fn(|i| (h / 6.0) * (k_0[i] + 2.0 * k_1[i] + 2.0 * k_2[i] + k_3[i]))
+}
+
+// Boyer-Lindquist coordinates freeze the photon just outside the horizon, so we stop slightly
+// above it.
+pub fn kerr_hit(particle: &KerrParticle, field: &KerrField) -> bool {
+    particle.r < field.horizon_radius() + 0.01 * field.m
+}
@@ -1,2 +1,11 @@
+pub mod adaptive_step;
+pub mod drift;
 pub mod field;
+pub mod integrator;
+pub mod kerr_field;
+pub mod kerr_step;
+pub mod multi_field;
+pub mod multi_step;
 pub mod step;
+pub mod wormhole_field;
+pub mod wormhole_step;
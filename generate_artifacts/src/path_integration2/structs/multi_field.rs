@@ -0,0 +1,63 @@
+use super::field::Particle;
+use glam::DVec3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointMass {
+    pub position: DVec3,
+    // Schwarzschild radius of the mass.
+    pub radius: f64,
+}
+
+// Several point masses at arbitrary positions, for scenes like binary black holes that break the
+// axisymmetry the caches rely on.
+//
+// Each mass contributes the Binet force `FieldModel::Schwarzschild` uses, with the particle's
+// angular momentum taken about that mass. For a single mass this is exact; for several it is the
+// weak field superposition, which is what lensing by well separated masses reduces to.
+pub struct MultiField {
+    pub masses: Vec<PointMass>,
+}
+
+impl MultiField {
+    pub fn new(masses: Vec<PointMass>) -> Self {
+        Self { masses }
+    }
+
+    pub fn force(&self, p: DVec3, v: DVec3) -> DVec3 {
+        self.masses
+            .iter()
+            .map(|mass| {
+                let diff = p - mass.position;
+                let angular_momentum_sq = diff.cross(v).length_squared();
+                -1.5 * mass.radius * angular_momentum_sq * diff / diff.length().powi(5)
+            })
+            .fold(DVec3::ZERO, |total, force| total + force)
+    }
+
+    // Distance from p to the closest horizon; negative inside one.
+    pub fn horizon_distance(&self, p: DVec3) -> f64 {
+        self.masses
+            .iter()
+            .map(|mass| (p - mass.position).length() - mass.radius)
+            .reduce(f64::min)
+            .unwrap_or(f64::INFINITY)
+    }
+
+    // Center of mass, which the renderer measures the camera distance from.
+    pub fn center(&self) -> DVec3 {
+        let total: f64 = self.masses.iter().map(|mass| mass.radius).sum();
+        self.masses.iter().fold(DVec3::ZERO, |center, mass| {
+            center + mass.radius * mass.position
+        }) / total
+    }
+
+    // Rays start moving at the speed of light, so every mass sees c = 1.
+    pub fn spawn_particle(&self, p: DVec3, velocity_direction: DVec3) -> Particle {
+        let v = velocity_direction.normalize();
+        Particle {
+            p,
+            v,
+            angular_momentum: p.cross(v).length(),
+        }
+    }
+}
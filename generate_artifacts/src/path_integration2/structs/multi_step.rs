@@ -0,0 +1,24 @@
+use glam::DVec3;
+
+use super::{field::Particle, multi_field::MultiField, step::ParticleField};
+
+const MIN_STEP: f64 = 0.0002;
+const STEP_SCALE: f64 = 0.02;
+
+// The force depends on the velocity through the angular momentum about each mass, so it's taken
+// from the stage velocity rather than the particle's.
+impl ParticleField for MultiField {
+    fn acceleration(&self, p: DVec3, v: DVec3, _angular_momentum: f64) -> DVec3 {
+        self.force(p, v)
+    }
+
+    // Steps shrink as the particle nears any of the horizons.
+    fn step_size(&self, particle: &Particle) -> f64 {
+        STEP_SCALE * self.horizon_distance(particle.p).max(0.) + MIN_STEP
+    }
+}
+
+// Anything inside a photon sphere falls in, so the horizons are a safe cutoff.
+pub fn multi_hit(particle: &Particle, field: &MultiField) -> bool {
+    field.horizon_distance(particle.p) < 0.
+}
@@ -4,13 +4,47 @@ use super::{field::Field, field::Particle};
 
 const MIN_STEP: f64 = 0.0002;
 
-pub fn step_particle(particle: &mut Particle, field: &Field) {
-    let h = step_size(particle, field);
+// A force law the shared integrators can step particles through.
+pub trait ParticleField {
+    // Forces that depend on the angular momentum either use the particle's conserved one or take
+    // it from `v`, which is the velocity at the current stage.
+    fn acceleration(&self, p: DVec3, v: DVec3, angular_momentum: f64) -> DVec3;
 
-    let (delta_p, delta_v) = rk4(&particle, field, h);
+    // Step size for the fixed step integrators.
+    fn step_size(&self, particle: &Particle) -> f64;
+}
+
+impl ParticleField for Field {
+    fn acceleration(&self, p: DVec3, _v: DVec3, angular_momentum: f64) -> DVec3 {
+        self.force(&p, angular_momentum)
+    }
+
+    fn step_size(&self, particle: &Particle) -> f64 {
+        step_size(particle, self)
+    }
+}
+
+// Returns the step size used.
+pub fn step_particle<F: ParticleField>(particle: &mut Particle, field: &F) -> f64 {
+    let h = field.step_size(particle);
+
+    let (delta_p, delta_v) = rk4(particle, field, h);
 
     particle.p += delta_p;
     particle.v += delta_v;
+    h
+}
+
+// Kick-drift-kick; symplectic for a fixed step, so energy errors stay bounded instead of
+// accumulating. Returns the step size used.
+pub fn leapfrog_step_particle<F: ParticleField>(particle: &mut Particle, field: &F) -> f64 {
+    let h = field.step_size(particle);
+    let l = particle.angular_momentum;
+
+    particle.v += 0.5 * h * field.acceleration(particle.p, particle.v, l);
+    particle.p += h * particle.v;
+    particle.v += 0.5 * h * field.acceleration(particle.p, particle.v, l);
+    h
 }
 
 fn step_size(particle: &Particle, field: &Field) -> f64 {
@@ -20,26 +54,27 @@ fn step_size(particle: &Particle, field: &Field) -> f64 {
     let m_4 = 8.0 * field.m;
     let h = match r > m_4 {
         true => match diff.dot(particle.v) > 0. {
-            true => (0.1 * (r - m_4) + MIN_STEP),
-            false => (0.1 * r + MIN_STEP),
+            true => 0.1 * (r - m_4) + MIN_STEP,
+            false => 0.1 * r + MIN_STEP,
         },
         false => MIN_STEP,
-    } / (v * v);
-    h
+    };
+    h / (v * v)
 }
 
-fn rk4(particle: &Particle, field: &Field, h: f64) -> (DVec3, DVec3) {
-    let k_0 = h * particle.v;
-    let l_0 = h * field.force(&particle.p);
+fn rk4<F: ParticleField>(particle: &Particle, field: &F, h: f64) -> (DVec3, DVec3) {
+    let (p, v, l) = (particle.p, particle.v, particle.angular_momentum);
+    let k_0 = h * v;
+    let l_0 = h * field.acceleration(p, v, l);
 
-    let k_1 = h * (particle.v + 0.5 * l_0);
-    let l_1 = h * field.force(&(particle.p + 0.5 * k_0));
+    let k_1 = h * (v + 0.5 * l_0);
+    let l_1 = h * field.acceleration(p + 0.5 * k_0, v + 0.5 * l_0, l);
 
-    let k_2 = h * (particle.v + 0.5 * l_1);
-    let l_2 = h * field.force(&(particle.p + 0.5 * k_1));
+    let k_2 = h * (v + 0.5 * l_1);
+    let l_2 = h * field.acceleration(p + 0.5 * k_1, v + 0.5 * l_1, l);
 
-    let k_3 = h * (particle.v + l_2);
-    let l_3 = h * field.force(&(particle.p + k_2));
+    let k_3 = h * (v + l_2);
+    let l_3 = h * field.acceleration(p + k_2, v + l_2, l);
 
     (
         (1.0 / 6.0) * (k_0 + 2.0 * k_1 + 2.0 * k_2 + k_3),
@@ -48,6 +83,5 @@ fn rk4(particle: &Particle, field: &Field, h: f64) -> (DVec3, DVec3) {
 }
 
 pub fn hit(particle: &Particle, field: &Field) -> bool {
-    // We add some error so that the geodesics that are on the edge of the schwarzchild radius don't get pulled in accidentally.
-    particle.p.length() < 0.85 * field.schwarzchild_radius()
+    particle.p.length() < field.capture_radius()
 }
@@ -0,0 +1,99 @@
+use std::f64::consts::PI;
+
+use glam::DVec3;
+use serde::{Deserialize, Serialize};
+
+// Which side of the throat a point of an Ellis wormhole is on. The camera is always on the near
+// side.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Universe {
+    #[default]
+    Near,
+    Far,
+}
+
+// Photon state in the equatorial plane of the Ellis metric
+//
+//     ds^2 = -dt^2 + dl^2 + (l^2 + a^2) dOmega^2
+//
+// where the proper radial distance l runs from -inf (far side) to +inf (near side) and the throat
+// is at l = 0. Like every spherically symmetric spacetime, rays stay in the plane they start in.
+// Time translation and rotation are symmetries, so the energy (1) and angular momentum are fixed.
+#[derive(Debug, Clone, Copy)]
+pub struct WormholeParticle {
+    pub l: f64,
+    pub phi: f64,
+    pub p_l: f64,
+    pub angular_momentum: f64,
+}
+
+pub struct WormholeField {
+    // Areal radius of the throat, `a` in the metric.
+    pub throat_radius: f64,
+}
+
+// Derivatives of (l, phi, p_l) with respect to the affine parameter.
+pub type WormholeDerivative = [f64; 3];
+
+impl WormholeParticle {
+    pub fn universe(&self) -> Universe {
+        match self.l < 0. {
+            true => Universe::Far,
+            false => Universe::Near,
+        }
+    }
+}
+
+impl WormholeField {
+    pub fn new(throat_radius: f64) -> Self {
+        Self { throat_radius }
+    }
+
+    // Areal radius at proper radial distance l from the throat.
+    pub fn radius(&self, l: f64) -> f64 {
+        (l * l + self.throat_radius * self.throat_radius).sqrt()
+    }
+
+    // Spawns a photon at (l, phi = 0) travelling along `direction`, expressed in the frame of a
+    // static observer with z pointing at the throat and x along phi.
+    pub fn spawn_particle(&self, l: f64, direction: DVec3) -> WormholeParticle {
+        let n = direction.normalize();
+        WormholeParticle {
+            l,
+            phi: 0.,
+            p_l: -n.z,
+            angular_momentum: self.radius(l) * n.x,
+        }
+    }
+
+    pub fn derivative(&self, particle: &WormholeParticle) -> WormholeDerivative {
+        let WormholeParticle {
+            l,
+            p_l,
+            angular_momentum: h,
+            ..
+        } = *particle;
+        let r_sq = l * l + self.throat_radius * self.throat_radius;
+        [p_l, h / r_sq, h * h * l / (r_sq * r_sq)]
+    }
+
+    // Each side of the throat gets its own copy of the x-z plane used by the other caches. The far
+    // side is mirrored through the throat, so a ray through the middle keeps heading along +z.
+    pub fn to_cartesian(&self, particle: &WormholeParticle) -> DVec3 {
+        let r = self.radius(particle.l).copysign(particle.l);
+        DVec3::new(r * particle.phi.sin(), 0., -r * particle.phi.cos())
+    }
+
+    // Unwrapped angle of the direction of travel, measured from +z towards +x in the frame of
+    // `to_cartesian`, so a ray starting along (sin(angle), 0, cos(angle)) that isn't deflected
+    // ends with the same angle.
+    pub fn direction_angle(&self, particle: &WormholeParticle) -> f64 {
+        let outward = particle.p_l * particle.l.signum();
+        let tangential = particle.angular_momentum / self.radius(particle.l);
+        let heading = f64::atan2(tangential, outward);
+        match particle.universe() {
+            Universe::Near => PI - particle.phi - heading,
+            Universe::Far => 2. * PI - particle.phi - heading,
+        }
+    }
+}
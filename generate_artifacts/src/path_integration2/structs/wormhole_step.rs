@@ -0,0 +1,39 @@
+use super::wormhole_field::{WormholeDerivative, WormholeField, WormholeParticle};
+
+const STEP_SCALE: f64 = 0.02;
+
+// Returns the step size used.
+pub fn step_wormhole_particle(particle: &mut WormholeParticle, field: &WormholeField) -> f64 {
+    let h = step_size(particle, field);
+
+    let delta = rk4(particle, field, h);
+
+    particle.l += delta[0];
+    particle.phi += delta[1];
+    particle.p_l += delta[2];
+    h
+}
+
+// The affine parameter is proper distance, so we take steps proportional to the areal radius. It
+// never drops below the throat radius, so there's no need for a minimum step.
+fn step_size(particle: &WormholeParticle, field: &WormholeField) -> f64 {
+    STEP_SCALE * field.radius(particle.l)
+}
+
+fn offset(particle: &WormholeParticle, k: &WormholeDerivative, scale: f64) -> WormholeParticle {
+    WormholeParticle {
+        l: particle.l + scale * k[0],
+        phi: particle.phi + scale * k[1],
+        p_l: particle.p_l + scale * k[2],
+        angular_momentum: particle.angular_momentum,
+    }
+}
+
+fn rk4(particle: &WormholeParticle, field: &WormholeField, h: f64) -> WormholeDerivative {
+    let k_0 = field.derivative(particle);
+    let k_1 = field.derivative(&offset(particle, &k_0, 0.5 * h));
+    let k_2 = field.derivative(&offset(particle, &k_1, 0.5 * h));
+    let k_3 = field.derivative(&offset(particle, &k_2, h));
+
+    std::array::from_fn(|i| (h / 6.0) * (k_0[i] + 2.0 * k_1[i] + 2.0 * k_2[i] + k_3[i]))
+}
@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{cache_spec::AxisSpec, path_integration2::batch::parallel_map};
+
+// Intervals narrower than this (as a fraction of the axis) aren't split, so a discontinuity can't
+// eat the whole sample budget.
+const MIN_SPACING: f64 = 1e-7;
+
+// Error-driven placement of the samples along a cache axis. Starting from the axis spec's samples,
+// any interval whose midpoint is further than `tolerance` from the interpolation of its ends gets
+// the midpoint as a new sample, until every interval is within tolerance or there are `max_size`
+// samples.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Refinement {
+    pub tolerance: f64,
+    pub max_size: usize,
+}
+
+// Samples `sample` at every position the axis spec places, in [0, 1].
+pub fn sample_axis<T, F>(axis: &AxisSpec, sample: F) -> Vec<(f64, T)>
+where
+    T: Send,
+    F: Fn(f64) -> T + Sync,
+{
+    let positions: Vec<f64> = (0..axis.size).map(|i| axis.index_to_float_01(i)).collect();
+    let values = parallel_map(&positions, |&x| sample(x));
+    positions.into_iter().zip(values).collect()
+}
+
+// Samples `sample` over [0, 1], refining the axis spec's samples until `error(left, right, middle)`
+// is within the tolerance for every interval. `error` should compare the middle value with what
+// the cache would interpolate between left and right. Returns the samples in order.
+pub fn refine<T, F, E>(
+    axis: &AxisSpec,
+    refinement: Refinement,
+    sample: F,
+    error: E,
+) -> Vec<(f64, T)>
+where
+    T: Send,
+    F: Fn(f64) -> T + Sync,
+    E: Fn(&T, &T, &T) -> f64,
+{
+    // `AxisSpec`'s constructors already require this, but its fields are public.
+    assert!(
+        axis.size >= 2,
+        "refining needs at least 2 samples, got {}",
+        axis.size
+    );
+    let mut samples = sample_axis(axis, &sample);
+    // Whether the interval starting at each sample still needs checking.
+    let mut open = vec![true; samples.len() - 1];
+    loop {
+        let budget = refinement.max_size.saturating_sub(samples.len());
+        let candidates: Vec<usize> = (0..open.len())
+            .filter(|&i| open[i] && samples[i + 1].0 - samples[i].0 > MIN_SPACING)
+            .collect();
+        if budget == 0 || candidates.is_empty() {
+            return samples;
+        }
+
+        let middles: Vec<f64> = candidates
+            .iter()
+            .map(|&i| 0.5 * (samples[i].0 + samples[i + 1].0))
+            .collect();
+        let values = parallel_map(&middles, |&x| sample(x));
+        let mut splits: Vec<(usize, f64, T, f64)> = candidates
+            .into_iter()
+            .zip(middles)
+            .zip(values)
+            .filter_map(|((i, x), value)| {
+                // A NaN error can't be ranked, so the interval is left as it is.
+                let error = error(&samples[i].1, &samples[i + 1].1, &value);
+                (error > refinement.tolerance).then_some((i, x, value, error))
+            })
+            .collect();
+        // The worst intervals get the budget first.
+        splits.sort_by(|a, b| b.3.total_cmp(&a.3));
+        splits.truncate(budget);
+        if splits.is_empty() {
+            return samples;
+        }
+        splits.sort_by_key(|split| split.0);
+
+        let mut refined = Vec::with_capacity(samples.len() + splits.len());
+        let mut refined_open = Vec::with_capacity(open.len() + splits.len());
+        let mut splits = splits.into_iter().peekable();
+        for (i, sample) in samples.into_iter().enumerate() {
+            refined.push(sample);
+            match splits.next_if(|split| split.0 == i) {
+                Some((_, x, value, _)) => {
+                    refined.push((x, value));
+                    refined_open.extend([true, true]);
+                }
+                None if i < open.len() => refined_open.push(false),
+                None => {}
+            }
+        }
+        samples = refined;
+        open = refined_open;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache_spec::{AxisSpec, SamplingCurve};
+
+    use super::{refine, Refinement};
+
+    #[test]
+    fn refines_where_the_error_is() {
+        // Flat, then a steep rise towards 1, like the deflection near the photon sphere.
+        let f = |x: f64| x.powi(40);
+        let refinement = Refinement {
+            tolerance: 0.001,
+            max_size: 1000,
+        };
+        let samples = refine(
+            &AxisSpec::linear(5).unwrap(),
+            refinement,
+            f,
+            |left, right, middle| (0.5 * (left + right) - middle).abs(),
+        );
+        assert!(samples.len() < refinement.max_size);
+        assert!(samples.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(samples.iter().all(|(x, value)| f(*x) == *value));
+
+        // Every interval interpolates its midpoint within tolerance.
+        for pair in samples.windows(2) {
+            let x = 0.5 * (pair[0].0 + pair[1].0);
+            let interpolated = 0.5 * (pair[0].1 + pair[1].1);
+            assert!(
+                (interpolated - f(x)).abs() <= refinement.tolerance,
+                "x: {}",
+                x
+            );
+        }
+
+        // Most of the samples are where the curve bends.
+        let upper = samples.iter().filter(|(x, _)| *x > 0.75).count();
+        assert!(
+            upper > 3 * (samples.len() - upper),
+            "samples: {}",
+            samples.len()
+        );
+    }
+
+    #[test]
+    fn respects_budget() {
+        let refinement = Refinement {
+            tolerance: 0.,
+            max_size: 20,
+        };
+        let samples = refine(
+            &AxisSpec::linear(5).unwrap(),
+            refinement,
+            |x| x * x,
+            |left, right, middle| (0.5 * (left + right) - middle).abs(),
+        );
+        assert_eq!(samples.len(), 20);
+    }
+
+    #[test]
+    fn leaves_nan_intervals_alone() {
+        let refinement = Refinement {
+            tolerance: 0.001,
+            max_size: 100,
+        };
+        // NaN over the left half, a kink the refinement should chase on the right.
+        let f = |x: f64| match x < 0.5 {
+            true => f64::NAN,
+            false => (x - 0.8).abs(),
+        };
+        let samples = refine(
+            &AxisSpec::linear(5).unwrap(),
+            refinement,
+            f,
+            |left, right, middle| (0.5 * (left + right) - middle).abs(),
+        );
+        assert!(samples.len() > 5);
+        assert!(samples.iter().filter(|(x, _)| *x < 0.5).count() == 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 2 samples")]
+    fn needs_two_samples() {
+        let axis = AxisSpec {
+            size: 0,
+            ..AxisSpec::new(2, SamplingCurve::Linear).unwrap()
+        };
+        refine(
+            &axis,
+            Refinement {
+                tolerance: 0.001,
+                max_size: 100,
+            },
+            |x| x,
+            |left, right, middle| (0.5 * (left + right) - middle).abs(),
+        );
+    }
+}
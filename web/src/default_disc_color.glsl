@@ -16,6 +16,8 @@ uniform sampler2D galaxy;
 uniform ivec2 galaxy_dim;
 uniform sampler3D distance_cache_tex;
 uniform ivec3 distance_cache_tex_dim;
+uniform sampler3D g_factor_cache_tex;
+uniform ivec3 g_factor_cache_tex_dim;
 uniform sampler2D distance_cache_z_bounds;
 uniform ivec2 distance_cache_z_bounds_dim;
 uniform sampler2D direction_cache;
@@ -120,6 +122,24 @@ vec4 disc_color(float dist_01,float theta_01){
     return vec4(scale_color(vec3(show,0.),3.*(1.-density)),clamp(alpha,0.,1.));
 }
 
+// g from the two factors `GFactorCache` stores; 0 where the disc doesn't emit.
+float g_factor(vec2 shift,float alignment){
+    if(shift.x<=0.){
+        return 0.;
+    }
+    return shift.x/(1.-shift.y*alignment);
+}
+
+// Beaming and colour shift for light whose frequency is scaled by g on its way to the camera.
+// Where g is 0 nothing is emitted, so the disc is drawn black rather than dividing by g.
+vec4 apply_g_factor(vec4 color,float g){
+    if(g<=0.){
+        return vec4(0.,0.,0.,color.w);
+    }
+    vec3 shifted=color.xyz*vec3(1./g,1.,g);
+    return vec4(scale_color(shifted,g*g*g),color.w);
+}
+
 //
 /* Disc distance/angle calculations */
 //
@@ -164,6 +184,8 @@ vec4 get_disc_color(vec2 coord){
     
     vec3 travel_normal=normalize(cross(normalized_dir,true_start_dir));
     vec3 intersection=normalize(cross(travel_normal,vec3(0.,1.,0.)));
+    // The disc rotates around +y; light from its approaching side is blueshifted.
+    float alignment=travel_normal.y;
     float dist=dot(intersection,-normalized_pos);
     
     // there are two angles that matter;
@@ -193,7 +215,8 @@ vec4 get_disc_color(vec2 coord){
         float dist=texture(distance_cache_tex,vec3(z_index,other_angle_01.x,camera_dist_01)+dist_offset).x;
         if(dist>disc_dim.x&&dist<disc_dim.y){
             float dist_01=(disc_dim.y-dist)/(disc_dim.y-disc_dim.x);
-            total_disc_color=disc_color(dist_01,angle_01.y);
+            vec2 shift=texture(g_factor_cache_tex,vec3(z_index,other_angle_01.x,camera_dist_01)+dist_offset).xy;
+            total_disc_color=apply_g_factor(disc_color(dist_01,angle_01.y),g_factor(shift,alignment));
         }
     }
     z_bounds=texture(distance_cache_z_bounds,vec2(angle_01.x,camera_dist_01)+offset).xy;
@@ -203,7 +226,8 @@ vec4 get_disc_color(vec2 coord){
         if(dist>disc_dim.x&&dist<disc_dim.y){
             float dist_01=(disc_dim.y-dist)/(disc_dim.y-disc_dim.x);
             float alpha=1.-total_disc_color.w;
-            vec4 d_color=disc_color(dist_01,other_angle_01.y);
+            vec2 shift=texture(g_factor_cache_tex,vec3(z_index,angle_01.x,camera_dist_01)+dist_offset).xy;
+            vec4 d_color=apply_g_factor(disc_color(dist_01,other_angle_01.y),g_factor(shift,alignment));
             d_color*=alpha_mod;
             total_disc_color+=alpha*d_color;
         }
@@ -244,7 +268,8 @@ vec3 get_color(vec2 coord){
             }
             float i_1=val_1/20.;
             float index=clamp(max(i_0,i_1),0.,1.);
-            vec3 cached_dir=texture(direction_cache,vec2(index,(distance-distance_bounds.x)/(distance_bounds.y-distance_bounds.x))+.5/vec2(direction_cache_dim)).xzy;
+            float final_angle=texture(direction_cache,vec2(index,(distance-distance_bounds.x)/(distance_bounds.y-distance_bounds.x))+.5/vec2(direction_cache_dim)).x;
+            vec3 cached_dir=vec3(sin(final_angle),0.,-cos(final_angle));
             float angle=PI/2.;
             if(start_dir.x!=0.){
                 angle=atan(start_dir.y,start_dir.x);
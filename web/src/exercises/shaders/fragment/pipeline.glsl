@@ -148,7 +148,8 @@ vec3 get_color(vec2 coord){
         }
         float i_1=val_1/20.;
         float index=clamp(max(i_0,i_1),0.,1.);
-        vec3 cached_dir=texture(direction_cache,vec2(index,(distance-5.)/15.)+.5/vec2(cache_dim)).xzy;
+        float final_angle=texture(direction_cache,vec2(index,(distance-5.)/15.)+.5/vec2(cache_dim)).x;
+        vec3 cached_dir=vec3(sin(final_angle),0.,-cos(final_angle));
         float angle=PI/2.;
         if(start_dir.x!=0.){
             angle=atan(start_dir.y,start_dir.x);
@@ -9,7 +9,10 @@ use framework::source_context::SourceContext;
 use framework::texture_utils::generate_3d_texture_from_f32;
 use framework::texture_utils::generate_texture_from_f32;
 use framework::texture_utils::Format;
-use generate_artifacts::black_hole_cache::BlackHoleCache;
+use generate_artifacts::{
+    artifact_container::read_artifact, black_hole_cache::BlackHoleCache,
+    cache_spec::SHADER_DIRECTION_Z_CURVE,
+};
 use glam::IVec2;
 use glam::Mat3;
 use glam::Quat;
@@ -351,7 +354,7 @@ const GALAXY_URL: &str = "galaxy.jpg";
 const CONSTELLATIONS_URL: &str = "constellations.jpg";
 const STARS_URL: &str = "stars.jpg";
 const COMBINED_URL: &str = "combined.jpg";
-const BLACK_HOLE_CACHE_URL: &str = "black_hole_cache.txt";
+const BLACK_HOLE_CACHE_URL: &str = "black_hole_cache.bin";
 const NOISE_URL: &str = "noise.jpg";
 
 fn to_image(u8: Uint8Array) -> DynamicImage {
@@ -373,10 +376,30 @@ impl ImageCache {
         let disc_noise_tex = fetch_rgb_texture(gl, NOISE_URL, "disc_noise").await;
 
         let black_hole_cache = fetch_url_binary(BLACK_HOLE_CACHE_URL.to_string()).await?;
-        let black_hole_cache =
-            serde_json::from_slice::<BlackHoleCache>(&black_hole_cache.to_vec()).unwrap();
+        let black_hole_cache = read_artifact::<BlackHoleCache>(&black_hole_cache.to_vec())
+            .map_err(|error| JsValue::from_str(&format!("{}: {}", BLACK_HOLE_CACHE_URL, error)))?;
         let direction_cache = black_hole_cache.direction_cache;
         let distance_cache = black_hole_cache.distance_cache;
+        // Shifts stored before they followed the resampled texture may not line up with it, and
+        // older caches have none; either way the cache has to be regenerated.
+        let (distance_size, angle_size, z_size) = distance_cache.cache_size;
+        let expected = distance_size * angle_size * z_size;
+        let stale = |reason: String| {
+            let message = format!("{} is stale: {}", BLACK_HOLE_CACHE_URL, reason);
+            console_log!("{}", message);
+            JsValue::from_str(&message)
+        };
+        let g_factor_cache = match black_hole_cache.g_factor_cache {
+            Some(cache) if cache.shifts.len() == expected => cache,
+            Some(cache) => {
+                return Err(stale(format!(
+                    "it has {} g factor shifts, but its distance cache needs {}",
+                    cache.shifts.len(),
+                    expected
+                )))
+            }
+            None => return Err(stale("it has no g factor cache".to_string())),
+        };
 
         let disc_dim = UniformContext::vec2(
             Vec2::new(
@@ -386,10 +409,6 @@ impl ImageCache {
             "disc_dim",
         );
 
-        let mut distance_cache_vec = Vec::new();
-        let mut z_bounds_vec = Vec::new();
-        let mut min_z_vec = Vec::new();
-
         let min_angle = UniformContext::f32(
             distance_cache.distance_angle_to_z_to_distance[0].min_angle as f32,
             "min_angle",
@@ -401,19 +420,22 @@ impl ImageCache {
             ),
             "distance_bounds",
         );
-        for fixed_distance in distance_cache.distance_angle_to_z_to_distance {
-            min_z_vec.push(fixed_distance.min_z);
-            for fixed_angle in fixed_distance.angle_to_z_to_distance {
-                let z_bounds = fixed_angle.z_bounds;
-                z_bounds_vec.push(z_bounds.0 as f32);
-                z_bounds_vec.push(z_bounds.1 as f32);
-                for fixed_z in fixed_angle.z_to_distance {
-                    distance_cache_vec.push(fixed_z as f32);
-                }
-            }
+
+        // The shaders look the caches up on fixed axes, so the caches are resampled onto them
+        // rather than uploaded as they were sampled.
+        let distance_texture = distance_cache.texture();
+        let distance_cache_vec: Vec<f32> = distance_texture
+            .distances
+            .iter()
+            .map(|dist| *dist as f32)
+            .collect();
+        let mut z_bounds_vec = Vec::new();
+        for z_bounds in &distance_texture.z_bounds {
+            z_bounds_vec.push(z_bounds.0 as f32);
+            z_bounds_vec.push(z_bounds.1 as f32);
         }
 
-        let (width, height, depth) = distance_cache.cache_size;
+        let (width, height, depth) = distance_texture.dimensions;
         let distance_cache_tex = generate_3d_texture_from_f32(
             &gl.gl,
             &distance_cache_vec,
@@ -429,6 +451,26 @@ impl ImageCache {
             height as i32,
             depth as i32,
         );
+        let mut g_factor_vec = Vec::new();
+        for (gravitational_shift, doppler_coefficient) in g_factor_cache.shifts {
+            g_factor_vec.push(gravitational_shift as f32);
+            g_factor_vec.push(doppler_coefficient as f32);
+        }
+        let g_factor_tex = generate_3d_texture_from_f32(
+            &gl.gl,
+            &g_factor_vec,
+            width as i32,
+            height as i32,
+            depth as i32,
+            Format::RG,
+        );
+        let g_factor_tex = UniformContext::texture_3d(
+            g_factor_tex,
+            "g_factor_cache_tex",
+            width as i32,
+            height as i32,
+            depth as i32,
+        );
         let distance_cache_z_bounds =
             generate_texture_from_f32(&gl.gl, &z_bounds_vec, height as i32, Format::RG);
         let distance_cache_z_bounds = UniformContext::new_from_allocated_val(
@@ -437,25 +479,23 @@ impl ImageCache {
             height as i32,
             depth as i32,
         );
-        let mut direction_vec = Vec::new();
-        let mut direction_z_max_vec = Vec::new();
 
-        let direction_height = direction_cache.distance_angle_to_z_to_distance.len();
-        let direction_width = direction_cache.distance_angle_to_z_to_distance[0]
-            .z_to_final_dir
-            .len();
-        for y in 0..direction_height {
-            let cache = &direction_cache.distance_angle_to_z_to_distance[y];
-            direction_z_max_vec.push(cache.min_z as f32);
-            direction_z_max_vec.push(cache.max_z as f32);
-            for x in 0..direction_width {
-                let final_dir = cache.z_to_final_dir[x].1;
-                direction_vec.push(final_dir.0 as f32);
-                direction_vec.push(final_dir.1 as f32);
-            }
+        let direction_texture = direction_cache.texture(&SHADER_DIRECTION_Z_CURVE);
+        // Unwrapped angles blend correctly where the direction loops around; the shaders rebuild
+        // the direction from them.
+        let direction_vec: Vec<f32> = direction_texture
+            .final_angles
+            .iter()
+            .map(|angle| *angle as f32)
+            .collect();
+        let mut direction_z_max_vec = Vec::new();
+        for z_bounds in &direction_texture.z_bounds {
+            direction_z_max_vec.push(z_bounds.0 as f32);
+            direction_z_max_vec.push(z_bounds.1 as f32);
         }
+        let (direction_width, direction_height) = direction_texture.dimensions;
         let direction_tex =
-            generate_texture_from_f32(&gl.gl, &direction_vec, direction_width as i32, Format::RG);
+            generate_texture_from_f32(&gl.gl, &direction_vec, direction_width as i32, Format::R);
         let direction_tex = UniformContext::new_from_allocated_val(
             direction_tex,
             "direction_cache",
@@ -485,6 +525,7 @@ impl ImageCache {
                 disc_dim,
                 distance_cache_z_bounds,
                 distance_cache_tex,
+                g_factor_tex,
                 min_angle,
                 distance_bounds,
                 direction_tex,
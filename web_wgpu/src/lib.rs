@@ -1,6 +1,7 @@
 use std::time::SystemTime;
 
 
+use generate_artifacts::artifact_container::{read_artifact, Artifact};
 use glam::Mat4;
 use shader::{
     black_hole::BlackHole,
@@ -21,6 +22,12 @@ use winit::{
 };
 
 mod shader;
+
+// Artifacts are built into the binary, so a bad one is a build problem; name it in the panic.
+fn read_bundled_artifact<T: Artifact>(name: &str, bytes: &[u8]) -> T {
+    read_artifact(bytes).unwrap_or_else(|error| panic!("{}: {}", name, error))
+}
+
 struct State {
     surface: wgpu::Surface,
     device: wgpu::Device,
@@ -39,6 +46,7 @@ struct State {
     diffuse_bind_group: wgpu::BindGroup,
     depth_texture: Texture,
     start_time: SystemTime,
+    wormhole_throat_radius: f32,
 }
 
 impl State {
@@ -111,6 +119,7 @@ impl State {
             disc_bounds: [2., 12.],
             distance_bounds: [5., 30.],
             radius: [1.0],
+            throat_radius: [0.],
         };
         let (black_hole_buffer, _) = black_hole.to_buffer(&device);
         let render_params = RenderParams {
@@ -234,7 +243,7 @@ impl State {
         .to_vec();
 
         let angle_cache: ViewAngleParameterCache =
-            serde_json::from_slice(include_bytes!("angle_cache.txt")).unwrap();
+            read_bundled_artifact("angle_cache.bin", include_bytes!("angle_cache.bin"));
 
         let mut theta_final = Vec::new();
         let mut theta_max_start = Vec::new();
@@ -286,7 +295,7 @@ impl State {
             theta_min_start_tex.add_entry(bind_group_entries, bind_group_layout_entries);
 
         let view_bound_sampler: ViewBound =
-            serde_json::from_slice(include_bytes!("view_bounds.txt")).unwrap();
+            read_bundled_artifact("view_bounds.bin", include_bytes!("view_bounds.bin"));
         let view_bound_tex = SmallFloatTexture::from_f32(
             &device,
             &queue,
@@ -298,6 +307,100 @@ impl State {
         (bind_group_entries, bind_group_layout_entries) =
             view_bound_tex.add_entry(bind_group_entries, bind_group_layout_entries);
 
+        let time_delay: TimeDelayTable =
+            read_bundled_artifact("time_delay.bin", include_bytes!("time_delay.bin"));
+        let time_delay_tex = SmallFloatTexture::from_f32(
+            &device,
+            &queue,
+            &time_delay.delays,
+            time_delay.dimensions,
+            "time delay",
+        )
+        .unwrap();
+        (bind_group_entries, bind_group_layout_entries) =
+            time_delay_tex.add_entry(bind_group_entries, bind_group_layout_entries);
+
+        let wormhole_directions: WormholeDirectionTable = read_bundled_artifact(
+            "wormhole_directions.bin",
+            include_bytes!("wormhole_directions.bin"),
+        );
+        let wormhole_angle_tex = SmallFloatTexture::from_f32(
+            &device,
+            &queue,
+            &wormhole_directions.final_angles,
+            wormhole_directions.dimensions,
+            "wormhole angle",
+        )
+        .unwrap();
+        let wormhole_far_side_tex = SmallFloatTexture::from_f32(
+            &device,
+            &queue,
+            &wormhole_directions.far_side,
+            wormhole_directions.dimensions,
+            "wormhole far side",
+        )
+        .unwrap();
+        (bind_group_entries, bind_group_layout_entries) =
+            wormhole_angle_tex.add_entry(bind_group_entries, bind_group_layout_entries);
+        (bind_group_entries, bind_group_layout_entries) =
+            wormhole_far_side_tex.add_entry(bind_group_entries, bind_group_layout_entries);
+
+        // The sky on the far side of the wormhole.
+        let far_galaxy_tex = Texture::from_bytes(
+            &device,
+            &queue,
+            include_bytes!("far_side.jpg"),
+            "Far side backdrop",
+        )
+        .unwrap();
+        bind_group_layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: bind_group_layout_entries.len() as u32,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        });
+        bind_group_entries.push(wgpu::BindGroupEntry {
+            binding: bind_group_entries.len() as u32,
+            resource: wgpu::BindingResource::TextureView(&far_galaxy_tex.view),
+        });
+        bind_group_layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: bind_group_layout_entries.len() as u32,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        });
+        bind_group_entries.push(wgpu::BindGroupEntry {
+            binding: bind_group_entries.len() as u32,
+            resource: wgpu::BindingResource::Sampler(&far_galaxy_tex.sampler),
+        });
+
+        let g_factor: GFactorTable =
+            read_bundled_artifact("g_factor.bin", include_bytes!("g_factor.bin"));
+        let gravitational_shift_tex = SmallFloatTexture::from_f32(
+            &device,
+            &queue,
+            &g_factor.gravitational_shifts,
+            g_factor.dimensions,
+            "gravitational shift",
+        )
+        .unwrap();
+        let doppler_coefficient_tex = SmallFloatTexture::from_f32(
+            &device,
+            &queue,
+            &g_factor.doppler_coefficients,
+            g_factor.dimensions,
+            "doppler coefficient",
+        )
+        .unwrap();
+        (bind_group_entries, bind_group_layout_entries) =
+            gravitational_shift_tex.add_entry(bind_group_entries, bind_group_layout_entries);
+        (bind_group_entries, bind_group_layout_entries) =
+            doppler_coefficient_tex.add_entry(bind_group_entries, bind_group_layout_entries);
+
         let stencil_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &stencil_bind_group_layout,
             entries: &Vec::new(),
@@ -448,6 +551,7 @@ impl State {
             diffuse_bind_group,
             depth_texture,
             start_time,
+            wormhole_throat_radius: wormhole_directions.throat_radius,
         }
     }
 
@@ -529,6 +633,22 @@ impl State {
                 self.params.1.update_cursor(pos);
                 self.update_params();
             }
+            // Switches between the black hole and the wormhole.
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::W),
+                        ..
+                    },
+                ..
+            } => {
+                self.params.0.throat_radius = match self.params.0.throat_radius[0] > 0. {
+                    true => [0.],
+                    false => [self.wormhole_throat_radius],
+                };
+                self.update_params();
+            }
             _ => {
                 return false;
             }
@@ -625,7 +745,9 @@ impl State {
 use wasm_bindgen::prelude::*;
 use winit::dpi::PhysicalSize;
 use wire_structs::sampler::{
+    g_factor_table::GFactorTable, time_delay_table::TimeDelayTable,
     view_angle_parameter_cache::ViewAngleParameterCache, view_bound::ViewBound,
+    wormhole_direction_table::WormholeDirectionTable,
 };
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
 pub async fn run() {
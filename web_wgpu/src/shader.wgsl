@@ -29,7 +29,7 @@ struct BlackHole {
     disc_bounds: vec2<f32>,
     distance_bounds: vec2<f32>,
     radius: f32,
-    temp: f32,
+    throat_radius: f32,
     temp1: f32,
     temp2: f32,
 }
@@ -75,6 +75,30 @@ var theta_min_start_s: sampler;
 var view_bounds_t: texture_1d<f32>;
 @group(0) @binding(15)
 var view_bounds_s: sampler;
+@group(0) @binding(16)
+var time_delay_t: texture_3d<f32>;
+@group(0) @binding(17)
+var time_delay_s: sampler;
+@group(0) @binding(18)
+var wormhole_angle_t: texture_2d<f32>;
+@group(0) @binding(19)
+var wormhole_angle_s: sampler;
+@group(0) @binding(20)
+var wormhole_far_side_t: texture_2d<f32>;
+@group(0) @binding(21)
+var wormhole_far_side_s: sampler;
+@group(0) @binding(22)
+var far_galaxy_t: texture_2d<f32>;
+@group(0) @binding(23)
+var far_galaxy_s: sampler;
+@group(0) @binding(24)
+var gravitational_shift_t: texture_3d<f32>;
+@group(0) @binding(25)
+var gravitational_shift_s: sampler;
+@group(0) @binding(26)
+var doppler_coefficient_t: texture_3d<f32>;
+@group(0) @binding(27)
+var doppler_coefficient_s: sampler;
 
 fn to_float(v: vec2<f32>) -> f32 {
     return v.x + v.y/2048.0;
@@ -105,7 +129,7 @@ fn scale_color(color:vec3<f32>, scale: f32) -> vec3<f32>{
 }
 
 
-fn disc_color( dist_01:f32, theta_01:f32)-> vec4<f32>{
+fn disc_color( dist_01:f32, theta_01:f32, time_s:f32)-> vec4<f32>{
 
 let REVOLUTION_COUNT =10.;
 let ARMS_COUNT = 2.0;
@@ -124,7 +148,7 @@ let dist_01 =1.0-dist_01;
     let density=clamp(1.-dist_rescaled/1.1,0.,1.);
     
     let x =arm ;
-    let y = arm_dist+render_params.time_s*CLOUD_DENSITY;
+    let y = arm_dist+time_s*CLOUD_DENSITY;
     let noi_tex=textureSample(noise_t,noise_s,fract(vec2(x,y))).r*1.1;
     let noi=smoothstep(0.25,.5,dist_01)*clamp((1./density)*(noi_tex -(1.-density)),0.,1.);
     let brightness=2.*clamp(1.-density,0.,1.);
@@ -145,6 +169,37 @@ fn get_params(d_01:f32, coords: vec2<f32>) -> vec4<f32> {
    return vec4(theta_f + PI / 2.,theta_max + PI / 2., min_dist, theta_min);
 }
 
+// Seconds of animation per unit of light travel distance.
+let LIGHT_TRAVEL_TIME_S: f32 = 0.05;
+
+// Time at which the light that reaches the camera now left the disc at distance `dist`, after
+// sweeping `theta` around the black hole.
+fn emission_time(dist: f32, theta: f32, d_01: f32) -> f32 {
+    let disc_bounds = black_hole.disc_bounds;
+    let dist_01 = (dist - disc_bounds.x) / (disc_bounds.y - disc_bounds.x);
+    let delay = to_high_p_float(textureSample(time_delay_t, time_delay_s, vec3(dist_01, theta / TAU, d_01)));
+    return render_params.time_s - LIGHT_TRAVEL_TIME_S * delay;
+}
+
+// Frequency shift g = observed / emitted for light from the disc at distance `dist`, after
+// sweeping `theta` around the black hole, from the `GFactorTable` artifact. `alignment` is the
+// cosine between the disc's rotation axis and the angular momentum of the light. 0 where the disc
+// doesn't emit.
+fn g_factor(dist: f32, theta: f32, d_01: f32, alignment: f32) -> f32 {
+    let disc_bounds = black_hole.disc_bounds;
+    let coords = vec3((dist - disc_bounds.x) / (disc_bounds.y - disc_bounds.x), theta / TAU, d_01);
+    let gravitational = to_high_p_float(textureSample(gravitational_shift_t, gravitational_shift_s, coords));
+    let doppler = to_high_p_float(textureSample(doppler_coefficient_t, doppler_coefficient_s, coords));
+    return select(gravitational / (1. - doppler * alignment), 0., gravitational <= 0.);
+}
+
+// Beaming and colour shift for light whose frequency is scaled by g. Where g is 0 nothing is
+// emitted, so the disc is drawn black rather than dividing by g.
+fn apply_g_factor(color: vec4<f32>, g: f32) -> vec4<f32> {
+    let shifted = color.xyz * vec3(1. / max(g, 1e-6), 1., g);
+    return select(vec4(scale_color(shifted, g * g * g), color.w), vec4(0., 0., 0., color.w), g <= 0.);
+}
+
 fn in_bounds(bounds:vec2<f32>, v:f32) -> f32 {
     return step(bounds.x, v) - step(bounds.y, v);
 }
@@ -196,8 +251,17 @@ fn get_disc_color( start_dir: vec3<f32>, coords:vec2<f32>, d_01:f32) -> vec4<f32
     let has_secondary = step(1.5, params.z);
     let is_secondary = step(3.,d_secondary) - step(13.,d_secondary);
 
-    var main_c = is_main*disc_color((d_main - 3.) / 10., angle_01.y);
-    let secondary_c = has_secondary*is_secondary*disc_color((d_secondary - 3.) / 10., other_angle_01.y);
+    // The secondary image travels further around the black hole, so it shows the disc earlier.
+    let main_time = emission_time(d_main, TAU*angle_01.x, d_01);
+    let secondary_time = emission_time(d_secondary, TAU*other_angle_01.x, d_01);
+
+    // The disc rotates around its normal, so light from the approaching side is blueshifted.
+    let alignment = dot(travel_normal, disc_normal);
+    let main_g = g_factor(d_main, TAU*angle_01.x, d_01, alignment);
+    let secondary_g = g_factor(d_secondary, TAU*other_angle_01.x, d_01, alignment);
+
+    var main_c = is_main*apply_g_factor(disc_color((d_main - 3.) / 10., angle_01.y, main_time), main_g);
+    let secondary_c = has_secondary*is_secondary*apply_g_factor(disc_color((d_secondary - 3.) / 10., other_angle_01.y, secondary_time), secondary_g);
 
     return vec4( main_c.w * main_c.xyz + (1. - main_c.w)*secondary_c.xyz, main_c.w + (1. - main_c.w)*secondary_c.w);
 }
@@ -213,6 +277,23 @@ fn background_color(start_dir: vec3<f32>, d_01: f32,coords:vec2<f32>) -> vec3<f3
     return (1.-hit_black_hole)*textureSample(galaxy_t,galaxy_s,vec2(phi, theta)).xyz;
 }
 
+// Sky seen along `start_dir` around an Ellis wormhole. Rays that pass through the throat see the
+// far side's sky instead of our own.
+fn wormhole_color(start_dir: vec3<f32>, d_01: f32) -> vec3<f32> {
+    // The wormhole table covers z in [0, 1], sampled evenly in the angle to the throat.
+    let z_01 = 1. - acos(clamp(start_dir.z, 0., 1.)) / PI2;
+    let angle = to_high_p_float(textureSample(wormhole_angle_t, wormhole_angle_s, vec2(z_01, d_01)));
+    let far_side = step(0.5, textureSample(wormhole_far_side_t, wormhole_far_side_s, vec2(z_01, d_01)).x);
+    let rot_angle = atan2(start_dir.y, start_dir.x);
+    let final_dir = vec3(sin(angle)*cos(rot_angle), sin(angle)*sin(rot_angle), cos(angle));
+    let final_dir = (render_params.observer_matrix * vec4(final_dir,0.)).xyz;
+    let theta = (atan2(final_dir.y, length(final_dir.xz)) / PI + 0.5) % 1.;
+    let phi = (atan2(final_dir.z,final_dir.x)/ TAU + 1.)  % 1.;
+    let near_color = textureSample(galaxy_t, galaxy_s, vec2(phi, theta)).xyz;
+    let far_color = textureSample(far_galaxy_t, far_galaxy_s, vec2(phi, theta)).xyz;
+    return far_side * far_color + (1. - far_side) * near_color;
+}
+
 // returns normalized coords. If < 0. or 1. >, then there should be letterboxes
 fn to_coords(tex_coords: vec2<f32>) -> vec2<f32> {
     return (tex_coords + render_params.coords_offset) * render_params.coords_scale;
@@ -228,7 +309,9 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let start_dir = normalize(vec3(render_params.view_width*coords, 1.));
     let background_color=background_color(start_dir,d_01,coords);
     let disc_color = get_disc_color(start_dir, coords, d_01);
-    let final_color =disc_color.w* disc_color.xyz + (1.-disc_color.w)*background_color;
+    let black_hole_color =disc_color.w* disc_color.xyz + (1.-disc_color.w)*background_color;
+    let is_wormhole = step(0.000001, black_hole.throat_radius);
+    let final_color = is_wormhole * wormhole_color(start_dir, d_01) + (1. - is_wormhole) * black_hole_color;
    
 
     if (coords.x <-0.5 || coords.y < -0.5 || coords.x >0.5 || coords.y >0.5) {
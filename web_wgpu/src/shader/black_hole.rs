@@ -6,6 +6,8 @@ pub struct BlackHole {
     pub disc_bounds: [f32; 2],
     pub distance_bounds: [f32; 2],
     pub radius: [f32; 1],
+    // Radius of the wormhole throat; 0 renders the black hole instead.
+    pub throat_radius: [f32; 1],
 }
 
 impl BlackHole {
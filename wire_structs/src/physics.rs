@@ -0,0 +1,164 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+// The pseudo force law shared by the f64 path integrator in generate_artifacts and the f32 GPU
+// sampler. `shader.wgsl` can't call into Rust, so its `force` is kept in line by the CPU port in
+// `sampler::gpu::cpu_state`, which is tested against both this module and the shader.
+//
+// The force on a particle at p is `pseudo_force_scale(|p|^2, magnitude) * -p`, which is
+// `magnitude / r^5` towards the black hole.
+
+pub trait Scalar:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    fn from_f64(value: f64) -> Self;
+    fn sqrt(self) -> Self;
+    fn powi(self, n: i32) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($t:ty) => {
+        impl Scalar for $t {
+            fn from_f64(value: f64) -> Self {
+                value as $t
+            }
+            fn sqrt(self) -> Self {
+                <$t>::sqrt(self)
+            }
+            fn powi(self, n: i32) -> Self {
+                <$t>::powi(self, n)
+            }
+        }
+    };
+}
+
+impl_scalar!(f32);
+impl_scalar!(f64);
+
+// Strength of the force for a black hole of `radius` seen from `camera_distance`.
+pub fn pseudo_force_magnitude<S: Scalar>(radius: S, camera_distance: S) -> S {
+    let two = S::from_f64(2.);
+    let one = S::from_f64(1.);
+    two / ((two / radius.powi(4)) - (one / camera_distance.powi(4)))
+}
+
+// Multiplies -p to give the force; takes |p|^2 so callers don't need a square root.
+pub fn pseudo_force_scale<S: Scalar>(distance_sq: S, magnitude: S) -> S {
+    magnitude / (distance_sq * distance_sq * distance_sq)
+}
+
+// The force is -grad of this.
+pub fn pseudo_potential<S: Scalar>(distance: S, magnitude: S) -> S {
+    -magnitude / (S::from_f64(4.) * distance.powi(4))
+}
+
+// Speed rays are launched with from `distance`, given the black hole's radius.
+pub fn pseudo_initial_speed<S: Scalar>(distance: S, radius: S, magnitude: S) -> S {
+    let half = S::from_f64(0.5);
+    let two = S::from_f64(2.);
+    let one = S::from_f64(1.);
+    (half * magnitude * (two / radius.powi(4) - one / distance.powi(4))).sqrt()
+}
+
+// Speed at `distance` of a particle moving at `reference_speed` at `reference_distance`, from
+// conservation of energy.
+pub fn pseudo_speed_at<S: Scalar>(
+    distance: S,
+    reference_distance: S,
+    reference_speed: S,
+    magnitude: S,
+) -> S {
+    let half = S::from_f64(0.5);
+    let two = S::from_f64(2.);
+    let energy =
+        half * reference_speed * reference_speed + pseudo_potential(reference_distance, magnitude);
+    (two * (energy - pseudo_potential(distance, magnitude))).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        pseudo_force_magnitude, pseudo_force_scale, pseudo_initial_speed, pseudo_potential,
+        pseudo_speed_at,
+    };
+
+    // f32 keeps about 7 significant digits; the r^-24 in the force scale loses a few more.
+    const F32_TOLERANCE: f64 = 1e-5;
+
+    fn assert_close(f32_value: f32, f64_value: f64, name: &str) {
+        let error = (f32_value as f64 - f64_value).abs() / f64_value.abs().max(1e-30);
+        assert!(
+            error < F32_TOLERANCE,
+            "{}: f32 {}, f64 {}, relative error {}",
+            name,
+            f32_value,
+            f64_value,
+            error
+        );
+    }
+
+    #[test]
+    fn f32_matches_f64() {
+        for radius in [0.5_f64, 1., 1.5, 2.] {
+            for camera_distance in [3_f64, 5., 10., 20.] {
+                let magnitude = pseudo_force_magnitude(radius, camera_distance);
+                let magnitude_f32 = pseudo_force_magnitude(radius as f32, camera_distance as f32);
+                assert_close(magnitude_f32, magnitude, "magnitude");
+
+                for distance in [1.01 * radius, 1.5 * radius, 3., 10., 40.] {
+                    let distance_f32 = distance as f32;
+                    assert_close(
+                        pseudo_force_scale(distance_f32 * distance_f32, magnitude_f32),
+                        pseudo_force_scale(distance * distance, magnitude),
+                        "force",
+                    );
+                    assert_close(
+                        pseudo_potential(distance_f32, magnitude_f32),
+                        pseudo_potential(distance, magnitude),
+                        "potential",
+                    );
+                    assert_close(
+                        pseudo_initial_speed(distance_f32, radius as f32, magnitude_f32),
+                        pseudo_initial_speed(distance, radius, magnitude),
+                        "initial speed",
+                    );
+                    // A ray launched from the camera, so it's never bound.
+                    assert_close(
+                        pseudo_speed_at(
+                            distance_f32,
+                            camera_distance as f32,
+                            pseudo_initial_speed(
+                                camera_distance as f32,
+                                radius as f32,
+                                magnitude_f32,
+                            ),
+                            magnitude_f32,
+                        ),
+                        pseudo_speed_at(
+                            distance,
+                            camera_distance,
+                            pseudo_initial_speed(camera_distance, radius, magnitude),
+                            magnitude,
+                        ),
+                        "speed",
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn speed_conserves_energy() {
+        let magnitude = pseudo_force_magnitude(1.5_f64, 20.);
+        let speed = pseudo_speed_at(3., 10., 0.8, magnitude);
+        let energy =
+            |distance: f64, speed: f64| 0.5 * speed * speed + pseudo_potential(distance, magnitude);
+        assert!((energy(3., speed) - energy(10., 0.8)).abs() < 1e-12);
+        assert!((pseudo_speed_at(10., 10., 0.8, magnitude) - 0.8).abs() < 1e-12);
+    }
+}
@@ -2,6 +2,8 @@ use std::f32::consts::{PI, TAU};
 
 use glam::Vec2;
 
+use crate::physics::pseudo_speed_at;
+
 use super::{
     dimension_params::DimensionParams,
     gpu::{field::Particle, gpu_state::simulate_particles},
@@ -15,38 +17,56 @@ pub struct DistanceVelocityPaths {
     pub paths: Vec<SimulatedPath>,
     pub velocity_bounds: (f32, f32),
 }
-const SAMPLES_PER_DIST: usize = 1 << 6;
-const ITERATIONS: u32 = 5;
+
+// What the search for the velocity that keeps a particle orbiting depends on. Each iteration
+// simulates `samples` velocities spread over the bounds, then narrows the bounds around the best.
+#[derive(Debug, PartialEq, Deserialize, Serialize, Copy, Clone)]
+pub struct DistanceVelocityParams {
+    pub angles: DimensionParams,
+    pub initial_velocity_bounds: (f32, f32),
+    pub samples: usize,
+    pub iterations: u32,
+    pub max_distance: f32,
+}
+
+impl Default for DistanceVelocityParams {
+    fn default() -> Self {
+        Self {
+            angles: DimensionParams {
+                size: 360,
+                bounds: [0., TAU],
+            },
+            initial_velocity_bounds: (0., 2.5),
+            samples: 1 << 6,
+            iterations: 5,
+            max_distance: 40.,
+        }
+    }
+}
 
 impl DistanceVelocityPaths {
     pub fn velocity_at(&self, dist: f32) -> f32 {
-        let start_pe = 0.25 * (dist.powi(-4) - 1.5_f32.powi(-4));
+        // `Particle::new` uses a unit magnitude, and the paths here start at 1.5.
         let final_v = (self.velocity_bounds.1 + self.velocity_bounds.0) / 2.;
-        let final_ke = 0.5 * final_v * final_v;
-        let start_ke = final_ke + start_pe;
-        (2. * start_ke).sqrt()
+        pseudo_speed_at(dist, 1.5, final_v, 1.)
     }
 
-    pub fn new() -> Self {
-        let angles = DimensionParams {
-            size: 360,
-            bounds: [0., TAU],
-        };
-
-        let mut velocity_bounds = (0., 2.5);
+    pub fn new(params: &DistanceVelocityParams) -> Self {
+        let samples = params.samples;
+        let mut velocity_bounds = params.initial_velocity_bounds;
 
         let mut ret = Vec::new();
-        for _ in 0..ITERATIONS {
+        for _ in 0..params.iterations {
             let mut test = Vec::new();
             let initial_dir = Vec2::new(1., 0.);
-            for sample_index in 0..SAMPLES_PER_DIST {
-                let velocity = sample_index as f32 / (SAMPLES_PER_DIST - 1) as f32;
+            for sample_index in 0..samples {
+                let velocity = sample_index as f32 / (samples - 1) as f32;
                 let velocity =
                     velocity * (velocity_bounds.1 - velocity_bounds.0) + velocity_bounds.0;
                 let particle = Particle::new(1.5, initial_dir, velocity);
                 test.push(particle);
             }
-            let paths = simulate_particles(test, &angles, 40.);
+            let paths = simulate_particles(test, &params.angles, params.max_distance);
 
             ret = Vec::new();
             for ray in &paths {
@@ -90,13 +110,13 @@ impl DistanceVelocityPaths {
                 true => opt,
                 false => opt - 1,
             };
-            let min_vel = min as f32 / (SAMPLES_PER_DIST - 1) as f32;
+            let min_vel = min as f32 / (samples - 1) as f32;
             let min_vel = min_vel * (velocity_bounds.1 - velocity_bounds.0) + velocity_bounds.0;
             let max = match opt == paths.len() - 1 {
                 true => opt,
                 false => opt + 1,
             };
-            let max_vel = max as f32 / (SAMPLES_PER_DIST - 1) as f32;
+            let max_vel = max as f32 / (samples - 1) as f32;
             let max_vel = max_vel * (velocity_bounds.1 - velocity_bounds.0) + velocity_bounds.0;
             velocity_bounds = (min_vel, max_vel);
         }
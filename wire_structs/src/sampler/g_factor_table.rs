@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+// The two factors of the disc's frequency shift g = gravitational_shift / (1 - doppler_coefficient
+// * alignment), where alignment is the cosine between the disc's rotation axis and the angular
+// momentum of the light. A gravitational shift of 0 means the disc doesn't emit there.
+//
+// Samples are indexed like `TimeDelayTable`: (disc radius, angle, camera distance), with the disc
+// radius varying fastest.
+#[derive(Serialize, Deserialize)]
+pub struct GFactorTable {
+    pub dimensions: [u32; 3],
+    pub disc_bounds: [f32; 2],
+    pub distance_bounds: [f32; 2],
+    pub gravitational_shifts: Vec<f32>,
+    pub doppler_coefficients: Vec<f32>,
+}
@@ -0,0 +1,282 @@
+use std::thread;
+
+use glam::{Vec2, Vec4};
+
+use super::{field::Particle, gpu_state::AngleLine, simulated_ray::SimulatedRay};
+
+// CPU port of `shader.wgsl`, for machines without a GPU. Every function mirrors the shader
+// function of the same name and does its f32 arithmetic in the same order, so the results
+// only differ where the GPU fuses or reorders operations.
+
+// Same as the number of dispatches `SimulatorState::simulate_particles` makes.
+pub const STEP_COUNT: usize = 1 << 19;
+const STEP_SIZE: f32 = 0.005;
+
+#[derive(Clone, Copy)]
+struct CpuParticle {
+    p: Vec2,
+    v: Vec2,
+    index: u32,
+    black_hole_magnitude: f32,
+    black_hole_radius: f32,
+    max_distance: f32,
+}
+
+impl CpuParticle {
+    fn from_particle(particle: &Particle) -> Self {
+        Self {
+            p: Vec2::new(particle.pv[0], particle.pv[1]),
+            v: Vec2::new(particle.pv[2], particle.pv[3]),
+            index: particle.index,
+            black_hole_magnitude: particle.black_hole_magnitude,
+            black_hole_radius: particle.black_hole_radius,
+            max_distance: particle.max_distance,
+        }
+    }
+}
+
+fn crossed_line(particle: &CpuParticle, dir: Vec2) -> bool {
+    particle.p.x * dir.y - particle.p.y * dir.x <= 0.
+}
+
+fn intersection(start: &CpuParticle, end: &CpuParticle, dir: Vec2) -> f32 {
+    let start = start.p;
+    let diff = end.p - start;
+    -(dir.x * start.y - dir.y * start.x) / (diff.x * dir.y - diff.y * dir.x)
+}
+
+fn stop(particle: &CpuParticle) -> bool {
+    let dist = particle.p.length();
+    dist <= 0.9 * particle.black_hole_radius || dist > particle.max_distance
+}
+
+fn force(p: Vec2, magnitude: f32) -> Vec2 {
+    let diff = -p;
+    let len = diff.dot(diff);
+    let len_6 = len * len * len;
+    diff * magnitude / len_6
+}
+
+fn rk4(particle: &CpuParticle, h: f32) -> CpuParticle {
+    let k_0 = h * particle.v;
+    let l_0 = h * force(particle.p, particle.black_hole_magnitude);
+
+    let k_1 = h * (particle.v + 0.5 * l_0);
+    let l_1 = h * force(particle.p + 0.5 * k_0, particle.black_hole_magnitude);
+
+    let k_2 = h * (particle.v + 0.5 * l_1);
+    let l_2 = h * force(particle.p + 0.5 * k_1, particle.black_hole_magnitude);
+
+    let k_3 = h * (particle.v + l_2);
+    let l_3 = h * force(particle.p + k_2, particle.black_hole_magnitude);
+
+    let delta_p = 0.16666666 * (k_0 + 2.0 * k_1 + 2.0 * k_2 + k_3);
+    let delta_v = 0.16666666 * (l_0 + 2.0 * l_1 + 2.0 * l_2 + l_3);
+
+    CpuParticle {
+        p: particle.p + delta_p,
+        v: particle.v + delta_v,
+        ..*particle
+    }
+}
+
+fn passes_through(pos1: Vec2, pos2: Vec2, radius: f32) -> Vec2 {
+    let diff = pos2 - pos1;
+    let step = diff.normalize();
+    let dot_ps = pos1.dot(step);
+    let rad_sq = radius * radius;
+    let delta = dot_ps * dot_ps + rad_sq - pos1.dot(pos1);
+    if delta < 0. {
+        return pos2;
+    }
+    let d = -dot_ps - delta.sqrt();
+    let t = d / diff.length();
+    if t >= 0. {
+        return pos1 + t.clamp(0., 1.) * diff;
+    }
+    pos2
+}
+
+fn step_particle(particle: &CpuParticle, lines: &[AngleLine]) -> CpuParticle {
+    let next_line = Vec2::from_array(lines[particle.index as usize + 1].dir);
+    let mut h = STEP_SIZE;
+    let delta_pv = loop {
+        let delta_pv = rk4(particle, h);
+        let delta_pv2 = rk4(&rk4(particle, 0.5 * h), 0.5 * h);
+        let diff = Vec4::new(delta_pv.p.x, delta_pv.p.y, delta_pv.v.x, delta_pv.v.y)
+            - Vec4::new(delta_pv2.p.x, delta_pv2.p.y, delta_pv2.v.x, delta_pv2.v.y);
+        let delta_p = delta_pv.p - particle.p;
+        // The shader halves h in its `continuing` block, before checking whether to stop.
+        h *= 0.5;
+        if !crossed_line(&delta_pv, next_line)
+            && diff.dot(diff) < 0.0001
+            && delta_p.dot(delta_p) < 0.000001
+        {
+            break delta_pv;
+        }
+    };
+
+    CpuParticle {
+        p: passes_through(particle.p, delta_pv.p, particle.black_hole_radius),
+        v: delta_pv.v,
+        ..*particle
+    }
+}
+
+// One invocation of the shader's `main` for every dispatch, until the particle stops.
+fn simulate_particle(particle: &Particle, lines: &[AngleLine], angle_count: usize) -> SimulatedRay {
+    let mut particle = CpuParticle::from_particle(particle);
+    // The GPU output buffer starts zeroed, so lines that are never crossed read as 0.
+    let mut angle_dist = vec![0.; angle_count];
+    for _ in 0..STEP_COUNT {
+        if stop(&particle) || particle.index as usize == lines.len() - 1 {
+            break;
+        }
+        let l = Vec2::from_array(lines[particle.index as usize].dir);
+        let mut next = step_particle(&particle, lines);
+        let t = intersection(&particle, &next, l);
+        if crossed_line(&next, l) {
+            let pos = particle.p + (next.p - particle.p) * t;
+            angle_dist[particle.index as usize] = pos.length();
+            next.index += 1;
+        }
+        particle = next;
+    }
+    SimulatedRay {
+        angle_dist,
+        final_pos: particle.p.to_array(),
+        final_dir: particle.v.to_array(),
+    }
+}
+
+// Splits the particles evenly over every core; the rays come back in the same order.
+pub fn simulate_particles_cpu(
+    particles: &[Particle],
+    lines: &[AngleLine],
+    angle_count: usize,
+) -> Vec<SimulatedRay> {
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .clamp(1, particles.len().max(1));
+    let chunk_size = particles.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let workers: Vec<_> = particles
+            .chunks(chunk_size.max(1))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|particle| simulate_particle(particle, lines, angle_count))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use glam::Vec2;
+
+    use crate::{
+        physics::pseudo_force_scale,
+        sampler::{
+            dimension_params::DimensionParams,
+            gpu::{
+                field::Particle,
+                gpu_state::{angle_lines, SimulatorState},
+            },
+        },
+    };
+
+    use super::{force, simulate_particles_cpu};
+
+    // The shader's force only differs from the shared one by the order of its f32 operations.
+    #[test]
+    fn force_matches_physics() {
+        for magnitude in [0.5_f32, 1., 5.] {
+            for p in [
+                Vec2::new(0., -1.5),
+                Vec2::new(0.7, 1.1),
+                Vec2::new(-3., 4.),
+                Vec2::new(20., -0.5),
+            ] {
+                let expected = pseudo_force_scale(p.length_squared(), magnitude) * -p;
+                let error = (force(p, magnitude) - expected).length() / expected.length();
+                assert!(error < 1e-6, "p: {}, error: {}", p, error);
+            }
+        }
+    }
+
+    #[test]
+    fn massless_ray_is_straight() {
+        let angles = DimensionParams {
+            size: 90,
+            bounds: [0., FRAC_PI_2],
+        };
+        let mut particle = Particle::new(2., Vec2::new(1., 0.), 1.);
+        particle.black_hole_magnitude = 0.;
+        let rays = simulate_particles_cpu(&[particle], &angle_lines(&angles), angles.size);
+
+        // The ray runs along y = -2, so it crosses the line at angle a at 2 / cos(a).
+        for (i, angle) in angles.generate_list().iter().enumerate() {
+            let expected = 2. / angle.cos();
+            // Lines the ray never reaches before max_distance stay at 0.
+            if !(0. ..=60.).contains(&expected) {
+                assert_eq!(rays[0].angle_dist[i], 0.);
+            } else {
+                assert!(
+                    (rays[0].angle_dist[i] - expected).abs() < 0.001 * expected,
+                    "angle: {}, expected: {}, actual: {}",
+                    angle,
+                    expected,
+                    rays[0].angle_dist[i]
+                );
+            }
+        }
+        assert!(Vec2::from_array(rays[0].final_pos).length() > 60.);
+        assert_eq!(rays[0].final_dir, [1., 0.]);
+    }
+
+    #[test]
+    fn gpu_matches_cpu() {
+        let simulator = match pollster::block_on(SimulatorState::new()) {
+            Some(simulator) => simulator,
+            None => {
+                println!("No GPU adapter, skipping");
+                return;
+            }
+        };
+        let angles = DimensionParams {
+            size: 64,
+            bounds: [0., 6.],
+        };
+        let particles: Vec<Particle> = (0..32)
+            .map(|i| Particle::new(1.5, Vec2::new(1., 0.), 0.8 + 0.02 * i as f32))
+            .collect();
+        let cpu = simulate_particles_cpu(&particles, &angle_lines(&angles), angles.size);
+        let gpu = pollster::block_on(simulator.simulate_particles(&particles, &angles, 40.));
+        for (cpu, gpu) in cpu.iter().zip(gpu.iter()) {
+            for (c, g) in cpu.angle_dist.iter().zip(gpu.angle_dist.iter()) {
+                assert!(
+                    (c - g).abs() <= 0.001 * c.abs().max(1.),
+                    "cpu: {}, gpu: {}",
+                    c,
+                    g
+                );
+            }
+            let (cpu_pos, gpu_pos) = (
+                Vec2::from_array(cpu.final_pos),
+                Vec2::from_array(gpu.final_pos),
+            );
+            assert!((cpu_pos - gpu_pos).length() <= 0.001 * cpu_pos.length().max(1.));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::time::SystemTime;
+use std::{env, time::SystemTime};
 
 use glam::Vec2;
 use serde::{Deserialize, Serialize};
@@ -12,13 +12,14 @@ use crate::sampler::{
 };
 
 use super::{
-    field::{Field, Particle},
+    cpu_state::{simulate_particles_cpu, STEP_COUNT},
+    field::Particle,
     simulated_ray::SimulatedRay,
 };
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct AngleLine {
+pub struct AngleLine {
     pub dir: [f32; 2],
     pub temp: [f32; 2],
 }
@@ -32,6 +33,17 @@ impl AngleLine {
     }
 }
 
+// One more line than there are angles, so the last angle has a line to stop at.
+pub fn angle_lines(angles: &DimensionParams) -> Vec<AngleLine> {
+    let (min, delta) = angles.min_delta();
+    (0..=angles.size)
+        .map(|a| {
+            let angle = min + delta * a as f32 / (angles.size - 1) as f32;
+            AngleLine::new(angle)
+        })
+        .collect()
+}
+
 pub struct SimulatorState {
     device: Device,
     bind_group_layout: BindGroupLayout,
@@ -40,9 +52,10 @@ pub struct SimulatorState {
 }
 
 impl SimulatorState {
-    pub async fn new() -> Self {
+    // None if there's no hardware adapter, like on headless machines.
+    pub async fn new() -> Option<Self> {
         let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
-        let adapter = instance.request_adapter(&Default::default()).await.unwrap();
+        let adapter = instance.request_adapter(&Default::default()).await?;
         let features = adapter.features();
         let (device, queue) = adapter
             .request_device(
@@ -54,7 +67,7 @@ impl SimulatorState {
                 None,
             )
             .await
-            .unwrap();
+            .ok()?;
 
         let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Particle Shader Module"),
@@ -108,12 +121,12 @@ impl SimulatorState {
             entry_point: "main",
         });
 
-        Self {
+        Some(Self {
             device,
             bind_group_layout,
             pipeline,
             queue,
-        }
+        })
     }
 
     async fn retrieve_values<T: Pod>(&self, source_buffer: &Buffer, len: u64) -> Vec<T> {
@@ -173,13 +186,7 @@ impl SimulatorState {
             mapped_at_creation: false,
         });
 
-        let (min, delta) = angles.min_delta();
-        let angle_lines: Vec<AngleLine> = (0..=angles.size)
-            .map(|a| {
-                let angle = min + delta * a as f32 / (angles.size - 1) as f32;
-                AngleLine::new(angle)
-            })
-            .collect();
+        let angle_lines = angle_lines(angles);
         let angle_lines_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
             contents: &bytemuck::cast_slice(&angle_lines),
@@ -187,7 +194,7 @@ impl SimulatorState {
         });
 
         let _start = SystemTime::now();
-        let step_count = 1 << 19;
+        let step_count = STEP_COUNT as i32;
         let pieces = i32::max(step_count >> 12, 1);
         for step in 0..step_count {
             let mut encoder = device.create_command_encoder(&Default::default());
@@ -243,6 +250,30 @@ impl SimulatorState {
     }
 }
 
+// Set to "cpu" to skip the GPU even when there is one.
+const BACKEND_VAR: &str = "SIMULATION_BACKEND";
+
+pub enum Backend {
+    Gpu(SimulatorState),
+    Cpu,
+}
+
+impl Backend {
+    // Uses the GPU if there is one, unless `BACKEND_VAR` asks for the CPU.
+    pub async fn select() -> Self {
+        if env::var(BACKEND_VAR).is_ok_and(|backend| backend.eq_ignore_ascii_case("cpu")) {
+            return Backend::Cpu;
+        }
+        match SimulatorState::new().await {
+            Some(simulator) => Backend::Gpu(simulator),
+            None => {
+                println!("No GPU adapter found, simulating on the CPU");
+                Backend::Cpu
+            }
+        }
+    }
+}
+
 // This is about 4 million points, each of which takes ~2 bytes. If we do more, it can crash.
 // Todo: try a backout technique?
 const MAX_PROBLEM_SIZE: usize = 1 << 22;
@@ -251,7 +282,12 @@ async fn run(
     angles: &DimensionParams,
     max_distance: f32,
 ) -> Vec<SimulatedRay> {
-    let simulator = SimulatorState::new().await;
+    let simulator = match Backend::select().await {
+        Backend::Gpu(simulator) => simulator,
+        Backend::Cpu => {
+            return simulate_particles_cpu(&particles, &angle_lines(angles), angles.size);
+        }
+    };
     let mut rays = Vec::new();
 
     let particles_per_problem = MAX_PROBLEM_SIZE / angles.size;
@@ -1,3 +1,4 @@
+pub mod cpu_state;
 pub mod field;
 pub mod gpu_state;
 pub mod simulated_ray;
@@ -2,11 +2,14 @@ pub mod approximation_function;
 pub mod clean_approximation_functions;
 pub mod combined_ray_approximation;
 pub mod dimension_params;
+pub mod g_factor_table;
 pub mod distance_velocity_paths;
 mod gpu;
 mod optimization_utils;
 pub mod render_params;
 pub mod simple_path_generator;
 pub mod simulated_path;
+pub mod time_delay_table;
 pub mod view_angle_parameter_cache;
 pub mod view_bound;
+pub mod wormhole_direction_table;
@@ -1,8 +1,9 @@
 use std::f32::consts::TAU;
 
 use glam::Vec2;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Deserialize, Serialize)]
 pub struct RenderParams {
     pub black_hole_radius: f32,
     pub fov_degrees: f32,
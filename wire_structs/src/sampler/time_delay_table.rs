@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+// Light travel time from a point on the disc to the camera, minus the camera distance, so the
+// disc can be shaded at the time the light left it.
+//
+// Samples are indexed by (disc radius, angle, camera distance), with the disc radius varying
+// fastest. The angle is swept around the black hole from the camera, over (0, TAU].
+#[derive(Serialize, Deserialize)]
+pub struct TimeDelayTable {
+    pub dimensions: [u32; 3],
+    pub disc_bounds: [f32; 2],
+    pub distance_bounds: [f32; 2],
+    pub delays: Vec<f32>,
+}
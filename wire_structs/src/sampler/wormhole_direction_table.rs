@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+// Final directions of rays around an Ellis wormhole, for the web renderer.
+//
+// Samples are indexed by (z, camera distance), with z varying fastest. z is the cosine of the
+// angle between the ray and the direction to the throat, and is sampled evenly in that angle.
+// `final_angles` is the angle of the final direction from the direction to the throat, in the
+// plane of the ray, and `far_side` is 1 for rays that end up on the other side of the throat and 0
+// otherwise.
+#[derive(Serialize, Deserialize)]
+pub struct WormholeDirectionTable {
+    pub dimensions: [u32; 2],
+    pub distance_bounds: [f32; 2],
+    pub z_bounds: [f32; 2],
+    pub throat_radius: f32,
+    pub final_angles: Vec<f32>,
+    pub far_side: Vec<f32>,
+}